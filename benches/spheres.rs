@@ -0,0 +1,99 @@
+use std::{ hint::black_box, time::{ Duration, Instant } };
+
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+
+use raytracer::{
+    bvh::Bvh,
+    float::Float,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    interval::Interval,
+    point3d::Point3D,
+    primitive::Primitive,
+    ray::Ray,
+    scene::SceneDescription,
+    simd::{ FloatLanes, Lanes, ScalarLanes },
+    sphere_batch::{ SphereBatch, batch_neighbours },
+    vec3::Vec3,
+};
+#[cfg(target_arch = "x86_64")]
+use raytracer::simd::has_avx;
+
+// Shoots the same rays at the random spheres scene stored five ways: a HittableList of spheres
+// tested one at a time, a SphereBatch using the widest simd lanes the cpu allows, a SphereBatch
+// using the scalar fallback, a Bvh of the spheres and a Bvh of batches of neighbouring spheres,
+// which is what SceneDescription::build makes. Run with
+//   cargo bench --bench spheres
+// The batches use avx where the cpu has it either way, RUSTFLAGS="-C target-cpu=native" lets the
+// rest of the code use it too.
+fn main() {
+    let scene = SceneDescription::random_spheres(0);
+    let mut list = HittableList::new();
+    let mut batch: SphereBatch = SphereBatch::new();
+    let mut scalar_batch: SphereBatch<ScalarLanes> = SphereBatch::new();
+    for sphere in scene.build_spheres() {
+        batch.add(sphere);
+        scalar_batch.add(sphere);
+        list.add(sphere);
+    }
+
+    let bvh = Bvh::new(scene.build_spheres().into_iter().map(Primitive::Sphere).collect());
+    let batched_bvh = Bvh::new(batch_neighbours(scene.build_spheres()).into_iter().map(Primitive::SphereBatch).collect());
+
+    let rays = camera_rays(200_000);
+    println!("{} spheres, {} rays, {} lanes of {}", batch.len(), rays.len(), Lanes::LANES, std::any::type_name::<Float>());
+    #[cfg(target_arch = "x86_64")]
+    if Lanes::AVX_AT_RUNTIME && has_avx() {
+        println!("the simd batches switch to avx lanes on this cpu");
+    }
+    let list_time = bench("hittable list", &list, &rays);
+    let scalar_time = bench("scalar batch", &scalar_batch, &rays);
+    let batch_time = bench("simd batch", &batch, &rays);
+    println!(
+        "simd batch speedup: {:.2}x over the hittable list, {:.2}x over the scalar batch",
+        list_time.as_secs_f64() / batch_time.as_secs_f64(),
+        scalar_time.as_secs_f64() / batch_time.as_secs_f64()
+    );
+    let bvh_time = bench("bvh", &bvh, &rays);
+    println!(
+        "bvh speedup: {:.2}x over the hittable list, {:.2}x over the simd batch",
+        list_time.as_secs_f64() / bvh_time.as_secs_f64(),
+        batch_time.as_secs_f64() / bvh_time.as_secs_f64()
+    );
+    let batched_bvh_time = bench("batched bvh", &batched_bvh, &rays);
+    println!("batched bvh speedup: {:.2}x over the bvh", bvh_time.as_secs_f64() / batched_bvh_time.as_secs_f64());
+}
+
+// rays from around the camera of the random spheres scene towards the spheres
+fn camera_rays(count: usize) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(1);
+    return (0..count)
+        .map(|_| {
+            let origin = Point3D::new(13.0, 2.0, 3.0) + Vec3::new(rng.gen_range(-0.5..0.5), rng.gen_range(-0.5..0.5), 0.0);
+            let target = Point3D::new(rng.gen_range(-11.0..11.0), rng.gen_range(-1.0..2.0), rng.gen_range(-11.0..11.0));
+            Ray::new(origin, target - origin)
+        })
+        .collect();
+}
+
+// the fastest of a few runs over all the rays
+fn bench(name: &str, world: &dyn Hittable, rays: &[Ray]) -> Duration {
+    let mut best = Duration::MAX;
+    let mut hits = 0;
+    for _ in 0..5 {
+        let start = Instant::now();
+        hits = rays
+            .iter()
+            .filter(|ray| black_box(world.hit(ray, &Interval::new(0.001, Float::INFINITY))).is_some())
+            .count();
+        best = best.min(start.elapsed());
+    }
+    println!(
+        "{:>14}: {:>7.1} ms, {:>6.2} M rays/s, {} hits",
+        name,
+        best.as_secs_f64() * 1e3,
+        (rays.len() as f64) / best.as_secs_f64() / 1e6,
+        hits
+    );
+    return best;
+}
@@ -0,0 +1,34 @@
+use std::error::Error;
+#[cfg(feature = "header")]
+use std::{ env, fs, path::PathBuf };
+
+// With the header feature, generates the c header for the interface in src/ffi.rs into OUT_DIR and
+// fails the build when the committed include/raytracer.h no longer matches it. Without it there is
+// nothing to do, so ordinary builds don't need cbindgen.
+fn main() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "header")]
+    generate_header()?;
+    return Ok(());
+}
+
+#[cfg(feature = "header")]
+fn generate_header() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=include/raytracer.h");
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let generated = PathBuf::from(env::var("OUT_DIR")?).join("raytracer.h");
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))?;
+    // only the interface itself, not the rest of the crate's public items
+    cbindgen::Builder::new()
+        .with_src(crate_dir.join("src/ffi.rs"))
+        .with_config(config)
+        .generate()?
+        .write_to_file(&generated);
+    let committed = crate_dir.join("include/raytracer.h");
+    if fs::read(&committed).ok() != Some(fs::read(&generated)?) {
+        return Err(format!("include/raytracer.h is out of date, copy the new header from {}", generated.display()).into());
+    }
+    return Ok(());
+}
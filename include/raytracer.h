@@ -0,0 +1,102 @@
+#ifndef RAYTRACER_H
+#define RAYTRACER_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. Check it with cargo build --features header. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// what a call did, anything but RT_STATUS_OK comes with a message from rt_scene_last_error
+typedef enum RtStatus {
+  RT_STATUS_OK = 0,
+  RT_STATUS_NULL_POINTER = 1,
+  RT_STATUS_INVALID_ARGUMENT = 2,
+  RT_STATUS_BUFFER_TOO_SMALL = 3,
+  RT_STATUS_NO_CAMERA = 4,
+  RT_STATUS_RENDER_FAILED = 5,
+  // the renderer panicked partway through the call
+  RT_STATUS_PANIC = 6,
+} RtStatus;
+
+// an opaque handle to a scene and its camera
+typedef struct RtScene RtScene;
+
+typedef struct RtVec3 {
+  float x;
+  float y;
+  float z;
+} RtVec3;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// a new empty scene, freed with rt_scene_free
+struct RtScene *rt_scene_new(void);
+
+// frees a scene from rt_scene_new, null is ignored
+void rt_scene_free(struct RtScene *scene);
+
+// The message for the error returned by the last call on scene, or null if it succeeded. The string
+// belongs to the scene and stays valid until the next call on it.
+const char *rt_scene_last_error(const struct RtScene *scene);
+
+// mirrors Lambertian::new, id may be null
+enum RtStatus rt_scene_add_lambertian(struct RtScene *scene,
+                                      float r,
+                                      float g,
+                                      float b,
+                                      uint32_t *id);
+
+// mirrors Metal::new, id may be null
+enum RtStatus rt_scene_add_metal(struct RtScene *scene,
+                                 float r,
+                                 float g,
+                                 float b,
+                                 float fuzziness,
+                                 uint32_t *id);
+
+// mirrors Dielectric::new, id may be null
+enum RtStatus rt_scene_add_dielectric(struct RtScene *scene, float refraction_index, uint32_t *id);
+
+// mirrors Sphere::new, with material being an id from one of the rt_scene_add_ material functions,
+// the radius may not be negative
+enum RtStatus rt_scene_add_sphere(struct RtScene *scene,
+                                  struct RtVec3 center,
+                                  float radius,
+                                  uint32_t material);
+
+// Mirrors Camera::new, replacing the scene's camera. vfov has to be between 0 and 180 degrees, the
+// defocus angle at least 0 and below 180 degrees, and the focus distance positive.
+enum RtStatus rt_scene_set_camera(struct RtScene *scene,
+                                  float aspect_ratio,
+                                  int32_t image_width,
+                                  int32_t samples_per_pixel,
+                                  int32_t max_depth,
+                                  float vfov,
+                                  struct RtVec3 look_from,
+                                  struct RtVec3 look_at,
+                                  struct RtVec3 vup,
+                                  float defocus_angle,
+                                  float focus_distance);
+
+// changes the noise of the render, the same seed always gives the same image
+enum RtStatus rt_scene_set_seed(struct RtScene *scene, uint64_t seed);
+
+// the size of the image the camera renders, either pointer may be null
+enum RtStatus rt_scene_image_size(struct RtScene *scene, int32_t *width, int32_t *height);
+
+// Renders the scene into buffer as linear rgb floats, three per pixel, row by row from the top left.
+// buffer_len is the number of floats in buffer, which needs at least width * height * 3.
+enum RtStatus rt_scene_render(struct RtScene *scene,
+                              float *buffer,
+                              size_t buffer_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RAYTRACER_H */
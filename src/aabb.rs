@@ -0,0 +1,113 @@
+use crate::{ point3d::Point3D, vec3::Vec3, float::Float };
+
+// an axis aligned bounding box, the corners with the smallest and largest coordinates
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Point3D,
+    pub max: Point3D,
+}
+
+impl Aabb {
+    // contains nothing, and is the identity for union
+    pub const EMPTY: Aabb = Aabb {
+        min: Point3D::splat(Float::INFINITY),
+        max: Point3D::splat(Float::NEG_INFINITY),
+    };
+
+    pub fn new(min: Point3D, max: Point3D) -> Aabb {
+        Aabb { min, max }
+    }
+    // the box around two corners given in any order
+    pub fn from_points(a: Point3D, b: Point3D) -> Aabb {
+        return Aabb::new(Vec3::min(a, b), Vec3::max(a, b));
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        return Aabb::new(Vec3::min(self.min, other.min), Vec3::max(self.max, other.max));
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.min.x() > self.max.x() || self.min.y() > self.max.y() || self.min.z() > self.max.z();
+    }
+    pub fn centroid(&self) -> Point3D {
+        return 0.5 * (self.min + self.max);
+    }
+    pub fn extent(&self) -> Vec3 {
+        return self.max - self.min;
+    }
+    pub fn surface_area(&self) -> Float {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let e = self.extent();
+        return 2.0 * (e.x() * e.y() + e.y() * e.z() + e.z() * e.x());
+    }
+    // 0, 1 or 2 for the axis the box is longest along
+    pub fn longest_axis(&self) -> usize {
+        let e = self.extent();
+        return if e.x() >= e.y() && e.x() >= e.z() {
+            0
+        } else if e.y() >= e.z() {
+            1
+        } else {
+            2
+        };
+    }
+    // The slab test: whether the ray passes through the box somewhere between t_min and t_max.
+    // inverse_direction is one over each component of the ray's direction, worked out once per ray
+    // rather than once per box. A zero component makes an infinite inverse, which still works
+    // except for a ray lying exactly in one of the box's planes, where 0 * infinity gives nan; min
+    // and max ignore the nan, so that slab simply doesn't narrow the range.
+    pub fn hit(&self, origin: Point3D, inverse_direction: Vec3, t_min: Float, t_max: Float) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let inverse = inverse_direction.axis(axis);
+            let t0 = (self.min.axis(axis) - origin.axis(axis)) * inverse;
+            let t1 = (self.max.axis(axis) - origin.axis(axis)) * inverse;
+            let (near, far) = if inverse < 0.0 { (t1, t0) } else { (t0, t1) };
+            t_min = Float::max(near, t_min);
+            t_max = Float::min(far, t_max);
+            // flat boxes, like the bounds of a disk, are hit when the range shrinks to a point
+            if t_max < t_min {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
+#[cfg(test)]
+fn inverse(direction: Vec3) -> Vec3 {
+    return Vec3::new(1.0 / direction.x(), 1.0 / direction.y(), 1.0 / direction.z());
+}
+
+#[test]
+fn test_aabb_union_and_area() {
+    let a = Aabb::from_points(Point3D::new(1.0, 0.0, 0.0), Point3D::new(0.0, 1.0, 1.0));
+    assert_eq!(a.min, Point3D::new(0.0, 0.0, 0.0));
+    assert_eq!(a.surface_area(), 6.0);
+    let b = Aabb::new(Point3D::new(2.0, 0.0, 0.0), Point3D::new(3.0, 2.0, 1.0));
+    let u = a.union(&b);
+    assert_eq!(u, Aabb::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(3.0, 2.0, 1.0)));
+    assert_eq!(u.longest_axis(), 0);
+    assert_eq!(u.centroid(), Point3D::new(1.5, 1.0, 0.5));
+    assert_eq!(Aabb::EMPTY.union(&a), a);
+    assert!(Aabb::EMPTY.is_empty());
+    assert_eq!(Aabb::EMPTY.surface_area(), 0.0);
+}
+
+#[test]
+fn test_aabb_hit() {
+    let b = Aabb::new(Point3D::new(-1.0, -1.0, -3.0), Point3D::new(1.0, 1.0, -1.0));
+    let origin = Point3D::new(0.0, 0.0, 0.0);
+    let forward = Vec3::new(0.0, 0.0, -1.0);
+    assert!(b.hit(origin, inverse(forward), 0.0, Float::INFINITY));
+    // the box is beyond t_max, or behind the ray
+    assert!(!b.hit(origin, inverse(forward), 0.0, 0.5));
+    assert!(!b.hit(origin, inverse(-forward), 0.0, Float::INFINITY));
+    assert!(!b.hit(origin, inverse(Vec3::new(1.0, 0.0, -0.2)), 0.0, Float::INFINITY));
+    // a ray lying in the plane of a face
+    assert!(b.hit(Point3D::new(1.0, 0.0, 0.0), inverse(forward), 0.0, Float::INFINITY));
+    // a flat box
+    let flat = Aabb::new(Point3D::new(-1.0, -1.0, -2.0), Point3D::new(1.0, 1.0, -2.0));
+    assert!(flat.hit(origin, inverse(forward), 0.0, Float::INFINITY));
+}
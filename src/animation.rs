@@ -0,0 +1,264 @@
+use std::{ fs, io::{ self, Error, ErrorKind }, ops::{ Add, Mul, Sub }, path::Path };
+
+use crate::{ point3d::Point3D, vec3::Vec3, float::Float };
+
+// the camera parameters that can change over an animation, time is measured in frames
+#[derive(Clone, Copy, Debug)]
+pub struct CameraKeyframe {
+    pub time: Float,
+    pub look_from: Point3D,
+    pub look_at: Point3D,
+    pub vfov: Float,
+    pub focus_distance: Float,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interpolation {
+    // straight lines between keyframes, the motion changes direction abruptly at each one
+    Linear,
+    // a catmull-rom spline through the keyframes, the motion stays smooth across them
+    CatmullRom,
+}
+
+impl Interpolation {
+    pub fn parse(name: &str) -> Option<Interpolation> {
+        return match name {
+            "linear" => Some(Interpolation::Linear),
+            "spline" => Some(Interpolation::CatmullRom),
+            _ => None,
+        };
+    }
+}
+
+pub struct CameraAnimation {
+    // sorted by time
+    keyframes: Vec<CameraKeyframe>,
+    interpolation: Interpolation,
+}
+
+impl CameraAnimation {
+    pub fn new(keyframes: Vec<CameraKeyframe>, interpolation: Interpolation) -> CameraAnimation {
+        let mut keyframes = keyframes;
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        CameraAnimation { keyframes, interpolation }
+    }
+
+    // Reads one keyframe per line as time, look_from x y z, look_at x y z, vfov and focus distance,
+    // separated by whitespace. Blank lines and everything after a # are ignored.
+    pub fn parse(text: &str, interpolation: Interpolation) -> io::Result<CameraAnimation> {
+        let mut keyframes = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("line {}: expected time, look_from, look_at, vfov and focus distance", number + 1)
+                )
+            };
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<Float>())
+                .collect::<Result<Vec<Float>, _>>()
+                .map_err(|_| invalid())?;
+            if values.len() != 9 {
+                return Err(invalid());
+            }
+            keyframes.push(CameraKeyframe {
+                time: values[0],
+                look_from: Point3D::new(values[1], values[2], values[3]),
+                look_at: Point3D::new(values[4], values[5], values[6]),
+                vfov: values[7],
+                focus_distance: values[8],
+            });
+        }
+        if keyframes.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "animation has no keyframes"));
+        }
+        return Ok(CameraAnimation::new(keyframes, interpolation));
+    }
+
+    pub fn load(path: &str, interpolation: Interpolation) -> io::Result<CameraAnimation> {
+        return CameraAnimation::parse(&fs::read_to_string(path)?, interpolation);
+    }
+
+    // the camera at the given time, holding still before the first keyframe and after the last
+    pub fn evaluate(&self, time: Float) -> CameraKeyframe {
+        let last = self.keyframes.len() - 1;
+        if time <= self.keyframes[0].time {
+            return CameraKeyframe { time, ..self.keyframes[0] };
+        }
+        if time >= self.keyframes[last].time {
+            return CameraKeyframe { time, ..self.keyframes[last] };
+        }
+        // the segment between keyframes k1 and k2 contains time
+        let k2 = self.keyframes.iter().position(|k| k.time > time).unwrap();
+        let k1 = k2 - 1;
+        let (t1, t2) = (self.keyframes[k1].time, self.keyframes[k2].time);
+        let s = (time - t1) / (t2 - t1);
+        return match self.interpolation {
+            Interpolation::Linear => {
+                let (a, b) = (&self.keyframes[k1], &self.keyframes[k2]);
+                CameraKeyframe {
+                    time,
+                    look_from: lerp(a.look_from, b.look_from, s),
+                    look_at: lerp(a.look_at, b.look_at, s),
+                    vfov: lerp(a.vfov, b.vfov, s),
+                    focus_distance: lerp(a.focus_distance, b.focus_distance, s),
+                }
+            }
+            Interpolation::CatmullRom => {
+                // the end keyframes are repeated so the spline still passes through them
+                let k = [k1.saturating_sub(1), k1, k2, usize::min(k2 + 1, last)];
+                let times = k.map(|i| self.keyframes[i].time);
+                let spline = |get: &dyn Fn(&CameraKeyframe) -> Vec3| {
+                    catmull_rom(k.map(|i| get(&self.keyframes[i])), times, s)
+                };
+                let scalar = |get: &dyn Fn(&CameraKeyframe) -> Float| {
+                    catmull_rom(k.map(|i| get(&self.keyframes[i])), times, s)
+                };
+                CameraKeyframe {
+                    time,
+                    look_from: spline(&|k| k.look_from),
+                    look_at: spline(&|k| k.look_at),
+                    vfov: scalar(&|k| k.vfov),
+                    focus_distance: scalar(&|k| k.focus_distance),
+                }
+            }
+        };
+    }
+}
+
+fn lerp<T: Copy + Add<Output = T> + Mul<Float, Output = T>>(a: T, b: T, s: Float) -> T {
+    return a * (1.0 - s) + b * s;
+}
+
+// a cubic hermite segment from p[1] to p[2], with tangents taken from the neighbouring keyframes
+// and scaled by the segment's length so unevenly spaced keyframes don't overshoot
+fn catmull_rom<T>(p: [T; 4], t: [Float; 4], s: Float) -> T
+    where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Float, Output = T>
+{
+    let segment = t[2] - t[1];
+    let tangent = |a: usize, b: usize| {
+        if t[b] > t[a] { (p[b] - p[a]) * (segment / (t[b] - t[a])) } else { p[2] - p[1] }
+    };
+    let m1 = tangent(0, 2);
+    let m2 = tangent(1, 3);
+    let s2 = s * s;
+    let s3 = s2 * s;
+    return p[1] * (2.0 * s3 - 3.0 * s2 + 1.0) +
+        m1 * (s3 - 2.0 * s2 + s) +
+        p[2] * (-2.0 * s3 + 3.0 * s2) +
+        m2 * (s3 - s2);
+}
+
+// an inclusive range of frames written as numbered files <prefix>_0001.ppm, <prefix>_0002.ppm, ...
+#[derive(Clone, Debug)]
+pub struct FrameSequence {
+    pub first: u32,
+    pub last: u32,
+    pub prefix: String,
+    // leave frames whose file is already there alone, so an interrupted batch picks up where it stopped
+    pub skip_existing: bool,
+}
+
+impl FrameSequence {
+    // parses a range such as 1-48, or a single frame such as 12
+    pub fn parse_range(range: &str) -> Option<(u32, u32)> {
+        let (first, last) = match range.split_once('-') {
+            Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+            None => {
+                let frame = range.parse().ok()?;
+                (frame, frame)
+            }
+        };
+        return if first <= last { Some((first, last)) } else { None };
+    }
+    pub fn frame_path(&self, frame: u32, extension: &str) -> String {
+        return format!("{}_{:04}.{}", self.prefix, frame, extension);
+    }
+    // the frames still to render, in order
+    pub fn pending_frames(&self) -> Vec<u32> {
+        return (self.first..=self.last)
+            .filter(|frame| !(self.skip_existing && Path::new(&self.frame_path(*frame, "ppm")).exists()))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+fn keyframe(time: Float, x: Float, vfov: Float) -> CameraKeyframe {
+    return CameraKeyframe {
+        time,
+        look_from: Point3D::new(x, 0.0, 0.0),
+        look_at: Point3D::new(0.0, 0.0, 0.0),
+        vfov,
+        focus_distance: 10.0,
+    };
+}
+
+#[test]
+fn test_linear_animation() {
+    let animation = CameraAnimation::new(
+        vec![keyframe(10.0, 4.0, 40.0), keyframe(0.0, 0.0, 20.0)],
+        Interpolation::Linear
+    );
+    let k = animation.evaluate(2.5);
+    assert_eq!(k.look_from.x(), 1.0);
+    assert_eq!(k.vfov, 25.0);
+    // outside the keyframes the camera holds still
+    assert_eq!(animation.evaluate(-5.0).look_from.x(), 0.0);
+    assert_eq!(animation.evaluate(20.0).vfov, 40.0);
+}
+
+#[test]
+fn test_spline_animation_passes_through_keyframes() {
+    let keyframes = vec![keyframe(0.0, 0.0, 20.0), keyframe(10.0, 10.0, 30.0), keyframe(15.0, 0.0, 20.0)];
+    let animation = CameraAnimation::new(keyframes, Interpolation::CatmullRom);
+    for (time, x) in [(0.0, 0.0), (10.0, 10.0), (15.0, 0.0)] {
+        assert!((animation.evaluate(time).look_from.x() - x).abs() < 1e-5);
+    }
+    // the motion is smooth through the middle keyframe, so just before and after it the camera is
+    // still close to the peak rather than heading back in a straight line
+    let before = animation.evaluate(9.5).look_from.x();
+    let after = animation.evaluate(10.25).look_from.x();
+    let linear = CameraAnimation::new(
+        vec![keyframe(0.0, 0.0, 20.0), keyframe(10.0, 10.0, 30.0), keyframe(15.0, 0.0, 20.0)],
+        Interpolation::Linear
+    );
+    assert!(before > linear.evaluate(9.5).look_from.x());
+    assert!(after > linear.evaluate(10.25).look_from.x());
+}
+
+#[test]
+fn test_parse_animation() {
+    let text = "# time  from  at  vfov  focus\n0  13 2 3  0 0 0  20 10\n48  3 2 13  0 0 0  30 12\n";
+    let animation = CameraAnimation::parse(text, Interpolation::Linear).unwrap();
+    let k = animation.evaluate(24.0);
+    assert_eq!(k.look_from.x(), 8.0);
+    assert_eq!(k.focus_distance, 11.0);
+    assert!(CameraAnimation::parse("0 1 2 3\n", Interpolation::Linear).is_err());
+    assert!(CameraAnimation::parse("", Interpolation::Linear).is_err());
+}
+
+#[test]
+fn test_frame_sequence() {
+    assert_eq!(FrameSequence::parse_range("1-48"), Some((1, 48)));
+    assert_eq!(FrameSequence::parse_range("7"), Some((7, 7)));
+    assert_eq!(FrameSequence::parse_range("9-3"), None);
+    let sequence = FrameSequence {
+        first: 1,
+        last: 3,
+        prefix: String::from("./does/not/exist/frame"),
+        skip_existing: true,
+    };
+    assert_eq!(sequence.frame_path(1, "ppm"), "./does/not/exist/frame_0001.ppm");
+    assert_eq!(sequence.pending_frames(), vec![1, 2, 3]);
+
+    let prefix = std::env::temp_dir().join(format!("frame_sequence_{}", std::process::id()));
+    let sequence = FrameSequence { prefix: prefix.to_string_lossy().into_owned(), ..sequence };
+    fs::write(sequence.frame_path(2, "ppm"), "").unwrap();
+    assert_eq!(sequence.pending_frames(), vec![1, 3]);
+    fs::remove_file(sequence.frame_path(2, "ppm")).unwrap();
+}
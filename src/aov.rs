@@ -0,0 +1,237 @@
+use std::{ collections::HashMap, fs::File, io::{ self, BufWriter, Write } };
+
+use crate::{
+    color::Color,
+    vec3::Vec3,
+    hittable::HitRecord,
+    material::{ Material, Scatter },
+    image::PixelRect,
+    float::{ Float, to_f32 },
+};
+
+// arbitrary output variables: extra images describing the first surface each camera ray hits
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AovKind {
+    // world space shading normal, facing the camera
+    Normal,
+    // the surface color before any lighting
+    Albedo,
+    // the hit's ray parameter HitRecord::t, the distance from the camera in units of the camera
+    // ray's direction, which is the distance itself only for cameras whose rays have unit length
+    Depth,
+    // materials are numbered in the order the render first sees them
+    MaterialId,
+    // index of the hit object in the world's object list
+    ObjectId,
+}
+
+impl AovKind {
+    pub fn parse(name: &str) -> Option<AovKind> {
+        return match name {
+            "normal" => Some(AovKind::Normal),
+            "albedo" => Some(AovKind::Albedo),
+            "depth" => Some(AovKind::Depth),
+            "material-id" => Some(AovKind::MaterialId),
+            "object-id" => Some(AovKind::ObjectId),
+            _ => None,
+        };
+    }
+    pub fn name(&self) -> &'static str {
+        return match self {
+            AovKind::Normal => "normal",
+            AovKind::Albedo => "albedo",
+            AovKind::Depth => "depth",
+            AovKind::MaterialId => "material-id",
+            AovKind::ObjectId => "object-id",
+        };
+    }
+}
+
+// what one camera sample saw at its first hit
+pub struct AovSample {
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub depth: Float,
+    pub material_key: usize,
+    pub object_id: usize,
+}
+
+impl AovSample {
+    // material is the hit's material
+    pub fn new(hit: &HitRecord, material: &Material) -> AovSample {
+        AovSample {
+            normal: hit.normal,
+            albedo: material.albedo(hit),
+            depth: hit.t,
+            material_key: hit.material,
+            object_id: hit.object_id,
+        }
+    }
+}
+
+// normal, albedo and depth are averaged over all the camera samples that hit something, the ids
+// come from the first sample that hit something since averaging them would be meaningless
+pub struct AovBuffers {
+    width: i32,
+    height: i32,
+    hits: Vec<u32>,
+    normals: Vec<Vec3>,
+    albedos: Vec<Color>,
+    depths: Vec<Float>,
+    material_ids: Vec<i32>,
+    object_ids: Vec<i32>,
+    material_numbers: HashMap<usize, i32>,
+}
+
+impl AovBuffers {
+    pub fn new(width: i32, height: i32) -> AovBuffers {
+        let len = (width * height) as usize;
+        AovBuffers {
+            width,
+            height,
+            hits: vec![0; len],
+            normals: vec![Vec3::new(0.0, 0.0, 0.0); len],
+            albedos: vec![Color::new(0.0, 0.0, 0.0); len],
+            depths: vec![0.0; len],
+            material_ids: vec![-1; len],
+            object_ids: vec![-1; len],
+            material_numbers: HashMap::new(),
+        }
+    }
+    pub fn width(&self) -> i32 {
+        return self.width;
+    }
+    pub fn height(&self) -> i32 {
+        return self.height;
+    }
+    fn index(&self, i: i32, j: i32) -> usize {
+        return (j * self.width + i) as usize;
+    }
+    pub fn add_sample(&mut self, i: i32, j: i32, sample: &AovSample) {
+        let index = self.index(i, j);
+        self.hits[index] += 1;
+        self.normals[index] += sample.normal;
+        self.albedos[index] += sample.albedo;
+        self.depths[index] += sample.depth;
+        if self.material_ids[index] < 0 {
+            let next_number = self.material_numbers.len() as i32;
+            let number = *self.material_numbers.entry(sample.material_key).or_insert(next_number);
+            self.material_ids[index] = number;
+            self.object_ids[index] = sample.object_id as i32;
+        }
+    }
+    // pixels whose samples all missed report a zero normal and albedo, infinite depth and id -1
+    pub fn normal(&self, i: i32, j: i32) -> Vec3 {
+        let index = self.index(i, j);
+        if self.hits[index] == 0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let sum = self.normals[index];
+        // averaging unit normals shortens them at edges, so renormalize
+        return if sum.near_zero() { sum } else { sum / sum.length() };
+    }
+    pub fn albedo(&self, i: i32, j: i32) -> Color {
+        let index = self.index(i, j);
+        if self.hits[index] == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        return self.albedos[index] * (1.0 / (self.hits[index] as Float));
+    }
+    pub fn depth(&self, i: i32, j: i32) -> Float {
+        let index = self.index(i, j);
+        if self.hits[index] == 0 {
+            return Float::INFINITY;
+        }
+        return self.depths[index] / (self.hits[index] as Float);
+    }
+    pub fn material_id(&self, i: i32, j: i32) -> i32 {
+        return self.material_ids[self.index(i, j)];
+    }
+    pub fn object_id(&self, i: i32, j: i32) -> i32 {
+        return self.object_ids[self.index(i, j)];
+    }
+    // the value of one aov at a pixel, scalar aovs are repeated across all three channels
+    pub fn value(&self, kind: AovKind, i: i32, j: i32) -> [Float; 3] {
+        return match kind {
+            AovKind::Normal => {
+                let n = self.normal(i, j);
+                [n.x(), n.y(), n.z()]
+            }
+            AovKind::Albedo => {
+                let a = self.albedo(i, j);
+                [a.x(), a.y(), a.z()]
+            }
+            AovKind::Depth => [self.depth(i, j); 3],
+            AovKind::MaterialId => [self.material_id(i, j) as Float; 3],
+            AovKind::ObjectId => [self.object_id(i, j) as Float; 3],
+        };
+    }
+    // aovs hold raw floating point data (negative normals, unbounded depths, ids) so they are
+    // written losslessly as portable float maps rather than 8 bit images, only the pixels in rect are written
+    pub fn write_pfm(&self, kind: AovKind, path: &str, rect: &PixelRect) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // a negative scale marks the data as little endian
+        file.write_all(format!("PF\n{} {}\n-1.0\n", rect.width, rect.height).as_bytes())?;
+        // pfm scanlines go from the bottom of the image to the top
+        for j in (rect.y..rect.y + rect.height).rev() {
+            for i in rect.x..rect.x + rect.width {
+                for channel in self.value(kind, i, j) {
+                    file.write_all(&to_f32(channel).to_le_bytes())?;
+                }
+            }
+        }
+        return file.flush();
+    }
+}
+
+#[cfg(test)]
+use crate::{ material::Lambertian, point3d::Point3D };
+
+#[test]
+fn test_aov_buffers_average_and_ids() {
+    let red = Material::Lambertian(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
+    let blue = Material::Lambertian(Lambertian::new(Color::new(0.0, 0.0, 1.0)));
+    let hit_red = HitRecord::new(
+        Point3D::new(0.0, 0.0, -2.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        1.0,
+        0.0,
+        0.0,
+        true,
+        0
+    );
+    let mut hit_blue = HitRecord::new(
+        Point3D::new(0.0, 0.0, -4.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        2.0,
+        0.0,
+        0.0,
+        true,
+        1
+    );
+    hit_blue.object_id = 3;
+
+    let mut aovs = AovBuffers::new(2, 1);
+    aovs.add_sample(0, 0, &AovSample::new(&hit_blue, &blue));
+    aovs.add_sample(0, 0, &AovSample::new(&hit_red, &red));
+    aovs.add_sample(1, 0, &AovSample::new(&hit_red, &red));
+
+    // the mean of the two hits' t
+    assert_eq!(aovs.depth(0, 0), 1.5);
+    assert_eq!(aovs.albedo(0, 0).x(), 0.5);
+    assert_eq!(aovs.albedo(0, 0).z(), 0.5);
+    assert!((aovs.normal(0, 0).length() - 1.0).abs() < 1e-6);
+    // ids come from the first sample, materials are numbered in the order they were first seen
+    assert_eq!(aovs.object_id(0, 0), 3);
+    assert_eq!(aovs.material_id(0, 0), 0);
+    assert_eq!(aovs.material_id(1, 0), 1);
+    assert_eq!(aovs.object_id(1, 0), 0);
+}
+
+#[test]
+fn test_aov_buffers_miss() {
+    let aovs = AovBuffers::new(1, 1);
+    assert_eq!(aovs.depth(0, 0), Float::INFINITY);
+    assert_eq!(aovs.material_id(0, 0), -1);
+    assert_eq!(aovs.value(AovKind::Normal, 0, 0), [0.0, 0.0, 0.0]);
+}
@@ -0,0 +1,392 @@
+use std::mem;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    primitive::Primitive,
+    ray::Ray,
+    vec3::Vec3,
+    float::Float,
+};
+
+// One node of the flattened tree, 32 bytes with f32 so two share a cache line. The nodes are stored
+// depth first: an interior node's first child is the next node in the array and only the second
+// child's index is stored.
+#[derive(Clone, Copy, Debug)]
+struct BvhNode {
+    bounds: Aabb,
+    // for a leaf the first of its objects, for an interior node the index of its second child
+    offset: u32,
+    // how many objects a leaf holds, 0 for an interior node
+    count: u16,
+    // the axis an interior node's children were split along, traversal visits the nearer one first
+    axis: u8,
+}
+
+// A bounding volume hierarchy: the objects sorted into a tree of boxes so a ray only tests the
+// objects whose boxes it passes through. The tree is built once with the surface area heuristic and
+// can then be refit in place after objects move, which keeps the tree's shape and only grows and
+// shrinks its boxes. That is much cheaper than a rebuild, but the tree slows down if objects move
+// far from where it was built.
+#[derive(Clone, Debug)]
+pub struct Bvh<T: Hittable = Primitive> {
+    nodes: Vec<BvhNode>,
+    // in the order the leaves refer to them
+    objects: Vec<T>,
+    // the object_id of each object's first hittable, counted in the order the objects were given
+    first_ids: Vec<usize>,
+    // where each object given to new ended up in objects
+    slots: Vec<usize>,
+    // the total of the objects' object counts
+    object_count: usize,
+}
+
+// leaves hold at most this many objects
+const MAX_LEAF_SIZE: usize = 4;
+// how many buckets split candidates are taken from along an axis
+const BUCKETS: usize = 12;
+// the cost of visiting a node relative to testing one object
+const TRAVERSAL_COST: Float = 0.125;
+// past this depth nodes are split in half by count, which keeps the tree shallow enough for the
+// traversal stack whatever the objects look like
+const MAX_SAH_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+
+// an object's box and centroid while the tree is being built
+struct BuildItem {
+    index: usize,
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+impl<T: Hittable> Bvh<T> {
+    pub fn new(objects: Vec<T>) -> Bvh<T> {
+        let mut first_ids = Vec::with_capacity(objects.len());
+        let mut next_id = 0;
+        for object in &objects {
+            first_ids.push(next_id);
+            next_id += object.object_count();
+        }
+        let mut items: Vec<BuildItem> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let bounds = object.bounding_box();
+                BuildItem { index, bounds, centroid: bounds.centroid() }
+            })
+            .collect();
+        let mut nodes = Vec::with_capacity(2 * items.len());
+        if !items.is_empty() {
+            Bvh::<T>::build(&mut nodes, &mut items, 0, 0);
+        }
+
+        let mut slots = vec![0; objects.len()];
+        for (slot, item) in items.iter().enumerate() {
+            slots[item.index] = slot;
+        }
+        let mut objects: Vec<Option<T>> = objects.into_iter().map(Some).collect();
+        let sorted = items.iter().map(|item| objects[item.index].take().unwrap()).collect();
+        let first_ids = items.iter().map(|item| first_ids[item.index]).collect();
+        return Bvh { nodes, objects: sorted, first_ids, slots, object_count: next_id };
+    }
+
+    // adds the node for items, which start at first in the final object order, and its children
+    fn build(nodes: &mut Vec<BvhNode>, items: &mut [BuildItem], first: usize, depth: usize) {
+        let bounds = items.iter().fold(Aabb::EMPTY, |bounds, item| bounds.union(&item.bounds));
+        let index = nodes.len();
+        nodes.push(BvhNode { bounds, offset: first as u32, count: items.len() as u16, axis: 0 });
+        if items.len() == 1 {
+            return;
+        }
+
+        let centroids = items.iter().fold(Aabb::EMPTY, |bounds, item| bounds.union(&Aabb::new(item.centroid, item.centroid)));
+        let axis = centroids.longest_axis();
+        let split = if depth >= MAX_SAH_DEPTH || centroids.extent().axis(axis) <= 0.0 {
+            // the centroids are all in one place, so no plane separates them
+            if items.len() <= MAX_LEAF_SIZE {
+                return;
+            }
+            items.len() / 2
+        } else {
+            match Bvh::<T>::sah_split(items, &bounds, &centroids, axis) {
+                Some(split) => split,
+                None => return,
+            }
+        };
+
+        nodes[index].count = 0;
+        nodes[index].axis = axis as u8;
+        let (left, right) = items.split_at_mut(split);
+        Bvh::<T>::build(nodes, left, first, depth + 1);
+        nodes[index].offset = nodes.len() as u32;
+        Bvh::<T>::build(nodes, right, first + split, depth + 1);
+    }
+
+    // Sorts items into buckets along axis and picks the boundary between buckets where splitting
+    // costs least, going by the chance of a ray hitting each side. Returns how many items end up on
+    // the left once they are partitioned, or None when a leaf is cheaper.
+    fn sah_split(items: &mut [BuildItem], bounds: &Aabb, centroids: &Aabb, axis: usize) -> Option<usize> {
+        let min = centroids.min.axis(axis);
+        let extent = centroids.extent().axis(axis);
+        let bucket_of = |item: &BuildItem| -> usize {
+            let bucket = ((item.centroid.axis(axis) - min) / extent * (BUCKETS as Float)) as usize;
+            bucket.min(BUCKETS - 1)
+        };
+        let mut counts = [0; BUCKETS];
+        let mut bucket_bounds = [Aabb::EMPTY; BUCKETS];
+        for item in items.iter() {
+            let bucket = bucket_of(item);
+            counts[bucket] += 1;
+            bucket_bounds[bucket] = bucket_bounds[bucket].union(&item.bounds);
+        }
+
+        // the cost of splitting after each bucket, from sweeps in from both ends
+        let mut costs = [0.0; BUCKETS - 1];
+        let (mut left_bounds, mut left_count) = (Aabb::EMPTY, 0);
+        for split in 0..BUCKETS - 1 {
+            left_bounds = left_bounds.union(&bucket_bounds[split]);
+            left_count += counts[split];
+            costs[split] = left_bounds.surface_area() * (left_count as Float);
+        }
+        let (mut right_bounds, mut right_count) = (Aabb::EMPTY, 0);
+        for split in (0..BUCKETS - 1).rev() {
+            right_bounds = right_bounds.union(&bucket_bounds[split + 1]);
+            right_count += counts[split + 1];
+            costs[split] += right_bounds.surface_area() * (right_count as Float);
+        }
+        let (best, best_cost) = costs
+            .iter()
+            .enumerate()
+            .fold((0, Float::INFINITY), |best, (split, &cost)| if cost < best.1 { (split, cost) } else { best });
+
+        let area = bounds.surface_area();
+        let split_cost = TRAVERSAL_COST + if area > 0.0 { best_cost / area } else { 0.0 };
+        if items.len() <= MAX_LEAF_SIZE && (items.len() as Float) <= split_cost {
+            return None;
+        }
+
+        // move the items in buckets up to best to the front
+        let mut split = 0;
+        for i in 0..items.len() {
+            if bucket_of(&items[i]) <= best {
+                items.swap(i, split);
+                split += 1;
+            }
+        }
+        if split == 0 || split == items.len() {
+            return if items.len() <= MAX_LEAF_SIZE { None } else { Some(items.len() / 2) };
+        }
+        return Some(split);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.objects.len();
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.objects.is_empty();
+    }
+    // the index-th object given to new
+    pub fn object(&self, index: usize) -> &T {
+        return &self.objects[self.slots[index]];
+    }
+    // the index-th object given to new, to move it before calling refit or rebuild, which mustn't
+    // change how many objects it holds since the object ids were counted in new
+    pub fn object_mut(&mut self, index: usize) -> &mut T {
+        return &mut self.objects[self.slots[index]];
+    }
+    // Recomputes every box after objects have moved, keeping the shape of the tree. Children come
+    // after their parents in the array, so going through it backwards reaches both children of a
+    // node before the node itself.
+    pub fn refit(&mut self) {
+        for index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[index];
+            let offset = node.offset as usize;
+            self.nodes[index].bounds = if node.count > 0 {
+                self.objects[offset..offset + (node.count as usize)]
+                    .iter()
+                    .fold(Aabb::EMPTY, |bounds, object| bounds.union(&object.bounding_box()))
+            } else {
+                self.nodes[index + 1].bounds.union(&self.nodes[offset].bounds)
+            };
+        }
+    }
+    // Builds the tree again from scratch around the objects where they are now, for when they have
+    // moved too far for refit to keep the tree fast. Object ids and indices stay the same.
+    pub fn rebuild(&mut self) {
+        let mut objects: Vec<Option<T>> = mem::take(&mut self.objects).into_iter().map(Some).collect();
+        let objects = self.slots.iter().map(|&slot| objects[slot].take().unwrap()).collect();
+        *self = Bvh::new(objects);
+    }
+}
+
+impl<T: Hittable> Hittable for Bvh<T> {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let inverse_direction = Vec3::new(1.0 / direction.x(), 1.0 / direction.y(), 1.0 / direction.z());
+
+        let mut closest_so_far = ray_t.max;
+        let mut hit_record = None;
+        // nodes still to visit, instead of recursing
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            if node.bounds.hit(origin, inverse_direction, ray_t.min, closest_so_far) {
+                let offset = node.offset as usize;
+                if node.count > 0 {
+                    for slot in offset..offset + (node.count as usize) {
+                        if let Some(mut hit) = self.objects[slot].hit(ray, &Interval::new(ray_t.min, closest_so_far)) {
+                            closest_so_far = hit.t;
+                            hit.object_id += self.first_ids[slot];
+                            hit_record = Some(hit);
+                        }
+                    }
+                } else {
+                    // visit the child on the side the ray comes from first, so later boxes can be
+                    // skipped once something closer has been hit
+                    let (near, far) = if direction.axis(node.axis as usize) < 0.0 {
+                        (offset, index + 1)
+                    } else {
+                        (index + 1, offset)
+                    };
+                    stack[stack_len] = far as u32;
+                    stack_len += 1;
+                    index = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            index = stack[stack_len] as usize;
+        }
+        return hit_record;
+    }
+    fn bounding_box(&self) -> Aabb {
+        return match self.nodes.first() {
+            Some(root) => root.bounds,
+            None => Aabb::EMPTY,
+        };
+    }
+    fn object_count(&self) -> usize {
+        return self.object_count;
+    }
+}
+
+#[cfg(test)]
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+#[cfg(test)]
+use crate::{ hittable_list::HittableList, scene::SceneDescription, sphere::Sphere, point3d::Point3D };
+
+#[cfg(test)]
+fn random_rays(count: usize, seed: u64) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    return (0..count)
+        .map(|_| {
+            let origin = Point3D::new(13.0, 2.0, 3.0) + Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+            let target = Point3D::new(rng.gen_range(-11.0..11.0), rng.gen_range(-1.0..2.0), rng.gen_range(-11.0..11.0));
+            Ray::new(origin, target - origin)
+        })
+        .collect();
+}
+
+// every hit, or miss, of world matches the one from a plain list of the same objects
+#[cfg(test)]
+fn assert_same_hits(world: &dyn Hittable, list: &HittableList, seed: u64) {
+    let ray_t = Interval::new(0.001, Float::INFINITY);
+    let mut hits = 0;
+    for ray in random_rays(1000, seed) {
+        let expected = list.hit(&ray, &ray_t);
+        hits += expected.is_some() as usize;
+        assert_eq!(world.hit(&ray, &ray_t), expected);
+    }
+    assert!(hits > 500);
+}
+
+#[test]
+fn test_bvh_matches_list() {
+    let spheres = SceneDescription::random_spheres(3).build_spheres();
+    let mut list = HittableList::new();
+    for sphere in &spheres {
+        list.add(*sphere);
+    }
+    let bvh = Bvh::new(spheres.iter().map(|&sphere| Primitive::Sphere(sphere)).collect());
+    assert_eq!(bvh.len(), spheres.len());
+    assert_eq!(bvh.object_count(), spheres.len());
+    assert_eq!(bvh.bounding_box(), list.bounding_box());
+    // a node per leaf and per interior node, and far fewer leaves than objects would be suspicious
+    assert!(bvh.nodes.len() < 2 * spheres.len());
+    assert_same_hits(&bvh, &list, 5);
+}
+
+#[test]
+fn test_bvh_refit() {
+    let spheres = SceneDescription::random_spheres(4).build_spheres();
+    let mut bvh = Bvh::new(spheres.iter().map(|&sphere| Primitive::Sphere(sphere)).collect());
+    // move every small sphere up and to the side, as an animation might between frames
+    let mut list = HittableList::new();
+    for (index, sphere) in spheres.iter().enumerate() {
+        let moved = if sphere.radius() < 1.0 {
+            Sphere::new(sphere.center() + Vec3::new(0.5, 1.0, -0.25), sphere.radius(), sphere.material())
+        } else {
+            *sphere
+        };
+        *bvh.object_mut(index) = Primitive::Sphere(moved);
+        list.add(moved);
+    }
+    bvh.refit();
+    assert_eq!(bvh.bounding_box(), list.bounding_box());
+    assert_same_hits(&bvh, &list, 6);
+}
+
+#[test]
+fn test_bvh_edge_cases() {
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+    let ray_t = Interval::new(0.001, Float::INFINITY);
+    let empty: Bvh = Bvh::new(Vec::new());
+    assert!(empty.is_empty());
+    assert!(empty.hit(&ray, &ray_t).is_none());
+    assert_eq!(empty.bounding_box(), Aabb::EMPTY);
+
+    // many objects in the same place can't be split by position, but must still all be found
+    let stacked: Vec<Primitive> = (0..20)
+        .map(|i| Primitive::Sphere(Sphere::new(Point3D::new(0.0, 0.0, -5.0), 1.0 + (i as Float) * 0.1, 0)))
+        .collect();
+    let bvh = Bvh::new(stacked);
+    let hit = bvh.hit(&ray, &ray_t).unwrap();
+    // the biggest sphere is hit first, and the ids are in the order the spheres were given
+    assert_eq!(hit.object_id, 19);
+    assert!((hit.t - 2.1).abs() < 1e-5);
+}
+
+#[test]
+fn test_bvh_layout() {
+    let spheres = SceneDescription::random_spheres(7).build_spheres();
+    let bvh = Bvh::new(spheres.iter().map(|&sphere| Primitive::Sphere(sphere)).collect());
+    #[cfg(not(feature = "f64"))]
+    assert_eq!(std::mem::size_of::<BvhNode>(), 32);
+    // walking the nodes in array order, every interior node's first child comes right after it and
+    // its second child after the whole of the first child's subtree, and the leaves cover the
+    // objects in order, each exactly once
+    let mut next_object = 0;
+    for (index, node) in bvh.nodes.iter().enumerate() {
+        let offset = node.offset as usize;
+        if node.count > 0 {
+            assert_eq!(offset, next_object);
+            next_object += node.count as usize;
+            assert!((node.count as usize) <= MAX_LEAF_SIZE);
+        } else {
+            assert!(offset > index + 1);
+            let (first, second) = (&bvh.nodes[index + 1], &bvh.nodes[offset]);
+            assert_eq!(node.bounds, first.bounds.union(&second.bounds));
+        }
+    }
+    assert_eq!(next_object, spheres.len());
+}
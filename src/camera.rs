@@ -1,173 +1,513 @@
-use std::{ io::{ self, Write }, fs::File };
-
-use rand::random;
+use std::{ hash::Hasher, io, net::{ TcpListener, TcpStream }, path::Path, time::Instant };
 
 use crate::{
     point3d::Point3D,
-    hittable_list::HittableList,
+    world::World,
+    material::Scatter,
     ray::Ray,
-    vec3::{ Vec3, UnitVec, Cross },
+    vec3::{ Vec3, UnitVec },
     hittable::Hittable,
     interval::Interval,
     color::Color,
+    framebuffer::FrameBuffer,
+    render_settings::RenderSettings,
+    sampler::Sampler,
+    aov::{ AovBuffers, AovSample },
+    denoise::denoise,
+    camera_model::{ CameraModel, ThinLensCamera },
+    image::{ Image, PixelRect },
+    checkpoint::{ save_checkpoint, load_checkpoint, FnvHasher },
+    distributed::{ split_into_tiles, coordinate, work, CoordinatorSettings, WORKER_TIMEOUT },
+    render_output::{ RenderOutput, RenderMetadata },
+    observer::{
+        RenderObserver,
+        NullObserver,
+        RenderStart,
+        RenderSnapshot,
+        RenderStatus,
+        CancellationToken,
+        cancelled_error,
+    },
+    float::Float,
 };
 
+// everything a render accumulates into while it runs
+struct RenderState {
+    // the resolution being rendered, smaller than the camera's when previewing
+    width: i32,
+    height: i32,
+    // the pixels that take samples
+    region: PixelRect,
+    sampler: Box<dyn Sampler>,
+    framebuffer: FrameBuffer,
+    aovs: Option<AovBuffers>,
+    // identifies the settings that decide which samples get taken, stored in checkpoints
+    settings_hash: u64,
+    last_checkpoint: Instant,
+    started: Instant,
+    // samples in the region, and how many of them were resumed from a checkpoint
+    samples_taken: u64,
+    resumed_samples: u64,
+}
+
 pub struct Camera {
     image_width: i32,
     image_height: i32,
-    center: Point3D,
-    pixel00_loc: Point3D,
-    pixel_delta_u: Vec3,
-    pixel_delta_v: Vec3,
     samples_per_pixel: i32,
     max_depth: i32,
-    defocus_disk_u: Vec3,
-    defocus_disk_v: Vec3,
-    defocus_angle: f32,
+    model: Box<dyn CameraModel>,
 }
 
 impl Camera {
+    // positional like the book's camera, whose settings these are
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        aspect_ratio: f32,
+        aspect_ratio: Float,
         image_width: i32,
         samples_per_pixel: i32,
         max_depth: i32,
-        vfov: f32,
+        vfov: Float,
         look_from: Point3D,
         look_at: Point3D,
         vup: Vec3,
-        defocus_angle: f32,
-        focus_distance: f32
+        defocus_angle: Float,
+        focus_distance: Float
+    ) -> Camera {
+        let image_height = Camera::image_height(aspect_ratio, image_width);
+        let model = ThinLensCamera::new(
+            // use the aspect ratio of the rounded image size so that pixels stay square
+            (image_width as Float) / (image_height as Float),
+            vfov,
+            look_from,
+            look_at,
+            vup,
+            defocus_angle,
+            focus_distance
+        );
+        return Camera::with_model(image_width, image_height, samples_per_pixel, max_depth, Box::new(model));
+    }
+
+    // renders with any camera model, every model shares the same render loop and outputs
+    pub fn with_model(
+        image_width: i32,
+        image_height: i32,
+        samples_per_pixel: i32,
+        max_depth: i32,
+        model: Box<dyn CameraModel>
     ) -> Camera {
-        // Calculate the image height based on the provided width to ensure we match the aspect ratio, ensure that the height is at least 1
-        let image_height = if (image_width as f32) / aspect_ratio < 1.0 {
+        Camera { image_width, image_height, samples_per_pixel, max_depth, model }
+    }
+
+    // Calculate the image height based on the provided width to ensure we match the aspect ratio, ensure that the height is at least 1
+    pub fn image_height(aspect_ratio: Float, image_width: i32) -> i32 {
+        return if (image_width as Float) / aspect_ratio < 1.0 {
             1
         } else {
-            ((image_width as f32) / aspect_ratio) as i32
+            ((image_width as Float) / aspect_ratio) as i32
         };
+    }
 
-        let center = look_from;
-
-        // Determine viewport dimensions
-        let theta = f32::to_radians(vfov);
-        let h = f32::tan(theta / 2.0);
-        let viewport_height = 2.0 * h * focus_distance;
-        let viewport_width =
-            viewport_height * (((image_width as f32) / (image_height as f32)) as f32);
-
-        // Calculate the u,v,w unit basis vectors for the camera coordinate frame
-        let w = (look_from - look_at).unit_vec();
-        let u = vup.cross(w).unit_vec();
-        let v = w.cross(u);
-
-        // Calculate the viewport edge vectors
-        let viewport_u = viewport_width * u;
-        let viewport_v = viewport_height * -v;
-
-        // Calculate the delta vectors between pixels
-        let pixel_delta_u = viewport_u / (image_width as f32);
-        let pixel_delta_v = viewport_v / (image_height as f32);
-
-        // Calculate the location of the upper left pixel
-        let viewport_upper_left = center - focus_distance * w - viewport_u / 2.0 - viewport_v / 2.0;
-        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
-
-        // Calculate the camrera defocus disk basis vectors
-        let defocus_radius = focus_distance * f32::tan(f32::to_radians(defocus_angle / 2.0));
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
-
-        return Camera {
-            image_width,
-            image_height,
-            center,
-            pixel00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
-            samples_per_pixel,
-            max_depth,
-            defocus_disk_u,
-            defocus_disk_v,
-            defocus_angle,
+    // Renders the world into memory. Nothing is written to disk except the checkpoint when settings
+    // ask for one, writing the result is up to the caller.
+    pub fn render_to_buffer(&self, world: &World, settings: &RenderSettings) -> io::Result<RenderOutput> {
+        return self.render_observed(world, settings, &mut NullObserver, &CancellationToken::new());
+    }
+
+    // Renders like render_to_buffer while telling observer how it is going, and gives up with an
+    // Interrupted error at the next tile once cancel is cancelled.
+    pub fn render_observed(
+        &self,
+        world: &World,
+        settings: &RenderSettings,
+        observer: &mut dyn RenderObserver,
+        cancel: &CancellationToken
+    ) -> io::Result<RenderOutput> {
+        let mut state = self.new_state(settings);
+        if let Some(checkpoint) = &settings.checkpoint {
+            if checkpoint.resume && Path::new(&checkpoint.path).exists() {
+                // the aovs are not saved, so after resuming they only describe the new samples
+                state.framebuffer = load_checkpoint(&checkpoint.path, checkpoint.scene_hash, state.settings_hash)?;
+                state.samples_taken = Camera::count_samples(&state.framebuffer, &state.region);
+                state.resumed_samples = state.samples_taken;
+            }
+        }
+        let tiles = split_into_tiles(&state.region, settings.tile_size);
+        observer.on_start(&self.render_start(&state, tiles.len()));
+        match &settings.progressive {
+            Some(_) => self.render_progressive(world, settings, &tiles, &mut state, observer, cancel)?,
+            None => self.render_by_pixel(world, settings, &tiles, &mut state, observer, cancel)?,
+        }
+        let output = self.finish(settings, state)?;
+        observer.on_finish(&output);
+        return Ok(output);
+    }
+
+    // Renders the image by handing tiles out to worker processes that connect to listener, see
+    // distributed.rs. Workers only send back their framebuffers, so aovs and denoising are not available.
+    pub fn render_distributed(
+        &self,
+        settings: &RenderSettings,
+        listener: TcpListener,
+        scene_hash: u64,
+        observer: &mut dyn RenderObserver,
+        cancel: &CancellationToken
+    ) -> io::Result<RenderOutput> {
+        let mut state = self.new_state(settings);
+        state.aovs = None;
+        let tiles = split_into_tiles(&state.region, settings.tile_size);
+        observer.on_start(&self.render_start(&state, tiles.len()));
+        let coordinator_settings = CoordinatorSettings {
+            width: state.width,
+            height: state.height,
+            margin: settings.filter.margin(),
+            scene_hash,
+            settings_hash: state.settings_hash,
+            timeout: WORKER_TIMEOUT,
         };
+        state.framebuffer = coordinate(
+            listener,
+            coordinator_settings,
+            tiles,
+            cancel,
+            |tile, tiles_left, framebuffer| {
+                state.samples_taken += Camera::count_samples(framebuffer, tile);
+                // the framebuffer being put together, rather than the state's empty one
+                let snapshot = RenderSnapshot { framebuffer, ..self.snapshot(settings, &state) };
+                observer.on_tile_complete(tile, tiles_left, &snapshot);
+            }
+        )?;
+        let output = self.finish(settings, state)?;
+        observer.on_finish(&output);
+        return Ok(output);
+    }
+
+    // Connects to a coordinator started with render_distributed and renders the tiles it hands out
+    // until there are none left, returning how many tiles this worker rendered.
+    pub fn serve_tiles(
+        &self,
+        world: &World,
+        settings: &RenderSettings,
+        stream: TcpStream,
+        scene_hash: u64
+    ) -> io::Result<u32> {
+        return work(stream, scene_hash, self.settings_hash(settings), |tile| self.render_tile(world, settings, tile));
     }
 
-    pub fn render(&self, world: &HittableList) {
-        println!("Beginning render for {}x{}", self.image_width, self.image_height);
-        let mut file = File::create("./output/image.ppm").expect("Failed creating file");
-        file.write(
-            format!("P3\n{} {}\n255\n", self.image_width, self.image_height).as_bytes()
-        ).expect("Failed writing to file");
-        for j in 0..self.image_height {
-            print!("Scanlines remaining: {}\r", self.image_height - j);
-            io::stdout().flush().unwrap();
-            for i in 0..self.image_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(i, j);
-                    pixel_color += self.ray_color(&ray, self.max_depth, world);
+    // Renders just the pixels of tile into a fresh framebuffer. Filters spread samples past the
+    // edge of the tile, so the result covers the tile grown by the filter radius, and is returned
+    // with the rect it covers in the full framebuffer.
+    pub fn render_tile(&self, world: &World, settings: &RenderSettings, tile: &PixelRect) -> (PixelRect, FrameBuffer) {
+        let mut state = self.new_state(settings);
+        state.aovs = None;
+        for j in tile.y..tile.y + tile.height {
+            for i in tile.x..tile.x + tile.width {
+                while !self.is_done(settings, &state.framebuffer, i, j) {
+                    self.add_sample(world, settings, &mut state, i, j);
                 }
-                let scale = 1.0 / (self.samples_per_pixel as f32);
-                // images are generally stored in gamma space, so here we convert our linear values into the gamma space
-                // for more accurate color intensity when viewing the image in image editors
-                let r = Color::linear_to_gamma(pixel_color.x() * scale);
-                let g = Color::linear_to_gamma(pixel_color.y() * scale);
-                let b = Color::linear_to_gamma(pixel_color.z() * scale);
-
-                let intensity = Interval::new(0.0, 0.999);
-
-                file.write(
-                    format!(
-                        "{} {} {}\n",
-                        (256.0 * intensity.clamp(r)) as i32,
-                        (256.0 * intensity.clamp(g)) as i32,
-                        (256.0 * intensity.clamp(b)) as i32
-                    ).as_bytes()
-                ).expect("Failed writing to file");
             }
         }
-        println!("\nFinished render");
+        let covered = tile.grown(settings.filter.margin(), &PixelRect::full(state.width, state.height));
+        return (covered, state.framebuffer.extract(&covered));
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        // Get a randomly-sampled camera ray for the pixel at i,j originating
-        // from the camera defocus disk
-        let pixel_center =
-            self.pixel00_loc + (i as f32) * self.pixel_delta_u + (j as f32) * self.pixel_delta_v;
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+    fn new_state(&self, settings: &RenderSettings) -> RenderState {
+        let (width, height) = settings.render_size(self.image_width, self.image_height);
+        RenderState {
+            width,
+            height,
+            region: settings.render_region(self.image_width, self.image_height),
+            sampler: settings.sampler.create(self.samples_per_pixel as u32, settings.seed),
+            framebuffer: FrameBuffer::new(width, height),
+            // the denoiser is guided by the normal and albedo aovs, so collect them even if they are not written
+            aovs: if settings.aovs.is_empty() && settings.denoise.is_none() {
+                None
+            } else {
+                Some(AovBuffers::new(width, height))
+            },
+            settings_hash: self.settings_hash(settings),
+            last_checkpoint: Instant::now(),
+            started: Instant::now(),
+            samples_taken: 0,
+            resumed_samples: 0,
+        }
+    }
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
-        } else {
-            self.defocus_disk_sample()
+    fn render_start(&self, state: &RenderState, tiles: usize) -> RenderStart {
+        return RenderStart {
+            width: state.width,
+            height: state.height,
+            region: state.region,
+            samples_per_pixel: self.samples_per_pixel as u32,
+            tiles,
+            resumed_samples: state.resumed_samples,
         };
-        let ray_direction = pixel_sample - ray_origin;
+    }
 
-        return Ray::new(ray_origin, ray_direction);
+    // saves the final checkpoint and gathers everything a finished render produced
+    fn finish(&self, settings: &RenderSettings, state: RenderState) -> io::Result<RenderOutput> {
+        if let Some(checkpoint) = &settings.checkpoint {
+            // keep the finished render too, so it can be resumed later with more samples per pixel
+            save_checkpoint(&checkpoint.path, checkpoint.scene_hash, state.settings_hash, &state.framebuffer)?;
+        }
+        let mut image = state.framebuffer.resolve();
+        if let (Some(denoise_settings), Some(aovs)) = (&settings.denoise, &state.aovs) {
+            image = denoise(&image, aovs, denoise_settings, &state.region);
+        }
+        let image = Camera::output_image(image, settings, &state);
+        let metadata = RenderMetadata {
+            width: state.width,
+            height: state.height,
+            rect: Camera::output_rect(settings, &state),
+            region: state.region,
+            samples_per_pixel: self.samples_per_pixel as u32,
+            samples: state.samples_taken,
+            resumed_samples: state.resumed_samples,
+            elapsed: state.started.elapsed(),
+            settings_hash: state.settings_hash,
+        };
+        return Ok(RenderOutput { image, framebuffer: state.framebuffer, aovs: state.aovs, metadata });
     }
 
-    // returns a random point in the camera defocus disk
-    fn defocus_disk_sample(&self) -> Point3D {
-        let p = Vec3::random_in_unit_disk();
-        return self.center + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v;
+    // everything that decides which samples a pixel receives, so samples from a checkpoint are only
+    // mixed with samples that would have been taken by the same render. The samples per pixel are
+    // left out so that a finished render can be resumed to add more. The size of Float is in there too,
+    // since framebuffers from an f32 and an f64 build neither match nor read back the same way.
+    pub fn settings_hash(&self, settings: &RenderSettings) -> u64 {
+        let mut hasher = FnvHasher::new();
+        let (width, height) = settings.render_size(self.image_width, self.image_height);
+        let region = settings.render_region(self.image_width, self.image_height);
+        for value in [width, height, region.x, region.y, region.width, region.height, self.max_depth] {
+            hasher.write(&value.to_le_bytes());
+        }
+        hasher.write(&settings.seed.to_le_bytes());
+        hasher.write(&size_of::<Float>().to_le_bytes());
+        hasher.write(format!("{:?} {:?}", settings.sampler, settings.filter).as_bytes());
+        return hasher.finish();
     }
 
-    // returns a random point in the square surrounding a pixel at the origin
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + random::<f32>();
-        let py = -0.5 + random::<f32>();
-        return px * self.pixel_delta_u + py * self.pixel_delta_v;
+    // saves a checkpoint if one is due
+    fn checkpoint(settings: &RenderSettings, state: &mut RenderState) -> io::Result<()> {
+        if let Some(checkpoint) = &settings.checkpoint {
+            if checkpoint.should_save(state.last_checkpoint.elapsed()) {
+                save_checkpoint(&checkpoint.path, checkpoint.scene_hash, state.settings_hash, &state.framebuffer)?;
+                state.last_checkpoint = Instant::now();
+            }
+        }
+        return Ok(());
+    }
+
+    // stops the render if it has been cancelled, saving its progress first so it can be resumed
+    fn check_cancelled(settings: &RenderSettings, state: &RenderState, cancel: &CancellationToken) -> io::Result<()> {
+        if !cancel.is_cancelled() {
+            return Ok(());
+        }
+        if let Some(checkpoint) = &settings.checkpoint {
+            save_checkpoint(&checkpoint.path, checkpoint.scene_hash, state.settings_hash, &state.framebuffer)?;
+        }
+        return Err(cancelled_error());
     }
 
-    fn ray_color(&self, ray: &Ray, depth: i32, world: &HittableList) -> Color {
+    fn snapshot<'a>(&self, settings: &RenderSettings, state: &'a RenderState) -> RenderSnapshot<'a> {
+        let pixels = (state.region.width as u64) * (state.region.height as u64);
+        return RenderSnapshot {
+            framebuffer: &state.framebuffer,
+            region: state.region,
+            rect: Camera::output_rect(settings, state),
+            status: RenderStatus {
+                samples: state.samples_taken,
+                resumed_samples: state.resumed_samples,
+                total_samples: pixels * (self.samples_per_pixel as u64),
+                pixels,
+                elapsed: state.started.elapsed(),
+            },
+        };
+    }
+
+    fn count_samples(framebuffer: &FrameBuffer, rect: &PixelRect) -> u64 {
+        let mut samples = 0;
+        for j in rect.y..rect.y + rect.height {
+            for i in rect.x..rect.x + rect.width {
+                samples += framebuffer.sample_count(i, j) as u64;
+            }
+        }
+        return samples;
+    }
+
+    // the part of the render that is written out: just the crop, or everything when the crop is
+    // shown in place
+    fn output_rect(settings: &RenderSettings, state: &RenderState) -> PixelRect {
+        return if settings.crop_full_frame { PixelRect::full(state.width, state.height) } else { state.region };
+    }
+
+    fn output_image(image: Image, settings: &RenderSettings, state: &RenderState) -> Image {
+        let mut image = image;
+        // filters splat samples a little past the edge of the region, which should stay black too
+        image.clear_outside(&state.region);
+        return image.crop(&Camera::output_rect(settings, state));
+    }
+
+    // takes every sample for a pixel before moving on to the next one. Pixels go in scanline order
+    // within each tile and tile by tile across the image, a tile_size at least as wide as the
+    // image gives back plain scanline order
+    fn render_by_pixel(
+        &self,
+        world: &World,
+        settings: &RenderSettings,
+        tiles: &[PixelRect],
+        state: &mut RenderState,
+        observer: &mut dyn RenderObserver,
+        cancel: &CancellationToken
+    ) -> io::Result<()> {
+        for (index, tile) in tiles.iter().enumerate() {
+            Camera::check_cancelled(settings, state, cancel)?;
+            for j in tile.y..tile.y + tile.height {
+                for i in tile.x..tile.x + tile.width {
+                    // a resumed render already has some of the pixel's samples
+                    while !self.is_done(settings, &state.framebuffer, i, j) {
+                        self.add_sample(world, settings, state, i, j);
+                    }
+                }
+            }
+            observer.on_tile_complete(tile, tiles.len() - index - 1, &self.snapshot(settings, state));
+            Camera::checkpoint(settings, state)?;
+        }
+        return Ok(());
+    }
+
+    // pixels only converge when adaptive sampling is enabled, otherwise they always take every sample
+    fn has_converged(settings: &RenderSettings, framebuffer: &FrameBuffer, i: i32, j: i32) -> bool {
+        return match &settings.adaptive {
+            Some(adaptive) =>
+                adaptive.has_converged(framebuffer.sample_count(i, j), framebuffer.relative_error(i, j)),
+            None => false,
+        };
+    }
+
+    // whether the pixel at i,j needs no more samples
+    fn is_done(&self, settings: &RenderSettings, framebuffer: &FrameBuffer, i: i32, j: i32) -> bool {
+        return framebuffer.sample_count(i, j) >= (self.samples_per_pixel as u32) ||
+            Camera::has_converged(settings, framebuffer, i, j);
+    }
+
+    // takes one sample for every pixel per pass, so the whole image sharpens together and
+    // intermediate results are worth looking at
+    fn render_progressive(
+        &self,
+        world: &World,
+        settings: &RenderSettings,
+        tiles: &[PixelRect],
+        state: &mut RenderState,
+        observer: &mut dyn RenderObserver,
+        cancel: &CancellationToken
+    ) -> io::Result<()> {
+        let region = state.region;
+        // a resumed render picks up at the pass its least sampled pixel had reached
+        let mut first_pass = u32::MAX;
+        for j in region.y..region.y + region.height {
+            for i in region.x..region.x + region.width {
+                first_pass = u32::min(first_pass, state.framebuffer.sample_count(i, j) + 1);
+            }
+        }
+        for pass in first_pass..=self.samples_per_pixel as u32 {
+            let mut active_pixels = 0;
+            for (index, tile) in tiles.iter().enumerate() {
+                Camera::check_cancelled(settings, state, cancel)?;
+                for j in tile.y..tile.y + tile.height {
+                    for i in tile.x..tile.x + tile.width {
+                        if self.is_done(settings, &state.framebuffer, i, j) {
+                            continue;
+                        }
+                        active_pixels += 1;
+                        self.add_sample(world, settings, state, i, j);
+                    }
+                }
+                observer.on_tile_complete(tile, tiles.len() - index - 1, &self.snapshot(settings, state));
+            }
+            // every pixel had already converged, so further passes would not add anything
+            if active_pixels == 0 {
+                break;
+            }
+            observer.on_pass_complete(pass, &self.snapshot(settings, state));
+            Camera::checkpoint(settings, state)?;
+        }
+        return Ok(());
+    }
+
+    // traces one more camera sample for the pixel at i,j
+    fn add_sample(
+        &self,
+        world: &World,
+        settings: &RenderSettings,
+        state: &mut RenderState,
+        i: i32,
+        j: i32
+    ) {
+        let sampler = state.sampler.as_mut();
+        sampler.start_pixel_sample(i, j, state.framebuffer.sample_count(i, j));
+        let (u, v) = sampler.get_2d();
+        let offset = (-0.5 + u, -0.5 + v);
+        let film_size = (state.width, state.height);
+        let color = match (self.get_ray(film_size, i, j, offset, sampler), &mut state.aovs) {
+            // the model has no ray for this part of the film, so it stays black
+            (None, _) => Color::new(0.0, 0.0, 0.0),
+            (Some(ray), Some(aovs)) => {
+                let mut first_hit = None;
+                let color = self.ray_color(&ray, self.max_depth, world, sampler, Some(&mut first_hit));
+                if let Some(first_hit) = first_hit {
+                    aovs.add_sample(i, j, &first_hit);
+                }
+                color
+            }
+            (Some(ray), None) => self.ray_color(&ray, self.max_depth, world, sampler, None),
+        };
+        state.framebuffer.add_filtered_sample(i, j, offset, color, &settings.filter);
+        state.samples_taken += 1;
+    }
+
+    // offset is where in the pixel the ray should pass through, relative to its center, and
+    // film_size is the resolution being rendered
+    fn get_ray(
+        &self,
+        film_size: (i32, i32),
+        i: i32,
+        j: i32,
+        offset: (Float, Float),
+        sampler: &mut dyn Sampler
+    ) -> Option<Ray> {
+        let film = (
+            ((i as Float) + 0.5 + offset.0) / (film_size.0 as Float),
+            ((j as Float) + 0.5 + offset.1) / (film_size.1 as Float),
+        );
+        // always draw the lens sample so that later dimensions line up whether or not the model uses it
+        let lens = sampler.get_2d();
+        return self.model.generate_ray(film, lens);
+    }
+
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        depth: i32,
+        world: &World,
+        sampler: &mut dyn Sampler,
+        first_hit: Option<&mut Option<AovSample>>
+    ) -> Color {
         // If we've exceeded the ray bounce limit, no more light is gathered.
         if depth <= 0 {
             return Color::new(0.0, 0.0, 0.0);
         }
-        // Ignore hits that are very close to the calculated intersection point to prevent "shadow acne" from floating point rounding errors
-        if let Some(hit) = world.hit(ray, &Interval::new(0.001, f32::INFINITY)) {
-            if let Some((scattered, attenuation)) = hit.material.scatter(ray, &hit) {
-                return attenuation * self.ray_color(&scattered, depth - 1, world);
+        // scattered rays start off the surface they leave by its rounding error (see HitRecord::spawn_ray),
+        // so there's no need to ignore close hits to avoid "shadow acne"
+        if let Some(hit) = world.hit(ray, &Interval::new(0.0, Float::INFINITY)) {
+            // an object whose material was never added to the world absorbs the ray
+            let Some(material) = world.material(hit.material) else {
+                return Color::new(0.0, 0.0, 0.0);
+            };
+            // only the camera ray asks for its hit to be recorded for the aovs
+            if let Some(first_hit) = first_hit {
+                *first_hit = Some(AovSample::new(&hit, material));
+            }
+            if let Some((scattered, attenuation)) = material.scatter(ray, &hit, sampler) {
+                return attenuation * self.ray_color(&scattered, depth - 1, world, sampler, None);
             }
             return Color::new(0.0, 0.0, 0.0);
         }
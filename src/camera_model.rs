@@ -0,0 +1,283 @@
+use crate::{
+    point3d::Point3D,
+    ray::Ray,
+    vec3::{ Vec3, UnitVec, Cross },
+    sampler::sample_uniform_disk_concentric,
+    float::{ Float, consts::PI },
+};
+
+// A camera model turns a position on the film into a ray. film is the position on the image
+// normalized to [0, 1] in both directions, from the top left corner, so a model does not need to
+// know the image resolution (only its aspect ratio). lens is a 2d sample in [0, 1) for models
+// with an aperture. Models return None for film positions that see nothing (e.g. outside a fisheye's circle).
+pub trait CameraModel {
+    fn generate_ray(&self, film: (Float, Float), lens: (Float, Float)) -> Option<Ray>;
+}
+
+// the orthonormal basis of a camera looking from look_from towards look_at,
+// u points right, v points up and w points backwards (away from what the camera sees)
+#[derive(Clone, Copy)]
+pub struct CameraFrame {
+    pub origin: Point3D,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl CameraFrame {
+    pub fn new(look_from: Point3D, look_at: Point3D, vup: Vec3) -> CameraFrame {
+        let w = (look_from - look_at).unit_vec();
+        let u = vup.cross(w).unit_vec();
+        let v = w.cross(u);
+        CameraFrame { origin: look_from, u, v, w }
+    }
+    // converts a direction from camera space (x right, y up, -z forward) to world space
+    pub fn to_world(&self, d: Vec3) -> Vec3 {
+        return d.x() * self.u + d.y() * self.v + d.z() * self.w;
+    }
+}
+
+// the classic perspective camera, with an optional thin lens for depth of field
+pub struct ThinLensCamera {
+    center: Point3D,
+    viewport_upper_left: Point3D,
+    viewport_u: Vec3,
+    viewport_v: Vec3,
+    defocus_disk_u: Vec3,
+    defocus_disk_v: Vec3,
+    defocus_angle: Float,
+}
+
+impl ThinLensCamera {
+    pub fn new(
+        aspect_ratio: Float,
+        vfov: Float,
+        look_from: Point3D,
+        look_at: Point3D,
+        vup: Vec3,
+        defocus_angle: Float,
+        focus_distance: Float
+    ) -> ThinLensCamera {
+        let frame = CameraFrame::new(look_from, look_at, vup);
+
+        // Determine viewport dimensions
+        let theta = Float::to_radians(vfov);
+        let h = Float::tan(theta / 2.0);
+        let viewport_height = 2.0 * h * focus_distance;
+        let viewport_width = viewport_height * aspect_ratio;
+
+        // Calculate the viewport edge vectors
+        let viewport_u = viewport_width * frame.u;
+        let viewport_v = viewport_height * -frame.v;
+
+        // Calculate the location of the upper left corner of the viewport
+        let viewport_upper_left =
+            frame.origin - focus_distance * frame.w - viewport_u / 2.0 - viewport_v / 2.0;
+
+        // Calculate the camrera defocus disk basis vectors
+        let defocus_radius = focus_distance * Float::tan(Float::to_radians(defocus_angle / 2.0));
+
+        return ThinLensCamera {
+            center: frame.origin,
+            viewport_upper_left,
+            viewport_u,
+            viewport_v,
+            defocus_disk_u: frame.u * defocus_radius,
+            defocus_disk_v: frame.v * defocus_radius,
+            defocus_angle,
+        };
+    }
+    // maps a 2d sample to a point in the camera defocus disk
+    fn defocus_disk_sample(&self, u: (Float, Float)) -> Point3D {
+        let p = sample_uniform_disk_concentric(u);
+        return self.center + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v;
+    }
+}
+
+impl CameraModel for ThinLensCamera {
+    fn generate_ray(&self, film: (Float, Float), lens: (Float, Float)) -> Option<Ray> {
+        // rays start on the defocus disk and pass through the film position on the focus plane,
+        // so only things on the focus plane are perfectly sharp
+        let pixel_sample = self.viewport_upper_left + film.0 * self.viewport_u + film.1 * self.viewport_v;
+        let ray_origin = if self.defocus_angle <= 0.0 {
+            self.center
+        } else {
+            self.defocus_disk_sample(lens)
+        };
+        return Some(Ray::new(ray_origin, pixel_sample - ray_origin));
+    }
+}
+
+// parallel rays with no perspective, view_height is the height in world units of the visible area
+pub struct OrthographicCamera {
+    frame: CameraFrame,
+    view_width: Float,
+    view_height: Float,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        aspect_ratio: Float,
+        view_height: Float,
+        look_from: Point3D,
+        look_at: Point3D,
+        vup: Vec3
+    ) -> OrthographicCamera {
+        OrthographicCamera {
+            frame: CameraFrame::new(look_from, look_at, vup),
+            view_width: view_height * aspect_ratio,
+            view_height,
+        }
+    }
+}
+
+impl CameraModel for OrthographicCamera {
+    fn generate_ray(&self, film: (Float, Float), _lens: (Float, Float)) -> Option<Ray> {
+        let x = (film.0 - 0.5) * self.view_width;
+        let y = (0.5 - film.1) * self.view_height;
+        let origin = self.frame.origin + x * self.frame.u + y * self.frame.v;
+        return Some(Ray::new(origin, -self.frame.w));
+    }
+}
+
+// a full 360 x 180 degree panorama, longitude runs across the image and latitude down it,
+// with the look_at direction in the middle of the image
+pub struct EquirectangularCamera {
+    frame: CameraFrame,
+}
+
+impl EquirectangularCamera {
+    pub fn new(look_from: Point3D, look_at: Point3D, vup: Vec3) -> EquirectangularCamera {
+        EquirectangularCamera { frame: CameraFrame::new(look_from, look_at, vup) }
+    }
+}
+
+impl CameraModel for EquirectangularCamera {
+    fn generate_ray(&self, film: (Float, Float), _lens: (Float, Float)) -> Option<Ray> {
+        let phi = (film.0 - 0.5) * 2.0 * PI;
+        let theta = (0.5 - film.1) * PI;
+        let direction = Vec3::new(
+            Float::cos(theta) * Float::sin(phi),
+            Float::sin(theta),
+            -Float::cos(theta) * Float::cos(phi)
+        );
+        return Some(Ray::new(self.frame.origin, self.frame.to_world(direction)));
+    }
+}
+
+// an equidistant fisheye: the angle from the view direction grows linearly with the distance from
+// the image center, fov is the angle across the image circle, which fits inside the shorter side
+pub struct FisheyeCamera {
+    frame: CameraFrame,
+    aspect_ratio: Float,
+    fov: Float,
+}
+
+impl FisheyeCamera {
+    pub fn new(aspect_ratio: Float, fov: Float, look_from: Point3D, look_at: Point3D, vup: Vec3) -> FisheyeCamera {
+        FisheyeCamera {
+            frame: CameraFrame::new(look_from, look_at, vup),
+            aspect_ratio,
+            fov: Float::to_radians(fov),
+        }
+    }
+}
+
+impl CameraModel for FisheyeCamera {
+    fn generate_ray(&self, film: (Float, Float), _lens: (Float, Float)) -> Option<Ray> {
+        // position relative to the image center, scaled so the image circle has radius 1
+        let (x, y) = if self.aspect_ratio >= 1.0 {
+            ((2.0 * film.0 - 1.0) * self.aspect_ratio, 1.0 - 2.0 * film.1)
+        } else {
+            (2.0 * film.0 - 1.0, (1.0 - 2.0 * film.1) / self.aspect_ratio)
+        };
+        let r = Float::sqrt(x * x + y * y);
+        if r > 1.0 {
+            return None;
+        }
+        let theta = r * self.fov / 2.0;
+        let phi = Float::atan2(y, x);
+        let direction = Vec3::new(
+            Float::sin(theta) * Float::cos(phi),
+            Float::sin(theta) * Float::sin(phi),
+            -Float::cos(theta)
+        );
+        return Some(Ray::new(self.frame.origin, self.frame.to_world(direction)));
+    }
+}
+
+#[cfg(test)]
+use crate::vec3::Dot;
+
+#[cfg(test)]
+fn assert_direction(ray: &Ray, expected: Vec3) {
+    let d = ray.direction().unit_vec();
+    assert!(d.dot(expected.unit_vec()) > 0.9999, "{:?} != {:?}", d, expected);
+}
+
+#[test]
+fn test_thin_lens_center_ray() {
+    let camera = ThinLensCamera::new(
+        2.0,
+        90.0,
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        1.0
+    );
+    let ray = camera.generate_ray((0.5, 0.5), (0.5, 0.5)).unwrap();
+    assert_direction(&ray, Vec3::new(0.0, 0.0, -1.0));
+    // a 90 degree vertical fov reaches 45 degrees up at the top edge
+    let ray = camera.generate_ray((0.5, 0.0), (0.5, 0.5)).unwrap();
+    assert_direction(&ray, Vec3::new(0.0, 1.0, -1.0));
+    // and twice as far sideways at the right edge
+    let ray = camera.generate_ray((1.0, 0.5), (0.5, 0.5)).unwrap();
+    assert_direction(&ray, Vec3::new(2.0, 0.0, -1.0));
+}
+
+#[test]
+fn test_orthographic_rays_are_parallel() {
+    let camera = OrthographicCamera::new(
+        1.0,
+        4.0,
+        Point3D::new(0.0, 0.0, 5.0),
+        Point3D::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0)
+    );
+    let corner = camera.generate_ray((0.0, 0.0), (0.5, 0.5)).unwrap();
+    let center = camera.generate_ray((0.5, 0.5), (0.5, 0.5)).unwrap();
+    assert_direction(&corner, Vec3::new(0.0, 0.0, -1.0));
+    assert_direction(&center, Vec3::new(0.0, 0.0, -1.0));
+    assert_eq!(corner.origin().x(), -2.0);
+    assert_eq!(corner.origin().y(), 2.0);
+}
+
+#[test]
+fn test_equirectangular_directions() {
+    let camera = EquirectangularCamera::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0)
+    );
+    assert_direction(&camera.generate_ray((0.5, 0.5), (0.5, 0.5)).unwrap(), Vec3::new(0.0, 0.0, -1.0));
+    assert_direction(&camera.generate_ray((0.75, 0.5), (0.5, 0.5)).unwrap(), Vec3::new(1.0, 0.0, 0.0));
+    assert_direction(&camera.generate_ray((0.0, 0.5), (0.5, 0.5)).unwrap(), Vec3::new(0.0, 0.0, 1.0));
+    assert_direction(&camera.generate_ray((0.5, 0.0), (0.5, 0.5)).unwrap(), Vec3::new(0.0, 1.0, 0.0));
+}
+
+#[test]
+fn test_fisheye_circle() {
+    let camera = FisheyeCamera::new(
+        2.0,
+        180.0,
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0)
+    );
+    assert_direction(&camera.generate_ray((0.5, 0.5), (0.5, 0.5)).unwrap(), Vec3::new(0.0, 0.0, -1.0));
+    // the top edge of the circle looks 90 degrees up
+    assert_direction(&camera.generate_ray((0.5, 0.0), (0.5, 0.5)).unwrap(), Vec3::new(0.0, 1.0, 0.0));
+    // the corners of a wide image are outside the circle
+    assert!(camera.generate_ray((0.0, 0.0), (0.5, 0.5)).is_none());
+}
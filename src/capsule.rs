@@ -0,0 +1,176 @@
+use std::io::{ self, Error, ErrorKind };
+
+use crate::{
+    point3d::Point3D,
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    polynomial::quadratic_roots,
+    sweep::Sweep,
+    aabb::Aabb,
+    ray::Ray,
+    vec3::{ Vec3, Dot },
+    float::{ Float, consts, gamma },
+};
+
+// A cylinder of radius with a half sphere on each end, standing on the y axis with the middle of
+// its bottom end's sphere at center and its top end's sphere height above that. It can be swept only
+// part of the way round, which leaves it open along the cut. Other orientations come from an Instance.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Capsule {
+    center: Point3D,
+    radius: Float,
+    height: Float,
+    sweep: Sweep,
+    // index into the world's materials
+    material: usize,
+}
+
+// which part of the capsule a root of the ray is on
+#[derive(Clone, Copy)]
+enum Part {
+    Side,
+    Bottom,
+    Top,
+}
+
+impl Capsule {
+    // fails unless radius and height are more than 0 and finite
+    pub fn new(center: Point3D, radius: Float, height: Float, material: usize) -> io::Result<Capsule> {
+        if !(radius > 0.0 && radius.is_finite() && height > 0.0 && height.is_finite()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "a capsule's radius and height must be more than 0 and finite"));
+        }
+        return Ok(Capsule { center, radius, height, sweep: Sweep::FULL, material });
+    }
+    pub fn with_sweep(self, sweep: Sweep) -> Capsule {
+        Capsule { sweep, ..self }
+    }
+    // the roots of the ray o + t d with the sphere of the capsule's radius height up the axis
+    fn end_roots(&self, o: Vec3, d: Vec3, height: Float) -> Option<(Float, Float)> {
+        let oc = o - Vec3::new(0.0, height, 0.0);
+        let a = d.length_squared();
+        let half_b = oc.dot(d);
+        let closest_point = oc - (half_b / a) * d;
+        let discriminant = a * (self.radius * self.radius - closest_point.length_squared());
+        return quadratic_roots(a, half_b, oc.length_squared() - self.radius * self.radius, discriminant);
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let o = ray.origin() - self.center;
+        let d = ray.direction();
+        let radius_squared = self.radius * self.radius;
+
+        // Every root of the side and both end spheres, each kept only if it lies on the part of that
+        // surface that is on the outside of the capsule. Both roots of each matter: a ray can enter
+        // an end's sphere inside the capsule and leave it through the capsule's surface.
+        let mut roots = [None; 3];
+        let a = d.x() * d.x() + d.z() * d.z();
+        let half_b = o.x() * d.x() + o.z() * d.z();
+        let (closest_x, closest_z) = (o.x() - (half_b / a) * d.x(), o.z() - (half_b / a) * d.z());
+        let discriminant = a * (radius_squared - (closest_x * closest_x + closest_z * closest_z));
+        roots[0] = quadratic_roots(a, half_b, o.x() * o.x() + o.z() * o.z() - radius_squared, discriminant).filter(|_| a > 0.0);
+        roots[1] = self.end_roots(o, d, 0.0);
+        roots[2] = self.end_roots(o, d, self.height);
+
+        let mut closest: Option<(Float, Part, Float)> = None;
+        for (roots, part) in roots.iter().zip([Part::Side, Part::Bottom, Part::Top]) {
+            let Some((near, far)) = roots else {
+                continue;
+            };
+            for t in [*near, *far] {
+                let local = o + t * d;
+                let on_part = match part {
+                    Part::Side => 0.0 <= local.y() && local.y() <= self.height,
+                    Part::Bottom => local.y() < 0.0,
+                    Part::Top => local.y() > self.height,
+                };
+                let closest_so_far = closest.map_or(ray_t.max, |(t, _, _)| t);
+                if !on_part || !Interval::new(ray_t.min, closest_so_far).surrounds(t) {
+                    continue;
+                }
+                // moving the hit onto the surface below only moves it out from the axis, so its
+                // angle round the axis is already the final one
+                if let Some(u) = self.sweep.u(local) {
+                    closest = Some((t, part, u));
+                    break;
+                }
+            }
+        }
+        let (t, part, u) = closest?;
+
+        // move the hit onto the surface, the side out from the axis and the ends out from their middles
+        let local = o + t * d;
+        let middle = match part {
+            Part::Side => Vec3::new(0.0, local.y(), 0.0),
+            Part::Bottom => Vec3::new(0.0, 0.0, 0.0),
+            Part::Top => Vec3::new(0.0, self.height, 0.0),
+        };
+        let outward_normal = (local - middle) / (local - middle).length();
+        let local = middle + self.radius * outward_normal;
+        // v is how far along the surface from the bottom to the top the hit is, over the ends and up the side
+        let quarter = consts::FRAC_PI_2 * self.radius;
+        let along = match part {
+            Part::Bottom => self.radius * Float::acos(-outward_normal.y().clamp(-1.0, 1.0)),
+            Part::Side => quarter + local.y(),
+            Part::Top => quarter + self.height + self.radius * Float::asin(outward_normal.y().clamp(-1.0, 1.0)),
+        };
+        let v = along / (2.0 * quarter + self.height);
+
+        let p = self.center + local;
+        let mut hit = HitRecord::new(p, outward_normal, t, u, v, false, self.material);
+        hit.set_face_normal(ray, &outward_normal);
+        hit.error = gamma(6) * (local.abs() + p.abs());
+        return Some(hit);
+    }
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius;
+        return Aabb::new(self.center - Vec3::splat(r), self.center + Vec3::new(r, self.height + r, r));
+    }
+}
+
+#[test]
+fn test_capsule_hit() {
+    let capsule = Capsule::new(Point3D::new(0.0, -1.0, -5.0), 1.0, 2.0, 6).unwrap();
+    let ray_t = Interval::new(0.0, Float::INFINITY);
+    assert_eq!(capsule.bounding_box(), Aabb::new(Point3D::new(-1.0, -2.0, -6.0), Point3D::new(1.0, 2.0, -4.0)));
+    // the whole surface from pole to pole is pi + 2 long, with the middle of the side halfway
+    let side = capsule.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert_eq!((side.t, side.normal, side.front_face, side.v), (4.0, Vec3::new(0.0, 0.0, 1.0), true, 0.5));
+    assert_eq!(side.material, 6);
+    // down onto the top pole and up into the bottom one
+    let top = capsule.hit(&Ray::new(Point3D::new(0.0, 10.0, -5.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).unwrap();
+    assert_eq!((top.t, top.normal, top.v), (8.0, Vec3::new(0.0, 1.0, 0.0), 1.0));
+    let bottom = capsule.hit(&Ray::new(Point3D::new(0.0, -10.0, -5.0), Vec3::new(0.0, 1.0, 0.0)), &ray_t).unwrap();
+    assert_eq!((bottom.t, bottom.normal, bottom.v), (8.0, Vec3::new(0.0, -1.0, 0.0), 0.0));
+    // on the rounded part of the top end, and missing just past it
+    let shoulder = capsule.hit(&Ray::new(Point3D::new(0.6, 10.0, -5.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).unwrap();
+    assert!((shoulder.t - 8.2).abs() < 1e-5);
+    assert!((shoulder.normal - Vec3::new(0.6, 0.8, 0.0)).length() < 1e-5);
+    assert!(capsule.hit(&Ray::new(Point3D::new(1.1, 10.0, -5.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).is_none());
+    // from inside, through the side and down out of the bottom end, past where the side would be
+    let inside = capsule.hit(&Ray::new(Point3D::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert_eq!((inside.t, inside.front_face), (1.0, false));
+    let down = Ray::new(Point3D::new(0.6, -0.5, -5.0), Vec3::new(0.0, -1.0, 0.0));
+    let out = capsule.hit(&down, &ray_t).unwrap();
+    assert!((out.t - 1.3).abs() < 1e-5);
+    assert!(!out.front_face);
+
+    // with only the -z half, the ray passes the missing near side and hits the inside of the far one
+    let half = capsule.with_sweep(Sweep::new(consts::PI).unwrap());
+    let far = half.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert_eq!((far.t, far.front_face, far.u), (6.0, false, 0.5));
+    // and the same on the ends, through the cut in the top and out of the far side of the bottom
+    let end = half.hit(&Ray::new(Point3D::new(0.0, 1.6, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert!((end.t - 5.8).abs() < 1e-5);
+    assert!(!end.front_face);
+    assert!((end.u - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_capsule_sizes() {
+    let center = Point3D::new(0.0, 0.0, 0.0);
+    for (radius, height) in [(0.0, 1.0), (-1.0, 1.0), (Float::NAN, 1.0), (Float::INFINITY, 1.0), (1.0, 0.0), (1.0, -1.0), (1.0, Float::NAN)] {
+        assert_eq!(Capsule::new(center, radius, height, 0).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{ fs::{ self, File }, hash::Hasher, io::{ self, BufReader, BufWriter, Error, ErrorKind, Read, Write } };
+
+use crate::framebuffer::FrameBuffer;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 1;
+// the magic, version and the two hashes before the framebuffer
+const HEADER_LEN: u64 = 24;
+
+// A checkpoint is the raw framebuffer of an unfinished render together with hashes of what was being
+// rendered and how, so that it is only ever resumed by a render that would have produced the same
+// samples. The file is written next to its final path and then renamed over it, so a crash while
+// saving leaves the previous checkpoint intact.
+pub fn save_checkpoint(path: &str, scene_hash: u64, settings_hash: u64, framebuffer: &FrameBuffer) -> io::Result<()> {
+    let temporary_path = format!("{}.partial", path);
+    {
+        let mut file = BufWriter::new(File::create(&temporary_path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&scene_hash.to_le_bytes())?;
+        file.write_all(&settings_hash.to_le_bytes())?;
+        framebuffer.write_raw(&mut file)?;
+        file.flush()?;
+    }
+    return fs::rename(&temporary_path, path);
+}
+
+pub fn load_checkpoint(path: &str, scene_hash: u64, settings_hash: u64) -> io::Result<FrameBuffer> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut file = BufReader::new(file);
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)?;
+    let mut version = [0u8; 4];
+    file.read_exact(&mut version)?;
+    if &magic != MAGIC || u32::from_le_bytes(version) != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} is not a checkpoint this version can read", path)));
+    }
+    let mut hash = [0u8; 8];
+    file.read_exact(&mut hash)?;
+    if u64::from_le_bytes(hash) != scene_hash {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} was saved while rendering a different scene", path)));
+    }
+    file.read_exact(&mut hash)?;
+    if u64::from_le_bytes(hash) != settings_hash {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} was saved with different render settings", path)));
+    }
+    // the framebuffer is the rest of the file, so its size can't claim more than that
+    return FrameBuffer::read_raw(&mut file, len.saturating_sub(HEADER_LEN));
+}
+
+// 64 bit fnv-1a, a tiny hash whose output is the same on every platform and every run (unlike the
+// standard library's randomly keyed hasher), so the scene and settings hashes stored in checkpoints
+// can be compared by a later run
+pub struct FnvHasher(u64);
+
+impl FnvHasher {
+    pub fn new() -> FnvHasher {
+        FnvHasher(0xcbf29ce484222325)
+    }
+}
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        return FnvHasher::new();
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+    fn finish(&self) -> u64 {
+        return self.0;
+    }
+}
+
+#[cfg(test)]
+use crate::color::Color;
+
+#[test]
+fn test_fnv_hash() {
+    // reference values of 64 bit fnv-1a
+    let hash = |bytes: &[u8]| {
+        let mut hasher = FnvHasher::new();
+        hasher.write(bytes);
+        hasher.finish()
+    };
+    assert_eq!(hash(b""), 0xcbf29ce484222325);
+    assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+}
+
+#[test]
+fn test_checkpoint_round_trip_and_mismatch() {
+    let path = std::env::temp_dir().join(format!("checkpoint_test_{}.ckpt", std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    let mut framebuffer = FrameBuffer::new(2, 2);
+    framebuffer.add_sample(1, 1, Color::new(0.5, 0.25, 1.0));
+    save_checkpoint(&path, 11, 22, &framebuffer).unwrap();
+
+    let loaded = load_checkpoint(&path, 11, 22).unwrap();
+    assert_eq!(loaded.sample_count(1, 1), 1);
+    assert_eq!(loaded.pixel(1, 1), framebuffer.pixel(1, 1));
+    assert!(matches!(load_checkpoint(&path, 12, 22), Err(e) if e.kind() == ErrorKind::InvalidData));
+    assert!(matches!(load_checkpoint(&path, 11, 23), Err(e) if e.kind() == ErrorKind::InvalidData));
+
+    // a checkpoint cut short claims a framebuffer bigger than what is left of the file
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(bytes.len() as u64, HEADER_LEN + FrameBuffer::raw_len(2, 2).unwrap());
+    fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(matches!(load_checkpoint(&path, 11, 22), Err(e) if e.kind() == ErrorKind::InvalidData));
+    fs::remove_file(&path).unwrap();
+}
@@ -1,19 +1,19 @@
 use std::ops;
 use rand::random;
 
-use crate::vec3::Vec3;
+use crate::{ vec3::Vec3, float::Float };
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color(Vec3);
 
 impl Color {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
+    pub fn new(x: Float, y: Float, z: Float) -> Self {
         Color(Vec3::new(x, y, z))
     }
     pub fn random() -> Color {
-        return Color(Vec3::new(random::<f32>(), random::<f32>(), random::<f32>()));
+        return Color(Vec3::new(random::<Float>(), random::<Float>(), random::<Float>()));
     }
-    pub fn random_in_range(min: f32, max: f32) -> Color {
+    pub fn random_in_range(min: Float, max: Float) -> Color {
         return Color(Vec3::random_in_range(min, max));
     }
     pub fn as_i32(&self) -> [i32; 3] {
@@ -23,8 +23,19 @@ impl Color {
             (255.999 * self.z()) as i32,
         ];
     }
-    pub fn linear_to_gamma(lc: f32) -> f32 {
-        return f32::sqrt(lc);
+    pub fn linear_to_gamma(lc: Float) -> Float {
+        return Float::sqrt(lc);
+    }
+    // the exact piecewise srgb transfer function, with a linear toe near black
+    pub fn linear_to_srgb(lc: Float) -> Float {
+        if lc <= 0.0031308 {
+            return 12.92 * lc;
+        }
+        return 1.055 * Float::powf(lc, 1.0 / 2.4) - 0.055;
+    }
+    // relative luminance of a linear rec.709/srgb color
+    pub fn luminance(&self) -> Float {
+        return 0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z();
     }
 }
 
@@ -38,6 +49,21 @@ impl ops::Deref for Color {
     }
 }
 
+#[test]
+fn test_linear_to_srgb() {
+    assert_eq!(Color::linear_to_srgb(0.0), 0.0);
+    assert!((Color::linear_to_srgb(0.001) - 0.01292).abs() < 1e-7);
+    assert!((Color::linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+    assert!((Color::linear_to_srgb(0.18) - 0.4613).abs() < 1e-3);
+}
+
+#[test]
+fn test_luminance() {
+    assert_eq!(Color::new(0.0, 0.0, 0.0).luminance(), 0.0);
+    assert!((Color::new(1.0, 1.0, 1.0).luminance() - 1.0).abs() < 1e-6);
+    assert!(Color::new(0.0, 1.0, 0.0).luminance() > Color::new(1.0, 0.0, 0.0).luminance());
+}
+
 impl ops::Mul<Color> for Color {
     type Output = Color;
 
@@ -55,10 +81,10 @@ fn test_color_mul() {
     assert_eq!(c.z(), 2.0);
 }
 
-impl ops::Mul<f32> for Color {
+impl ops::Mul<Float> for Color {
     type Output = Color;
 
-    fn mul(self, rhs: f32) -> Self::Output {
+    fn mul(self, rhs: Float) -> Self::Output {
         Color(self.0 * rhs)
     }
 }
@@ -72,7 +98,7 @@ fn test_f32_rhs_mul() {
     assert_eq!(c.z(), 4.0);
 }
 
-impl ops::Mul<Color> for f32 {
+impl ops::Mul<Color> for Float {
     type Output = Color;
 
     fn mul(self, rhs: Color) -> Self::Output {
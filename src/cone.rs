@@ -0,0 +1,163 @@
+use std::io::{ self, Error, ErrorKind };
+
+use crate::{
+    point3d::Point3D,
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    polynomial::quadratic_roots,
+    sweep::Sweep,
+    aabb::Aabb,
+    ray::Ray,
+    vec3::{ Vec3, UnitVec },
+    float::{ Float, gamma },
+};
+
+// A cone standing on the y axis, with its base of radius around center and its tip height above.
+// The base is closed with a disk unless made without a cap, and the cone can be swept only part of
+// the way round. Other orientations come from an Instance.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Cone {
+    center: Point3D,
+    radius: Float,
+    height: Float,
+    sweep: Sweep,
+    capped: bool,
+    // index into the world's materials
+    material: usize,
+}
+
+impl Cone {
+    // fails unless radius and height are more than 0 and finite
+    pub fn new(center: Point3D, radius: Float, height: Float, material: usize) -> io::Result<Cone> {
+        if !(radius > 0.0 && radius.is_finite() && height > 0.0 && height.is_finite()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "a cone's radius and height must be more than 0 and finite"));
+        }
+        return Ok(Cone { center, radius, height, sweep: Sweep::FULL, capped: true, material });
+    }
+    pub fn with_sweep(self, sweep: Sweep) -> Cone {
+        Cone { sweep, ..self }
+    }
+    // without the disk closing the base
+    pub fn without_cap(self) -> Cone {
+        Cone { capped: false, ..self }
+    }
+
+    // u goes around the cone and v up the side, or out from the middle of the base
+    fn record(&self, ray: &Ray, t: Float, local: Vec3, outward_normal: Vec3, u: Float, v: Float) -> HitRecord {
+        let p = self.center + local;
+        let mut hit = HitRecord::new(p, outward_normal, t, u, v, false, self.material);
+        hit.set_face_normal(ray, &outward_normal);
+        hit.error = gamma(7) * (local.abs() + p.abs());
+        return hit;
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let o = ray.origin() - self.center;
+        let d = ray.direction();
+        let mut closest_so_far = ray_t.max;
+        let mut hit_record = None;
+
+        // The side is where x^2 + z^2 = (k (height - y))^2 with k the slope radius / height, which
+        // also takes in the upside down cone above the tip that the height check below throws out.
+        let k = self.radius / self.height;
+        let below_tip = self.height - o.y();
+        let a = d.x() * d.x() + d.z() * d.z() - k * k * d.y() * d.y();
+        let half_b = o.x() * d.x() + o.z() * d.z() + k * k * below_tip * d.y();
+        let c = o.x() * o.x() + o.z() * o.z() - k * k * below_tip * below_tip;
+        // half_b^2 - a c subtracts two large, nearly equal numbers for rays from far away. Written
+        // out it is a sum of squared cross products of the ray's origin and direction instead, the
+        // way the sphere's comes from the ray's closest approach, and those stay small.
+        let across = o.x() * d.z() - o.z() * d.x();
+        let (along_x, along_z) = (o.x() * d.y() + below_tip * d.x(), o.z() * d.y() + below_tip * d.z());
+        let discriminant = k * k * (along_x * along_x + along_z * along_z) - across * across;
+        if let Some((near, far)) = quadratic_roots(a, half_b, c, discriminant) {
+            for t in [near, far] {
+                let local = o + t * d;
+                if !ray_t.surrounds(t) || local.y() < 0.0 || local.y() > self.height {
+                    continue;
+                }
+                let Some(u) = self.sweep.u(local) else {
+                    continue;
+                };
+                // move the hit onto the side, out from the axis to the cone's radius at its height
+                let distance = Float::sqrt(local.x() * local.x() + local.z() * local.z());
+                let (radial_x, radial_z) = if distance > 0.0 { (local.x() / distance, local.z() / distance) } else { (0.0, 0.0) };
+                let cone_radius = self.radius * (1.0 - local.y() / self.height);
+                let local = Vec3::new(radial_x * cone_radius, local.y(), radial_z * cone_radius);
+                let outward_normal = Vec3::new(radial_x, self.radius / self.height, radial_z).unit_vec();
+                hit_record = Some(self.record(ray, t, local, outward_normal, u, local.y() / self.height));
+                closest_so_far = t;
+                break;
+            }
+        }
+
+        if self.capped && d.y() != 0.0 {
+            let t = -o.y() / d.y();
+            let local = o + t * d;
+            let local = Vec3::new(local.x(), 0.0, local.z());
+            let distance_squared = local.x() * local.x() + local.z() * local.z();
+            if Interval::new(ray_t.min, closest_so_far).surrounds(t) && distance_squared <= self.radius * self.radius {
+                if let Some(u) = self.sweep.u(local) {
+                    let v = Float::sqrt(distance_squared) / self.radius;
+                    hit_record = Some(self.record(ray, t, local, Vec3::new(0.0, -1.0, 0.0), u, v));
+                }
+            }
+        }
+        return hit_record;
+    }
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius;
+        return Aabb::new(self.center + Vec3::new(-r, 0.0, -r), self.center + Vec3::new(r, self.height, r));
+    }
+}
+
+#[cfg(test)]
+use crate::vec3::Dot;
+
+#[test]
+fn test_cone_hit() {
+    // a cone with a 45 degree side, tip at y = 1
+    let cone = Cone::new(Point3D::new(0.0, -1.0, -5.0), 2.0, 2.0, 1).unwrap();
+    let ray_t = Interval::new(0.0, Float::INFINITY);
+    assert_eq!(cone.bounding_box(), Aabb::new(Point3D::new(-2.0, -1.0, -7.0), Point3D::new(2.0, 1.0, -3.0)));
+
+    // halfway up the side the cone's radius is 1
+    let hit = cone.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert!((hit.t - 4.0).abs() < 1e-6);
+    assert!((hit.normal - Vec3::new(0.0, 1.0, 1.0).unit_vec()).length() < 1e-6);
+    assert!(hit.front_face);
+    assert!((hit.u - 0.75).abs() < 1e-6 && (hit.v - 0.5).abs() < 1e-6);
+    // straight down onto the tip, and up into the base
+    let tip = cone.hit(&Ray::new(Point3D::new(0.0, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).unwrap();
+    assert!((tip.t - 4.0).abs() < 1e-5);
+    let base = cone.hit(&Ray::new(Point3D::new(0.5, -5.0, -5.0), Vec3::new(0.0, 1.0, 0.0)), &ray_t).unwrap();
+    assert_eq!((base.t, base.normal, base.front_face, base.v), (4.0, Vec3::new(0.0, -1.0, 0.0), true, 0.25));
+    // without the base the ray goes in and hits the inside of the side
+    let open = cone.without_cap().hit(&Ray::new(Point3D::new(0.5, -5.0, -5.0), Vec3::new(0.0, 1.0, 0.0)), &ray_t).unwrap();
+    assert!((open.t - 5.5).abs() < 1e-5);
+    assert!(!open.front_face);
+    assert!(open.normal.dot(Vec3::new(0.0, 1.0, 0.0)) < 0.0);
+    // past the tip, where the mirrored cone would be
+    assert!(cone.hit(&Ray::new(Point3D::new(0.0, 2.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).is_none());
+
+    // from far away the side is where it is from close up, across the whole width of the cone
+    for i in 0..=20 {
+        let x = (i as Float) * 0.1 - 1.0;
+        let near = cone.hit(&Ray::new(Point3D::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t);
+        let far = cone.hit(&Ray::new(Point3D::new(x, 0.0, 1e4), Vec3::new(0.0, 0.0, -1.0)), &ray_t);
+        assert_eq!(near.is_some(), far.is_some(), "{}", x);
+        if let (Some(near), Some(far)) = (near, far) {
+            assert!((far.t - 1e4 - near.t).abs() < 2e-3, "{} {} {}", x, near.t, far.t);
+        }
+    }
+}
+
+#[test]
+fn test_cone_sizes() {
+    let center = Point3D::new(0.0, 0.0, 0.0);
+    for (radius, height) in [(0.0, 1.0), (-1.0, 1.0), (Float::NAN, 1.0), (Float::INFINITY, 1.0), (1.0, 0.0), (1.0, -1.0), (1.0, Float::NAN)] {
+        assert_eq!(Cone::new(center, radius, height, 0).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
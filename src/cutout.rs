@@ -0,0 +1,142 @@
+use crate::{
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    ray::Ray,
+    texture::{ Texture, SurfaceTexture },
+    primitive::Primitive,
+    aabb::Aabb,
+    color::Color,
+    sampler::mix_bits,
+    float::{ Float, to_f32 },
+};
+
+// decides whether a hit on a partially transparent surface counts
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AlphaMode {
+    // the surface is solid wherever alpha is at least the threshold and invisible elsewhere
+    Threshold(Float),
+    // the surface is hit with probability alpha, so soft edges average out over many samples. The
+    // random number comes from hashing the ray and the hit's distance along it, as pbrt does, so
+    // the same ray makes the same decisions whichever tile, worker or resumed render traces it,
+    // and every layer a ray passes through gets a number of its own.
+    Stochastic,
+}
+
+// wraps any hittable with an opacity texture so leaf cards, fences and decals can be cut out of
+// simple geometry, alpha is read as the average of the texture's channels so grayscale masks work as-is
+#[derive(Clone, Debug)]
+pub struct Cutout {
+    object: Box<Primitive>,
+    opacity: Texture,
+    mode: AlphaMode,
+}
+
+impl Cutout {
+    pub fn new(object: impl Into<Primitive>, opacity: Texture, mode: AlphaMode) -> Cutout {
+        Cutout { object: Box::new(object.into()), opacity, mode }
+    }
+    pub fn alpha(color: Color) -> Float {
+        return (color.x() + color.y() + color.z()) / 3.0;
+    }
+    fn is_opaque(&self, ray: &Ray, hit: &HitRecord) -> bool {
+        let alpha = Cutout::alpha(self.opacity.value(hit.u, hit.v, &hit.p));
+        return match self.mode {
+            AlphaMode::Threshold(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => alpha > Cutout::hash_float(ray, hit.t),
+        };
+    }
+    // a number in [0, 1) that only depends on the ray and t, the same with and without the f64 feature
+    fn hash_float(ray: &Ray, t: Float) -> Float {
+        let (o, d) = (ray.origin(), ray.direction());
+        let hash = [o.x(), o.y(), o.z(), d.x(), d.y(), d.z(), t]
+            .iter()
+            .fold(0, |hash, value| mix_bits(hash ^ u64::from(to_f32(*value).to_bits())));
+        // the top 24 bits, which f32 holds exactly
+        return ((hash >> 40) as Float) / ((1u64 << 24) as Float);
+    }
+}
+
+impl Hittable for Cutout {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        // when a hit lands on a transparent texel, keep searching the same object past it
+        // so the ray can still find the far side of the surface (e.g. the back of a sphere)
+        let mut t_min = ray_t.min;
+        while let Some(hit) = self.object.hit(ray, &Interval::new(t_min, ray_t.max)) {
+            if self.is_opaque(ray, &hit) {
+                return Some(hit);
+            }
+            t_min = hit.t;
+        }
+        return None;
+    }
+    fn bounding_box(&self) -> Aabb {
+        return self.object.bounding_box();
+    }
+    fn object_count(&self) -> usize {
+        return self.object.object_count();
+    }
+}
+
+#[cfg(test)]
+use crate::{
+    sphere::Sphere,
+    point3d::Point3D,
+    vec3::Vec3,
+    texture::{ SolidColor, StripeTexture },
+};
+
+#[test]
+fn test_cutout_opaque_passes_through_hits() {
+    let sphere = Sphere::new(Point3D::new(0.0, 0.0, -2.0), 1.0, 0);
+    let opacity = Texture::Solid(SolidColor::new(Color::new(1.0, 1.0, 1.0)));
+    let cutout = Cutout::new(sphere, opacity, AlphaMode::Threshold(0.5));
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+    let hit = cutout.hit(&ray, &Interval::new(0.001, Float::INFINITY)).unwrap();
+    assert_eq!(hit.t, 1.0);
+}
+
+#[test]
+fn test_cutout_transparent_misses() {
+    let sphere = Sphere::new(Point3D::new(0.0, 0.0, -2.0), 1.0, 0);
+    let opacity = Texture::Solid(SolidColor::new(Color::new(0.0, 0.0, 0.0)));
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+    let threshold = Cutout::new(sphere, opacity.clone(), AlphaMode::Threshold(0.5));
+    assert!(threshold.hit(&ray, &Interval::new(0.001, Float::INFINITY)).is_none());
+    let stochastic = Cutout::new(sphere, opacity, AlphaMode::Stochastic);
+    assert!(stochastic.hit(&ray, &Interval::new(0.001, Float::INFINITY)).is_none());
+}
+
+#[test]
+fn test_cutout_continues_past_transparent_hit() {
+    // a mask that is opaque on the lower half of the sphere (v < 0.5) and transparent on the upper half
+    let sphere = Sphere::new(Point3D::new(0.0, 0.0, -2.0), 1.0, 0);
+    let opacity = Texture::Stripe(
+        StripeTexture::new(Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0), 1.0, 0.5)
+    );
+    let cutout = Cutout::new(sphere, opacity, AlphaMode::Threshold(0.5));
+    // enters the sphere through the upper half (transparent) and exits through the lower half (opaque)
+    let ray = Ray::new(Point3D::new(0.0, 0.6, 0.0), Vec3::new(0.0, -1.0, -2.0));
+    let hit = cutout.hit(&ray, &Interval::new(0.001, Float::INFINITY)).unwrap();
+    assert!(hit.p.y() < 0.0);
+    assert!(!hit.front_face);
+}
+
+#[test]
+fn test_cutout_stochastic_is_reproducible() {
+    let sphere = Sphere::new(Point3D::new(0.0, 0.0, -2.0), 1.0, 0);
+    let opacity = Texture::Solid(SolidColor::new(Color::new(0.5, 0.5, 0.5)));
+    let cutout = Cutout::new(sphere, opacity, AlphaMode::Stochastic);
+    let ray_t = Interval::new(0.001, Float::INFINITY);
+    let mut front = 0;
+    for i in 0..1000 {
+        let ray = Ray::new(Point3D::new((i as Float) * 1e-4, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = cutout.hit(&ray, &ray_t);
+        // the same ray always gets the same answer
+        assert_eq!(hit, cutout.hit(&ray, &ray_t));
+        // half the rays stop at the front, a quarter at the back and a quarter pass through
+        if hit.is_some_and(|hit| hit.front_face) {
+            front += 1;
+        }
+    }
+    assert!((400..600).contains(&front), "{}", front);
+}
@@ -0,0 +1,155 @@
+use std::io::{ self, Error, ErrorKind };
+
+use crate::{
+    point3d::Point3D,
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    polynomial::quadratic_roots,
+    sweep::Sweep,
+    aabb::Aabb,
+    ray::Ray,
+    vec3::Vec3,
+    float::{ Float, gamma },
+};
+
+// A cylinder standing on the y axis, from center at the middle of its bottom up to height. It is
+// closed with a disk at each end unless made without caps, and can be swept only part of the way
+// round. Other orientations come from an Instance.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Cylinder {
+    center: Point3D,
+    radius: Float,
+    height: Float,
+    sweep: Sweep,
+    capped: bool,
+    // index into the world's materials
+    material: usize,
+}
+
+impl Cylinder {
+    // fails unless radius and height are more than 0 and finite
+    pub fn new(center: Point3D, radius: Float, height: Float, material: usize) -> io::Result<Cylinder> {
+        if !(radius > 0.0 && radius.is_finite() && height > 0.0 && height.is_finite()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "a cylinder's radius and height must be more than 0 and finite"));
+        }
+        return Ok(Cylinder { center, radius, height, sweep: Sweep::FULL, capped: true, material });
+    }
+    pub fn with_sweep(self, sweep: Sweep) -> Cylinder {
+        Cylinder { sweep, ..self }
+    }
+    // an open tube
+    pub fn without_caps(self) -> Cylinder {
+        Cylinder { capped: false, ..self }
+    }
+
+    // u goes around the cylinder and v up the side, or out from the middle of a cap
+    fn record(&self, ray: &Ray, t: Float, local: Vec3, outward_normal: Vec3, u: Float, v: Float) -> HitRecord {
+        let p = self.center + local;
+        let mut hit = HitRecord::new(p, outward_normal, t, u, v, false, self.material);
+        hit.set_face_normal(ray, &outward_normal);
+        hit.error = gamma(6) * (local.abs() + p.abs());
+        return hit;
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let o = ray.origin() - self.center;
+        let d = ray.direction();
+        let radius_squared = self.radius * self.radius;
+        let mut closest_so_far = ray_t.max;
+        let mut hit_record = None;
+
+        // the side, a circle in x and z like a sphere one dimension down, with its discriminant
+        // worked out the same way from the ray's closest approach to the axis
+        let a = d.x() * d.x() + d.z() * d.z();
+        let half_b = o.x() * d.x() + o.z() * d.z();
+        let c = o.x() * o.x() + o.z() * o.z() - radius_squared;
+        let (closest_x, closest_z) = (o.x() - (half_b / a) * d.x(), o.z() - (half_b / a) * d.z());
+        let discriminant = a * (radius_squared - (closest_x * closest_x + closest_z * closest_z));
+        // a ray along the axis never meets the side
+        if let Some((near, far)) = quadratic_roots(a, half_b, c, discriminant).filter(|_| a > 0.0) {
+            for t in [near, far] {
+                let local = o + t * d;
+                if !ray_t.surrounds(t) || local.y() < 0.0 || local.y() > self.height {
+                    continue;
+                }
+                let Some(u) = self.sweep.u(local) else {
+                    continue;
+                };
+                // move the hit onto the side
+                let scale = self.radius / Float::sqrt(local.x() * local.x() + local.z() * local.z());
+                let local = Vec3::new(local.x() * scale, local.y(), local.z() * scale);
+                let outward_normal = Vec3::new(local.x() / self.radius, 0.0, local.z() / self.radius);
+                hit_record = Some(self.record(ray, t, local, outward_normal, u, local.y() / self.height));
+                closest_so_far = t;
+                break;
+            }
+        }
+
+        if self.capped && d.y() != 0.0 {
+            for (y, outward_normal) in [(0.0, Vec3::new(0.0, -1.0, 0.0)), (self.height, Vec3::new(0.0, 1.0, 0.0))] {
+                let t = (y - o.y()) / d.y();
+                if !Interval::new(ray_t.min, closest_so_far).surrounds(t) {
+                    continue;
+                }
+                let local = o + t * d;
+                let local = Vec3::new(local.x(), y, local.z());
+                let distance_squared = local.x() * local.x() + local.z() * local.z();
+                if distance_squared > radius_squared {
+                    continue;
+                }
+                let Some(u) = self.sweep.u(local) else {
+                    continue;
+                };
+                let v = Float::sqrt(distance_squared) / self.radius;
+                hit_record = Some(self.record(ray, t, local, outward_normal, u, v));
+                closest_so_far = t;
+            }
+        }
+        return hit_record;
+    }
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius;
+        return Aabb::new(self.center + Vec3::new(-r, 0.0, -r), self.center + Vec3::new(r, self.height, r));
+    }
+}
+
+#[cfg(test)]
+use crate::float::consts;
+
+#[test]
+fn test_cylinder_hit() {
+    let cylinder = Cylinder::new(Point3D::new(0.0, -1.0, -5.0), 1.0, 2.0, 4).unwrap();
+    let ray_t = Interval::new(0.0, Float::INFINITY);
+    assert_eq!(cylinder.bounding_box(), Aabb::new(Point3D::new(-1.0, -1.0, -6.0), Point3D::new(1.0, 1.0, -4.0)));
+
+    // the side, facing the camera, halfway up and a quarter of the way round from +x
+    let hit = cylinder.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert_eq!((hit.t, hit.p, hit.normal, hit.front_face), (4.0, Point3D::new(0.0, 0.0, -4.0), Vec3::new(0.0, 0.0, 1.0), true));
+    assert_eq!((hit.u, hit.v, hit.material), (0.75, 0.5, 4));
+    // from inside, the far side faces back
+    let inside = cylinder.hit(&Ray::new(Point3D::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0)), &ray_t).unwrap();
+    assert_eq!((inside.t, inside.normal, inside.front_face), (1.0, Vec3::new(-1.0, 0.0, 0.0), false));
+    // down the axis onto the top cap, which an open tube doesn't have
+    let down = Ray::new(Point3D::new(0.5, 10.0, -5.0), Vec3::new(0.0, -1.0, 0.0));
+    let top = cylinder.hit(&down, &ray_t).unwrap();
+    assert_eq!((top.t, top.normal, top.front_face, top.v), (9.0, Vec3::new(0.0, 1.0, 0.0), true, 0.5));
+    assert!(cylinder.without_caps().hit(&down, &ray_t).is_none());
+    // above the top, and past the side
+    assert!(cylinder.hit(&Ray::new(Point3D::new(0.0, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).is_none());
+    assert!(cylinder.hit(&Ray::new(Point3D::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).is_none());
+
+    // half a cylinder, the half on the -z side, so the ray goes through the cut and hits the back
+    let half = cylinder.with_sweep(Sweep::new(consts::PI).unwrap());
+    let through = half.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert_eq!((through.t, through.front_face, through.u), (6.0, false, 0.5));
+}
+
+#[test]
+fn test_cylinder_sizes() {
+    let center = Point3D::new(0.0, 0.0, 0.0);
+    for (radius, height) in [(0.0, 1.0), (-1.0, 1.0), (Float::NAN, 1.0), (Float::INFINITY, 1.0), (1.0, 0.0), (1.0, -1.0), (1.0, Float::NAN)] {
+        assert_eq!(Cylinder::new(center, radius, height, 0).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
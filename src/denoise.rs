@@ -0,0 +1,211 @@
+use crate::{ color::Color, vec3::{ Vec3, Dot }, image::{ Image, PixelRect }, aov::AovBuffers, float::Float };
+
+// Edge-avoiding a-trous wavelet denoiser (dammertz et al. 2010). Each iteration blurs the image
+// with a 5x5 b-spline kernel whose taps are spread twice as far apart as the previous iteration's,
+// so a handful of cheap iterations cover a large footprint. Every tap is weighted down when its
+// color, normal or albedo differs from the center pixel's, which keeps edges and texture sharp.
+#[derive(Clone, Copy, Debug)]
+pub struct DenoiseSettings {
+    pub iterations: u32,
+    // how different two pixels' colors may be before they stop blurring into each other, halved
+    // every iteration since the image gets smoother as it goes
+    pub sigma_color: Float,
+    pub sigma_normal: Float,
+    pub sigma_albedo: Float,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+const KERNEL: [Float; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// albedo below this is treated as missing (e.g. the sky) and left out of demodulation
+const MIN_ALBEDO: Float = 1e-3;
+
+// only the pixels in region are filtered and only they are blurred into each other, the rest is left black
+pub fn denoise(color: &Image, guides: &AovBuffers, settings: &DenoiseSettings, region: &PixelRect) -> Image {
+    let width = color.width();
+    let height = color.height();
+
+    // filter lighting rather than lighting times surface color, so that texture detail is put
+    // back afterwards untouched instead of being blurred along with the noise
+    let albedo = |i: i32, j: i32| {
+        let a = guides.albedo(i, j);
+        Color::new(
+            if a.x() < MIN_ALBEDO { 1.0 } else { a.x() },
+            if a.y() < MIN_ALBEDO { 1.0 } else { a.y() },
+            if a.z() < MIN_ALBEDO { 1.0 } else { a.z() }
+        )
+    };
+    let rows = region.y..region.y + region.height;
+    let columns = region.x..region.x + region.width;
+    let mut current = Image::new(width, height);
+    for j in rows.clone() {
+        for i in columns.clone() {
+            current.set(i, j, divide(color.get(i, j), albedo(i, j)));
+        }
+    }
+
+    for iteration in 0..settings.iterations {
+        // once the taps are spread further apart than the region is big, every tap but the center
+        // one falls outside it and further iterations change nothing, so stop before the step overflows
+        let Some(step) = (1i32).checked_shl(iteration).filter(|&step| step < i32::max(region.width, region.height)) else {
+            break;
+        };
+        let sigma_color = settings.sigma_color / (step as Float);
+        let mut next = Image::new(width, height);
+        for j in rows.clone() {
+            for i in columns.clone() {
+                let center_color = current.get(i, j);
+                let center_normal = guides.normal(i, j);
+                let center_albedo = guides.albedo(i, j);
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut weight_sum = 0.0;
+                for (ky, kernel_y) in KERNEL.iter().enumerate() {
+                    for (kx, kernel_x) in KERNEL.iter().enumerate() {
+                        let qi = i + ((kx as i32) - 2) * step;
+                        let qj = j + ((ky as i32) - 2) * step;
+                        if !region.contains(qi, qj) {
+                            continue;
+                        }
+                        let q_color = current.get(qi, qj);
+                        let w_color = edge_weight(distance_squared(center_color, q_color), sigma_color);
+                        let w_normal = edge_weight(
+                            (center_normal - guides.normal(qi, qj)).length_squared(),
+                            settings.sigma_normal
+                        );
+                        let w_albedo = edge_weight(
+                            distance_squared(center_albedo, guides.albedo(qi, qj)),
+                            settings.sigma_albedo
+                        );
+                        let weight = kernel_x * kernel_y * w_color * w_normal * w_albedo;
+                        sum += weight * q_color;
+                        weight_sum += weight;
+                    }
+                }
+                // the center tap always has full edge weights, so weight_sum is never zero
+                next.set(i, j, sum * (1.0 / weight_sum));
+            }
+        }
+        current = next;
+    }
+
+    let mut result = Image::new(width, height);
+    for j in rows {
+        for i in columns.clone() {
+            result.set(i, j, current.get(i, j) * albedo(i, j));
+        }
+    }
+    return result;
+}
+
+fn edge_weight(distance_squared: Float, sigma: Float) -> Float {
+    return Float::exp(-distance_squared / (sigma * sigma));
+}
+
+fn distance_squared(a: Color, b: Color) -> Float {
+    let d = Vec3::new(a.x() - b.x(), a.y() - b.y(), a.z() - b.z());
+    return d.dot(d);
+}
+
+fn divide(a: Color, b: Color) -> Color {
+    return Color::new(a.x() / b.x(), a.y() / b.y(), a.z() / b.z());
+}
+
+#[cfg(test)]
+use crate::{ aov::AovSample, hittable::HitRecord, material::{ Material, Lambertian }, point3d::Point3D };
+
+#[cfg(test)]
+fn flat_guides(width: i32, height: i32, albedo_at: impl Fn(i32) -> Color) -> AovBuffers {
+    let mut guides = AovBuffers::new(width, height);
+    for j in 0..height {
+        for i in 0..width {
+            let material = Material::Lambertian(Lambertian::new(albedo_at(i)));
+            let hit = HitRecord::new(
+                Point3D::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                1.0,
+                0.0,
+                0.0,
+                true,
+                0
+            );
+            guides.add_sample(i, j, &AovSample::new(&hit, &material));
+        }
+    }
+    return guides;
+}
+
+#[test]
+fn test_denoise_keeps_constant_image() {
+    let guides = flat_guides(8, 8, |_| Color::new(0.5, 0.5, 0.5));
+    let mut image = Image::new(8, 8);
+    for j in 0..8 {
+        for i in 0..8 {
+            image.set(i, j, Color::new(0.25, 0.25, 0.25));
+        }
+    }
+    let result = denoise(&image, &guides, &DenoiseSettings::default(), &PixelRect::full(image.width(), image.height()));
+    for j in 0..8 {
+        for i in 0..8 {
+            assert!((result.get(i, j).x() - 0.25).abs() < 1e-5);
+        }
+    }
+}
+
+#[test]
+fn test_denoise_reduces_noise() {
+    let guides = flat_guides(16, 16, |_| Color::new(1.0, 1.0, 1.0));
+    let mut image = Image::new(16, 16);
+    for j in 0..16 {
+        for i in 0..16 {
+            // a checkerboard of +-0.1 around 0.5 is about as high frequency as noise gets
+            let noise = if (i + j) % 2 == 0 { 0.1 } else { -0.1 };
+            image.set(i, j, Color::new(0.5 + noise, 0.5 + noise, 0.5 + noise));
+        }
+    }
+    let result = denoise(&image, &guides, &DenoiseSettings::default(), &PixelRect::full(image.width(), image.height()));
+    for j in 2..14 {
+        for i in 2..14 {
+            assert!((result.get(i, j).x() - 0.5).abs() < 0.02);
+        }
+    }
+}
+
+#[test]
+fn test_denoise_preserves_albedo_edges() {
+    // left half is dark, right half is bright, the edge between them should not bleed
+    let albedo_at = |i: i32| if i < 4 { Color::new(0.1, 0.1, 0.1) } else { Color::new(0.9, 0.9, 0.9) };
+    let guides = flat_guides(8, 8, albedo_at);
+    let mut image = Image::new(8, 8);
+    for j in 0..8 {
+        for i in 0..8 {
+            image.set(i, j, albedo_at(i));
+        }
+    }
+    let result = denoise(&image, &guides, &DenoiseSettings::default(), &PixelRect::full(image.width(), image.height()));
+    assert!((result.get(3, 4).x() - 0.1).abs() < 1e-4);
+    assert!((result.get(4, 4).x() - 0.9).abs() < 1e-4);
+}
+
+#[test]
+fn test_denoise_many_iterations() {
+    // iterations past the size of the image are no-ops rather than overflowing the tap spacing
+    let guides = flat_guides(4, 4, |_| Color::new(1.0, 1.0, 1.0));
+    let mut image = Image::new(4, 4);
+    image.set(1, 2, Color::new(1.0, 1.0, 1.0));
+    let region = PixelRect::full(4, 4);
+    let few = denoise(&image, &guides, &DenoiseSettings { iterations: 2, ..Default::default() }, &region);
+    for iterations in [3, 32, 33, u32::MAX] {
+        let many = denoise(&image, &guides, &DenoiseSettings { iterations, ..Default::default() }, &region);
+        assert_eq!(many.pixels(), few.pixels());
+    }
+}
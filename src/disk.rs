@@ -0,0 +1,122 @@
+use std::io::{ self, Error, ErrorKind };
+
+use crate::{
+    point3d::Point3D,
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    sweep::Sweep,
+    aabb::Aabb,
+    ray::Ray,
+    vec3::Vec3,
+    float::{ Float, gamma },
+};
+
+// A flat disk around center facing +y, with an optional hole in the middle to make a ring and an
+// optional partial sweep. Both sides can be hit, with +y as the outside. Other orientations come
+// from an Instance.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Disk {
+    center: Point3D,
+    radius: Float,
+    inner_radius: Float,
+    sweep: Sweep,
+    // index into the world's materials
+    material: usize,
+}
+
+impl Disk {
+    // fails unless radius is more than 0 and finite
+    pub fn new(center: Point3D, radius: Float, material: usize) -> io::Result<Disk> {
+        if !(radius > 0.0 && radius.is_finite()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "a disk's radius must be more than 0 and finite"));
+        }
+        return Ok(Disk { center, radius, inner_radius: 0.0, sweep: Sweep::FULL, material });
+    }
+    pub fn with_sweep(self, sweep: Sweep) -> Disk {
+        Disk { sweep, ..self }
+    }
+    // a ring, with a hole of inner_radius, which fails unless 0 <= inner_radius < radius
+    pub fn with_inner_radius(self, inner_radius: Float) -> io::Result<Disk> {
+        if !(inner_radius >= 0.0 && inner_radius < self.radius) {
+            return Err(Error::new(ErrorKind::InvalidInput, "a disk's inner radius must be at least 0 and less than its radius"));
+        }
+        return Ok(Disk { inner_radius, ..self });
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let o = ray.origin() - self.center;
+        let d = ray.direction();
+        // a ray in the plane of the disk only ever sees its edge
+        if d.y() == 0.0 {
+            return None;
+        }
+        let t = -o.y() / d.y();
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        let local = o + t * d;
+        let local = Vec3::new(local.x(), 0.0, local.z());
+        let distance = Float::sqrt(local.x() * local.x() + local.z() * local.z());
+        if distance > self.radius || distance < self.inner_radius {
+            return None;
+        }
+        let u = self.sweep.u(local)?;
+        // v goes from the outer edge in to the inner one
+        let v = (self.radius - distance) / (self.radius - self.inner_radius);
+        let p = self.center + local;
+        let outward_normal = Vec3::new(0.0, 1.0, 0.0);
+        let mut hit = HitRecord::new(p, outward_normal, t, u, v, false, self.material);
+        hit.set_face_normal(ray, &outward_normal);
+        hit.error = gamma(5) * (local.abs() + p.abs());
+        return Some(hit);
+    }
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, 0.0, self.radius);
+        return Aabb::new(self.center - r, self.center + r);
+    }
+}
+
+#[cfg(test)]
+use crate::float::consts;
+
+#[test]
+fn test_disk_hit() {
+    let disk = Disk::new(Point3D::new(0.0, -1.0, 0.0), 2.0, 2).unwrap();
+    let ray_t = Interval::new(0.0, Float::INFINITY);
+    assert_eq!(disk.bounding_box(), Aabb::new(Point3D::new(-2.0, -1.0, -2.0), Point3D::new(2.0, -1.0, 2.0)));
+    let down = Ray::new(Point3D::new(1.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let hit = disk.hit(&down, &ray_t).unwrap();
+    assert_eq!((hit.t, hit.p, hit.normal, hit.front_face), (2.0, Point3D::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), true));
+    assert_eq!((hit.u, hit.v, hit.material), (0.0, 0.5, 2));
+    // from below the back is hit
+    let up = disk.hit(&Ray::new(Point3D::new(1.0, -3.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), &ray_t).unwrap();
+    assert_eq!((up.normal, up.front_face), (Vec3::new(0.0, -1.0, 0.0), false));
+    // outside the edge, along the plane and behind the ray
+    assert!(disk.hit(&Ray::new(Point3D::new(2.5, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).is_none());
+    assert!(disk.hit(&Ray::new(Point3D::new(0.0, -1.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).is_none());
+    assert!(disk.hit(&Ray::new(Point3D::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), &ray_t).is_none());
+
+    // through the hole of a ring, and through the missing three quarters of a quarter disk
+    let ring = disk.with_inner_radius(1.5).unwrap();
+    assert!(ring.hit(&down, &ray_t).is_none());
+    assert_eq!(ring.hit(&Ray::new(Point3D::new(1.75, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).unwrap().v, 0.5);
+    let quarter = disk.with_sweep(Sweep::new(consts::FRAC_PI_2).unwrap());
+    assert!(quarter.hit(&Ray::new(Point3D::new(-1.0, 1.0, -1.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).is_none());
+    assert!(quarter.hit(&Ray::new(Point3D::new(1.0, 1.0, -1.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).is_some());
+}
+
+#[test]
+fn test_disk_radii() {
+    let center = Point3D::new(0.0, 0.0, 0.0);
+    for radius in [0.0, -1.0, Float::NAN, Float::INFINITY] {
+        assert_eq!(Disk::new(center, radius, 0).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+    // a hole as big as the disk would leave nothing, and divide by zero for v
+    let disk = Disk::new(center, 1.0, 0).unwrap();
+    for inner_radius in [1.0, 2.0, -0.5, Float::NAN] {
+        assert_eq!(disk.with_inner_radius(inner_radius).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+    assert!(disk.with_inner_radius(0.0).is_ok());
+}
@@ -0,0 +1,465 @@
+use std::{
+    collections::VecDeque,
+    io::{ self, BufReader, BufWriter, Error, ErrorKind, Read, Write },
+    net::{ Shutdown, TcpListener, TcpStream },
+    sync::{ atomic::{ AtomicBool, Ordering }, mpsc::{ self, RecvTimeoutError, Sender }, Arc, Mutex },
+    thread,
+    time::Duration,
+};
+
+use crate::{ framebuffer::FrameBuffer, image::PixelRect, observer::{ CancellationToken, cancelled_error } };
+
+// Distributed rendering splits one image into tiles and hands them out to workers over tcp.
+//
+// A worker connects to the coordinator and introduces itself with
+//   magic "RTDW", protocol version (u32), scene hash (u64), settings hash (u64)
+// and the coordinator answers with one of
+//   REJECT            the worker is rendering a different scene or with different settings
+//   TILE x y w h      render these pixels (i32s) and send back the result
+//   DONE              the image is finished
+// where each result is the x and y (i32s) of the framebuffer's top left pixel followed by the
+// framebuffer in FrameBuffer::write_raw's format. Everything is little endian.
+//
+// Tiles whose worker disconnects, stalls for longer than the timeout or sends back something that
+// doesn't fit the tile go back into the queue for the next worker to ask for one, so workers can
+// come and go while the coordinator waits.
+
+const MAGIC: &[u8; 4] = b"RTDW";
+const VERSION: u32 = 1;
+
+const DONE: u8 = 0;
+const TILE: u8 = 1;
+const REJECT: u8 = 2;
+
+// covers region with tiles of tile_size x tile_size pixels, smaller along the right and bottom edges
+pub fn split_into_tiles(region: &PixelRect, tile_size: i32) -> Vec<PixelRect> {
+    let tile_size = tile_size.max(1);
+    let mut tiles = Vec::new();
+    for y in (region.y..region.y + region.height).step_by(tile_size as usize) {
+        for x in (region.x..region.x + region.width).step_by(tile_size as usize) {
+            let width = i32::min(tile_size, region.x + region.width - x);
+            let height = i32::min(tile_size, region.y + region.height - y);
+            tiles.push(PixelRect::new(x, y, width, height));
+        }
+    }
+    return tiles;
+}
+
+// how long the coordinator waits on a worker, for its introduction or a tile's result, before giving
+// up on it and handing its tile to another worker
+pub const WORKER_TIMEOUT: Duration = Duration::from_secs(300);
+
+// what the coordinator puts together and what it accepts from workers
+#[derive(Clone, Copy, Debug)]
+pub struct CoordinatorSettings {
+    // the size of the framebuffer the tiles are merged into
+    pub width: i32,
+    pub height: i32,
+    // how far past its tile a result may reach, filters splat samples over the tile's edge
+    pub margin: i32,
+    pub scene_hash: u64,
+    pub settings_hash: u64,
+    pub timeout: Duration,
+}
+
+// what the threads talking to workers share with the coordinator
+struct Shared {
+    settings: CoordinatorSettings,
+    queue: Mutex<VecDeque<PixelRect>>,
+    results: Sender<(PixelRect, i32, i32, FrameBuffer)>,
+    // a clone of every worker's stream, so they can all be shut down when the render is cancelled
+    streams: Mutex<Vec<TcpStream>>,
+    // set once the coordinator has stopped, after which no more tiles are handed out
+    finished: AtomicBool,
+}
+
+// Hands out tiles to every worker that connects to listener until all of them have been rendered,
+// and returns the framebuffer they add up to. on_tile is called with each tile once its result is
+// in the framebuffer, along with how many tiles are still to come. Once cancel is cancelled the
+// coordinator stops waiting, cuts off the workers and returns an Interrupted error.
+pub fn coordinate(
+    listener: TcpListener,
+    settings: CoordinatorSettings,
+    tiles: Vec<PixelRect>,
+    cancel: &CancellationToken,
+    mut on_tile: impl FnMut(&PixelRect, usize, &FrameBuffer)
+) -> io::Result<FrameBuffer> {
+    let mut remaining = tiles.len();
+    let (results, received) = mpsc::channel();
+    let shared = Arc::new(Shared {
+        settings,
+        queue: Mutex::new(VecDeque::from(tiles)),
+        results,
+        streams: Mutex::new(Vec::new()),
+        finished: AtomicBool::new(false),
+    });
+
+    // accept without blocking so the thread notices when the render is finished
+    listener.set_nonblocking(true)?;
+    let acceptor = {
+        let shared = shared.clone();
+        thread::spawn(move || {
+            while !shared.finished.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        shared.streams.lock().unwrap().push(stream.try_clone()?);
+                        let shared = shared.clone();
+                        thread::spawn(move || {
+                            let _ = serve_worker(stream, &shared);
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(10)),
+                    Err(e) => {
+                        return Err(e);
+                    }
+                }
+            }
+            Ok(())
+        })
+    };
+
+    let mut framebuffer = FrameBuffer::new(settings.width, settings.height);
+    // the acceptor only stops early when the listener fails, which is returned below
+    while remaining > 0 && !cancel.is_cancelled() && !acceptor.is_finished() {
+        // wake up now and then to notice a cancellation
+        match received.recv_timeout(Duration::from_millis(100)) {
+            Ok((tile, x, y, result)) => {
+                framebuffer.merge(&result, x, y);
+                remaining -= 1;
+                on_tile(&tile, remaining, &framebuffer);
+            }
+            // the shared state holds a sender for as long as this runs, so it never disconnects
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {}
+        }
+    }
+    shared.finished.store(true, Ordering::Relaxed);
+    let accepted = acceptor.join().unwrap();
+    if remaining > 0 {
+        // Workers still rendering tiles that are no longer wanted are cut off rather than left to
+        // finish. Once the render is complete no worker is in the middle of a tile, and the waiting
+        // ones are sent DONE as soon as they notice it has finished.
+        for stream in shared.streams.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+    accepted?;
+    if remaining > 0 && cancel.is_cancelled() {
+        return Err(cancelled_error());
+    }
+    return Ok(framebuffer);
+}
+
+// talks to one worker, putting its tile back in the queue if it goes away halfway through or takes
+// longer than the timeout to answer
+fn serve_worker(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let settings = &shared.settings;
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(settings.timeout))?;
+    stream.set_write_timeout(Some(settings.timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream.try_clone()?);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    let version = read_u32(&mut reader)?;
+    let worker_scene_hash = read_u64(&mut reader)?;
+    let worker_settings_hash = read_u64(&mut reader)?;
+    if &magic != MAGIC ||
+        version != VERSION ||
+        worker_scene_hash != settings.scene_hash ||
+        worker_settings_hash != settings.settings_hash
+    {
+        writer.write_all(&[REJECT])?;
+        return writer.flush();
+    }
+
+    loop {
+        if shared.finished.load(Ordering::Relaxed) {
+            writer.write_all(&[DONE])?;
+            writer.flush()?;
+            return stream.shutdown(Shutdown::Both);
+        }
+        let Some(tile) = shared.queue.lock().unwrap().pop_front() else {
+            // the other workers' tiles come back to the queue if they fail, so wait for the render to
+            // finish rather than sending this worker away
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        };
+        match send_tile(&mut reader, &mut writer, &tile, settings) {
+            Ok((x, y, framebuffer)) => {
+                shared.results.send((tile, x, y, framebuffer)).map_err(|e| Error::new(ErrorKind::BrokenPipe, e))?;
+            }
+            Err(e) => {
+                shared.queue.lock().unwrap().push_back(tile);
+                let _ = stream.shutdown(Shutdown::Both);
+                return Err(e);
+            }
+        }
+    }
+}
+
+fn send_tile(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    tile: &PixelRect,
+    settings: &CoordinatorSettings
+) -> io::Result<(i32, i32, FrameBuffer)> {
+    writer.write_all(&[TILE])?;
+    for value in [tile.x, tile.y, tile.width, tile.height] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()?;
+    let x = read_i32(reader)?;
+    let y = read_i32(reader)?;
+    // The result covers the tile and whatever of the margin around it is inside the image. Anything
+    // bigger is refused before it is allocated, and anything elsewhere would be merged in the wrong
+    // place or out of bounds.
+    let allowed = tile.grown(settings.margin, &PixelRect::full(settings.width, settings.height));
+    let framebuffer = FrameBuffer::read_raw(reader, FrameBuffer::raw_len(allowed.width, allowed.height).unwrap_or(0))?;
+    let covered = PixelRect::new(x, y, framebuffer.width(), framebuffer.height());
+    if !allowed.contains_rect(&covered) || !covered.contains_rect(tile) {
+        return Err(Error::new(ErrorKind::InvalidData, "worker sent a result that doesn't match its tile"));
+    }
+    return Ok((x, y, framebuffer));
+}
+
+// The worker side: introduces itself to the coordinator on stream, then renders tiles with
+// render_tile until the coordinator has no more, returning how many it rendered. render_tile returns
+// the framebuffer for a tile along with the rect of the image it covers.
+pub fn work(
+    stream: TcpStream,
+    scene_hash: u64,
+    settings_hash: u64,
+    mut render_tile: impl FnMut(&PixelRect) -> (PixelRect, FrameBuffer)
+) -> io::Result<u32> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&scene_hash.to_le_bytes())?;
+    writer.write_all(&settings_hash.to_le_bytes())?;
+    writer.flush()?;
+
+    let mut rendered = 0;
+    loop {
+        let mut message = [0u8; 1];
+        reader.read_exact(&mut message)?;
+        match message[0] {
+            DONE => {
+                return Ok(rendered);
+            }
+            TILE => {
+                let tile = PixelRect::new(
+                    read_i32(&mut reader)?,
+                    read_i32(&mut reader)?,
+                    read_i32(&mut reader)?,
+                    read_i32(&mut reader)?
+                );
+                let (covered, framebuffer) = render_tile(&tile);
+                writer.write_all(&covered.x.to_le_bytes())?;
+                writer.write_all(&covered.y.to_le_bytes())?;
+                framebuffer.write_raw(&mut writer)?;
+                writer.flush()?;
+                rendered += 1;
+            }
+            REJECT => {
+                return Err(
+                    Error::new(ErrorKind::InvalidData, "the coordinator is rendering a different scene or with different settings")
+                );
+            }
+            other => {
+                return Err(Error::new(ErrorKind::InvalidData, format!("unknown message {} from the coordinator", other)));
+            }
+        }
+    }
+}
+
+fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    return Ok(i32::from_le_bytes(bytes));
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    return Ok(u32::from_le_bytes(bytes));
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    return Ok(u64::from_le_bytes(bytes));
+}
+
+#[cfg(test)]
+use crate::{
+    camera::Camera,
+    render_settings::RenderSettings,
+    sampler::SamplerKind,
+    filter::{ Filter, FilterKind },
+    scene::SceneDescription,
+    test_fixtures::spheres_camera,
+};
+
+#[cfg(test)]
+fn test_camera() -> Camera {
+    // some defocus so the lens samples have to line up between workers too
+    return spheres_camera(4.0 / 3.0, 20, 2, 0.6);
+}
+
+#[cfg(test)]
+fn test_settings() -> RenderSettings {
+    return RenderSettings {
+        sampler: SamplerKind::Sobol,
+        filter: Filter::with_default_radius(FilterKind::Gaussian),
+        ..Default::default()
+    };
+}
+
+#[test]
+fn test_split_into_tiles() {
+    let tiles = split_into_tiles(&PixelRect::new(2, 1, 10, 5), 4);
+    assert_eq!(tiles.len(), 6);
+    assert_eq!(tiles[0], PixelRect::new(2, 1, 4, 4));
+    assert_eq!(tiles[2], PixelRect::new(10, 1, 2, 4));
+    assert_eq!(tiles[5], PixelRect::new(10, 5, 2, 1));
+    let area: i32 = tiles.iter().map(|t| t.width * t.height).sum();
+    assert_eq!(area, 50);
+}
+
+#[cfg(test)]
+fn test_coordinator_settings(scene_hash: u64, settings_hash: u64, timeout: Duration) -> CoordinatorSettings {
+    return CoordinatorSettings {
+        width: 20,
+        height: 15,
+        margin: test_settings().filter.margin(),
+        scene_hash,
+        settings_hash,
+        timeout,
+    };
+}
+
+#[test]
+fn test_distributed_render_matches_local_render() {
+    let scene = SceneDescription::random_spheres(3);
+    let scene_hash = scene.hash();
+    let camera = test_camera();
+    let settings = test_settings();
+    let world = scene.build();
+    let (full, expected) = camera.render_tile(&world, &settings, &PixelRect::full(20, 15));
+    assert_eq!(full, PixelRect::full(20, 15));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let coordinator_settings = test_coordinator_settings(scene_hash, camera.settings_hash(&settings), WORKER_TIMEOUT);
+    thread::scope(|scope| {
+        let world = &world;
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                scope.spawn(move || {
+                    let stream = TcpStream::connect(address).unwrap();
+                    test_camera().serve_tiles(world, &test_settings(), stream, scene_hash).unwrap()
+                })
+            })
+            .collect();
+        // a worker that is rendering something else is turned away without getting any tiles
+        let stranger = scope.spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            work(stream, scene_hash + 1, 0, |_| panic!("a rejected worker should not render"))
+        });
+        let tiles = split_into_tiles(&PixelRect::full(20, 15), 4);
+        let mut completed = Vec::new();
+        let framebuffer = coordinate(
+            listener,
+            coordinator_settings,
+            tiles,
+            &CancellationToken::new(),
+            |tile, remaining, _| completed.push((*tile, remaining))
+        ).unwrap();
+        assert_eq!(completed.len(), 20);
+        assert_eq!(completed.last().unwrap().1, 0);
+        assert!(stranger.join().unwrap().is_err());
+        let rendered: u32 = workers.into_iter().map(|w| w.join().unwrap()).sum();
+        assert_eq!(rendered, 20);
+
+        for j in 0..15 {
+            for i in 0..20 {
+                assert_eq!(framebuffer.sample_count(i, j), 2);
+                let (a, b) = (framebuffer.pixel(i, j), expected.pixel(i, j));
+                assert!((a.x() - b.x()).abs() < 1e-4 && (a.z() - b.z()).abs() < 1e-4, "{:?} != {:?} at {},{}", a, b, i, j);
+            }
+        }
+    });
+}
+
+#[test]
+fn test_coordinator_requeues_stalled_and_bad_tiles() {
+    let scene = SceneDescription::random_spheres(3);
+    let scene_hash = scene.hash();
+    let world = scene.build();
+    let settings_hash = test_camera().settings_hash(&test_settings());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::scope(|scope| {
+        // one worker never answers and one answers with the whole image, both lose their tile to the
+        // worker that joins after them
+        let stalled = scope.spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            work(stream, scene_hash, settings_hash, |tile| {
+                thread::sleep(Duration::from_secs(1));
+                (*tile, FrameBuffer::new(tile.width, tile.height))
+            })
+        });
+        let oversized = scope.spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            work(stream, scene_hash, settings_hash, |_| (PixelRect::full(20, 15), FrameBuffer::new(20, 15)))
+        });
+        let world = &world;
+        let worker = scope.spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            let stream = TcpStream::connect(address).unwrap();
+            test_camera().serve_tiles(world, &test_settings(), stream, scene_hash).unwrap()
+        });
+        let coordinator_settings = test_coordinator_settings(scene_hash, settings_hash, Duration::from_millis(300));
+        let tiles = split_into_tiles(&PixelRect::full(20, 15), 4);
+        let framebuffer = coordinate(listener, coordinator_settings, tiles, &CancellationToken::new(), |_, _, _| {}).unwrap();
+        assert_eq!(worker.join().unwrap(), 20);
+        assert!(stalled.join().unwrap().is_err());
+        assert!(oversized.join().unwrap().is_err());
+        for j in 0..15 {
+            for i in 0..20 {
+                assert_eq!(framebuffer.sample_count(i, j), 2);
+            }
+        }
+    });
+}
+
+#[test]
+fn test_cancelled_coordinator_cuts_off_workers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let cancel = CancellationToken::new();
+    let worker = {
+        let cancel = cancel.clone();
+        thread::spawn(move || {
+            let mut rendered = 0;
+            let stream = TcpStream::connect(address).unwrap();
+            let result = work(stream, 1, 2, |tile| {
+                rendered += 1;
+                cancel.cancel();
+                thread::sleep(Duration::from_millis(300));
+                (*tile, FrameBuffer::new(tile.width, tile.height))
+            });
+            (result, rendered)
+        })
+    };
+    // with a timeout this long, the worker only finishes quickly if it is cut off
+    let coordinator_settings = test_coordinator_settings(1, 2, WORKER_TIMEOUT);
+    let tiles = split_into_tiles(&PixelRect::full(20, 15), 4);
+    let result = coordinate(listener, coordinator_settings, tiles, &cancel, |_, _, _| {});
+    assert!(matches!(result, Err(e) if e.kind() == ErrorKind::Interrupted));
+    let (result, rendered) = worker.join().unwrap();
+    assert!(result.is_err());
+    assert_eq!(rendered, 1);
+}
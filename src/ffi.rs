@@ -0,0 +1,452 @@
+// the functions take raw pointers from c, which has no notion of unsafe, so instead of being unsafe
+// themselves they check for null and trust the caller with the rest like any c library would
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use std::{
+    ffi::{ c_char, CString },
+    panic::{ self, AssertUnwindSafe },
+    ptr,
+    slice,
+};
+
+use crate::{
+    camera::Camera,
+    camera_model::ThinLensCamera,
+    color::Color,
+    point3d::Point3D,
+    render_settings::RenderSettings,
+    scene::{ SceneDescription, MaterialDescription },
+    vec3::Vec3,
+    float::{ Float, to_f32 },
+};
+
+// The C interface, built into the cdylib and described by include/raytracer.h, which is generated
+// from this file (build with the header feature to check it is up to date). A scene is an opaque
+// handle that collects materials, spheres and a camera the same way SceneDescription and
+// Camera::new do, and renders into a buffer the caller owns. Every function returns an RtStatus, and when it isn't RT_STATUS_OK rt_scene_last_error
+// describes what went wrong. Handles are not thread safe, but separate handles can be used from
+// separate threads. The interface uses 32 bit floats whichever Float the crate is built with.
+
+/// what a call did, anything but RT_STATUS_OK comes with a message from rt_scene_last_error
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    BufferTooSmall = 3,
+    NoCamera = 4,
+    RenderFailed = 5,
+    /// the renderer panicked partway through the call
+    Panic = 6,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RtVec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl From<RtVec3> for Vec3 {
+    fn from(v: RtVec3) -> Vec3 {
+        Vec3::new(v.x as Float, v.y as Float, v.z as Float)
+    }
+}
+
+// the arguments of Camera::new, with the image height worked out up front
+#[derive(Clone, Copy)]
+struct CameraDescription {
+    image_width: i32,
+    image_height: i32,
+    samples_per_pixel: i32,
+    max_depth: i32,
+    vfov: Float,
+    look_from: Point3D,
+    look_at: Point3D,
+    vup: Vec3,
+    defocus_angle: Float,
+    focus_distance: Float,
+}
+
+/// an opaque handle to a scene and its camera
+pub struct RtScene {
+    scene: SceneDescription,
+    camera: Option<CameraDescription>,
+    seed: u64,
+    last_error: Option<CString>,
+}
+
+impl RtScene {
+    fn fail(&mut self, status: RtStatus, message: &str) -> RtStatus {
+        // interior nul bytes can't be passed to c, and none of the messages have any
+        self.last_error = CString::new(message).ok();
+        return status;
+    }
+}
+
+// runs f on the scene behind the handle, turning a null handle or a panic into an error status
+fn with_scene(scene: *mut RtScene, f: impl FnOnce(&mut RtScene) -> RtStatus) -> RtStatus {
+    // safety: the caller passes a handle from rt_scene_new that has not been freed
+    let Some(scene) = (unsafe { scene.as_mut() }) else {
+        return RtStatus::NullPointer;
+    };
+    scene.last_error = None;
+    return match panic::catch_unwind(AssertUnwindSafe(|| f(scene))) {
+        Ok(status) => status,
+        Err(_) => scene.fail(RtStatus::Panic, "the renderer panicked"),
+    };
+}
+
+// Camera::image_height done in f32, since the aspect ratio comes in as an f32 and the same call
+// has to give the same image size in the f64 build, where 16.0f / 9.0f is a little more than 16 / 9
+fn image_height(aspect_ratio: f32, image_width: i32) -> i32 {
+    return i32::max(((image_width as f32) / aspect_ratio) as i32, 1);
+}
+
+fn is_color(r: f32, g: f32, b: f32) -> bool {
+    return [r, g, b].iter().all(|c| c.is_finite() && *c >= 0.0);
+}
+
+fn color(r: f32, g: f32, b: f32) -> Color {
+    return Color::new(r as Float, g as Float, b as Float);
+}
+
+// adds a material and writes its index for rt_scene_add_sphere to id
+fn add_material(scene: *mut RtScene, material: MaterialDescription, valid: bool, id: *mut u32) -> RtStatus {
+    return with_scene(scene, |scene| {
+        if !valid {
+            return scene.fail(RtStatus::InvalidArgument, "material parameters are out of range");
+        }
+        let index = scene.scene.add_material(material) as u32;
+        // safety: id is null or points to a u32 the caller owns
+        if let Some(id) = unsafe { id.as_mut() } {
+            *id = index;
+        }
+        RtStatus::Ok
+    });
+}
+
+/// a new empty scene, freed with rt_scene_free
+#[no_mangle]
+pub extern "C" fn rt_scene_new() -> *mut RtScene {
+    let scene = RtScene { scene: SceneDescription::default(), camera: None, seed: 0, last_error: None };
+    return Box::into_raw(Box::new(scene));
+}
+
+/// frees a scene from rt_scene_new, null is ignored
+#[no_mangle]
+pub extern "C" fn rt_scene_free(scene: *mut RtScene) {
+    if !scene.is_null() {
+        // safety: the handle came from Box::into_raw in rt_scene_new and is freed once
+        drop(unsafe { Box::from_raw(scene) });
+    }
+}
+
+/// The message for the error returned by the last call on scene, or null if it succeeded. The string
+/// belongs to the scene and stays valid until the next call on it.
+#[no_mangle]
+pub extern "C" fn rt_scene_last_error(scene: *const RtScene) -> *const c_char {
+    // safety: the caller passes a handle from rt_scene_new that has not been freed
+    return match unsafe { scene.as_ref() } {
+        Some(RtScene { last_error: Some(message), .. }) => message.as_ptr(),
+        _ => ptr::null(),
+    };
+}
+
+/// mirrors Lambertian::new, id may be null
+#[no_mangle]
+pub extern "C" fn rt_scene_add_lambertian(scene: *mut RtScene, r: f32, g: f32, b: f32, id: *mut u32) -> RtStatus {
+    let material = MaterialDescription::Lambertian(color(r, g, b));
+    return add_material(scene, material, is_color(r, g, b), id);
+}
+
+/// mirrors Metal::new, id may be null
+#[no_mangle]
+pub extern "C" fn rt_scene_add_metal(
+    scene: *mut RtScene,
+    r: f32,
+    g: f32,
+    b: f32,
+    fuzziness: f32,
+    id: *mut u32
+) -> RtStatus {
+    let material = MaterialDescription::Metal(color(r, g, b), fuzziness as Float);
+    return add_material(scene, material, is_color(r, g, b) && fuzziness.is_finite() && fuzziness >= 0.0, id);
+}
+
+/// mirrors Dielectric::new, id may be null
+#[no_mangle]
+pub extern "C" fn rt_scene_add_dielectric(scene: *mut RtScene, refraction_index: f32, id: *mut u32) -> RtStatus {
+    let material = MaterialDescription::Dielectric(refraction_index as Float);
+    return add_material(scene, material, refraction_index.is_finite() && refraction_index > 0.0, id);
+}
+
+/// mirrors Sphere::new, with material being an id from one of the rt_scene_add_ material functions,
+/// the radius may not be negative
+#[no_mangle]
+pub extern "C" fn rt_scene_add_sphere(scene: *mut RtScene, center: RtVec3, radius: f32, material: u32) -> RtStatus {
+    return with_scene(scene, |scene| {
+        if (material as usize) >= scene.scene.materials.len() {
+            return scene.fail(RtStatus::InvalidArgument, "the sphere's material has not been added to the scene");
+        }
+        if !(radius.is_finite() && radius >= 0.0) {
+            return scene.fail(RtStatus::InvalidArgument, "the sphere's radius must be a number that isn't negative");
+        }
+        scene.scene.add_sphere(center.into(), radius as Float, material as usize);
+        RtStatus::Ok
+    });
+}
+
+/// Mirrors Camera::new, replacing the scene's camera. vfov has to be between 0 and 180 degrees, the
+/// defocus angle at least 0 and below 180 degrees, and the focus distance positive.
+#[no_mangle]
+pub extern "C" fn rt_scene_set_camera(
+    scene: *mut RtScene,
+    aspect_ratio: f32,
+    image_width: i32,
+    samples_per_pixel: i32,
+    max_depth: i32,
+    vfov: f32,
+    look_from: RtVec3,
+    look_at: RtVec3,
+    vup: RtVec3,
+    defocus_angle: f32,
+    focus_distance: f32
+) -> RtStatus {
+    return with_scene(scene, |scene| {
+        if aspect_ratio.is_nan() || aspect_ratio <= 0.0 || image_width <= 0 || samples_per_pixel <= 0 || max_depth <= 0 {
+            return scene.fail(
+                RtStatus::InvalidArgument,
+                "the aspect ratio, image width, samples per pixel and max depth must be positive"
+            );
+        }
+        if !(vfov > 0.0 && vfov < 180.0) {
+            return scene.fail(RtStatus::InvalidArgument, "the vertical field of view must be between 0 and 180 degrees");
+        }
+        if !(focus_distance.is_finite() && focus_distance > 0.0) {
+            return scene.fail(RtStatus::InvalidArgument, "the focus distance must be positive");
+        }
+        if !(0.0..180.0).contains(&defocus_angle) {
+            return scene.fail(RtStatus::InvalidArgument, "the defocus angle must be at least 0 and below 180 degrees");
+        }
+        scene.camera = Some(CameraDescription {
+            image_width,
+            image_height: image_height(aspect_ratio, image_width),
+            samples_per_pixel,
+            max_depth,
+            vfov: vfov as Float,
+            look_from: look_from.into(),
+            look_at: look_at.into(),
+            vup: vup.into(),
+            defocus_angle: defocus_angle as Float,
+            focus_distance: focus_distance as Float,
+        });
+        RtStatus::Ok
+    });
+}
+
+/// changes the noise of the render, the same seed always gives the same image
+#[no_mangle]
+pub extern "C" fn rt_scene_set_seed(scene: *mut RtScene, seed: u64) -> RtStatus {
+    return with_scene(scene, |scene| {
+        scene.seed = seed;
+        RtStatus::Ok
+    });
+}
+
+/// the size of the image the camera renders, either pointer may be null
+#[no_mangle]
+pub extern "C" fn rt_scene_image_size(scene: *mut RtScene, width: *mut i32, height: *mut i32) -> RtStatus {
+    return with_scene(scene, |scene| {
+        let Some(camera) = scene.camera else {
+            return scene.fail(RtStatus::NoCamera, "the scene has no camera");
+        };
+        // safety: width and height are null or point to i32s the caller owns
+        if let Some(width) = unsafe { width.as_mut() } {
+            *width = camera.image_width;
+        }
+        if let Some(height) = unsafe { height.as_mut() } {
+            *height = camera.image_height;
+        }
+        RtStatus::Ok
+    });
+}
+
+/// Renders the scene into buffer as linear rgb floats, three per pixel, row by row from the top left.
+/// buffer_len is the number of floats in buffer, which needs at least width * height * 3.
+#[no_mangle]
+pub extern "C" fn rt_scene_render(scene: *mut RtScene, buffer: *mut f32, buffer_len: usize) -> RtStatus {
+    return with_scene(scene, |scene| {
+        if buffer.is_null() {
+            return scene.fail(RtStatus::NullPointer, "the buffer is null");
+        }
+        let Some(c) = scene.camera else {
+            return scene.fail(RtStatus::NoCamera, "the scene has no camera");
+        };
+        let needed = (c.image_width as usize) * (c.image_height as usize) * 3;
+        if buffer_len < needed {
+            return scene.fail(
+                RtStatus::BufferTooSmall,
+                &format!("the image needs {} floats but the buffer holds {}", needed, buffer_len)
+            );
+        }
+        let model = ThinLensCamera::new(
+            (c.image_width as Float) / (c.image_height as Float),
+            c.vfov,
+            c.look_from,
+            c.look_at,
+            c.vup,
+            c.defocus_angle,
+            c.focus_distance
+        );
+        let camera = Camera::with_model(c.image_width, c.image_height, c.samples_per_pixel, c.max_depth, Box::new(model));
+        let settings = RenderSettings { seed: scene.seed, ..Default::default() };
+        let output = match camera.render_to_buffer(&scene.scene.build(), &settings) {
+            Ok(output) => output,
+            Err(e) => {
+                return scene.fail(RtStatus::RenderFailed, &e.to_string());
+            }
+        };
+        // safety: the caller promises buffer holds buffer_len floats, of which needed are written
+        let buffer = unsafe { slice::from_raw_parts_mut(buffer, needed) };
+        for (rgb, color) in buffer.chunks_exact_mut(3).zip(output.image.pixels()) {
+            rgb.copy_from_slice(&[to_f32(color.x()), to_f32(color.y()), to_f32(color.z())]);
+        }
+        RtStatus::Ok
+    });
+}
+
+#[cfg(test)]
+use std::ffi::CStr;
+
+#[cfg(test)]
+fn v(x: f32, y: f32, z: f32) -> RtVec3 {
+    return RtVec3 { x, y, z };
+}
+
+#[test]
+fn test_render_through_c_interface() {
+    let scene = rt_scene_new();
+    let mut ground = u32::MAX;
+    let mut glass = u32::MAX;
+    assert_eq!(rt_scene_add_lambertian(scene, 0.5, 0.5, 0.5, &mut ground), RtStatus::Ok);
+    assert_eq!(rt_scene_add_dielectric(scene, 1.5, &mut glass), RtStatus::Ok);
+    assert_eq!((ground, glass), (0, 1));
+    assert_eq!(rt_scene_add_sphere(scene, v(0.0, -1000.0, 0.0), 1000.0, ground), RtStatus::Ok);
+    assert_eq!(rt_scene_add_sphere(scene, v(0.0, 1.0, 0.0), 1.0, glass), RtStatus::Ok);
+
+    let mut buffer = vec![-1.0f32; 16 * 8 * 3];
+    assert_eq!(rt_scene_render(scene, buffer.as_mut_ptr(), buffer.len()), RtStatus::NoCamera);
+    let status = rt_scene_set_camera(
+        scene,
+        2.0,
+        16,
+        2,
+        4,
+        20.0,
+        v(13.0, 2.0, 3.0),
+        v(0.0, 0.0, 0.0),
+        v(0.0, 1.0, 0.0),
+        0.0,
+        10.0
+    );
+    assert_eq!(status, RtStatus::Ok);
+    let (mut width, mut height) = (0, 0);
+    assert_eq!(rt_scene_image_size(scene, &mut width, &mut height), RtStatus::Ok);
+    assert_eq!((width, height), (16, 8));
+
+    assert_eq!(rt_scene_render(scene, buffer.as_mut_ptr(), buffer.len()), RtStatus::Ok);
+    assert!(rt_scene_last_error(scene).is_null());
+    // the sky at the top of the image is bright and blue tinted
+    assert!(buffer[2] > buffer[0] && buffer[0] > 0.3);
+    assert!(buffer.iter().all(|c| *c >= 0.0));
+    rt_scene_free(scene);
+}
+
+#[test]
+fn test_image_size_matches_across_precisions() {
+    // 32 / (16.0f / 9.0f) is a hair under 18 in f64, which would round the height down to 17
+    let scene = rt_scene_new();
+    let status = rt_scene_set_camera(
+        scene,
+        16.0 / 9.0,
+        32,
+        1,
+        1,
+        20.0,
+        v(13.0, 2.0, 3.0),
+        v(0.0, 0.0, 0.0),
+        v(0.0, 1.0, 0.0),
+        0.0,
+        10.0
+    );
+    assert_eq!(status, RtStatus::Ok);
+    let mut height = 0;
+    assert_eq!(rt_scene_image_size(scene, ptr::null_mut(), &mut height), RtStatus::Ok);
+    assert_eq!(height, 18);
+    rt_scene_free(scene);
+}
+
+#[test]
+fn test_c_interface_errors() {
+    let scene = rt_scene_new();
+    assert_eq!(rt_scene_add_sphere(scene, v(0.0, 0.0, 0.0), 1.0, 0), RtStatus::InvalidArgument);
+    let message = unsafe { CStr::from_ptr(rt_scene_last_error(scene)) };
+    assert!(message.to_str().unwrap().contains("material"));
+    assert_eq!(rt_scene_add_metal(scene, 1.0, 1.0, 1.0, -0.5, ptr::null_mut()), RtStatus::InvalidArgument);
+    // a successful call clears the error
+    assert_eq!(rt_scene_add_metal(scene, 1.0, 1.0, 1.0, 0.5, ptr::null_mut()), RtStatus::Ok);
+    assert!(rt_scene_last_error(scene).is_null());
+
+    let status = rt_scene_set_camera(
+        scene,
+        1.0,
+        4,
+        1,
+        1,
+        90.0,
+        v(0.0, 0.0, 1.0),
+        v(0.0, 0.0, 0.0),
+        v(0.0, 1.0, 0.0),
+        0.0,
+        1.0
+    );
+    assert_eq!(status, RtStatus::Ok);
+    let mut buffer = [0.0f32; 4 * 4 * 3 - 1];
+    assert_eq!(rt_scene_render(scene, buffer.as_mut_ptr(), buffer.len()), RtStatus::BufferTooSmall);
+    assert_eq!(rt_scene_render(scene, ptr::null_mut(), 0), RtStatus::NullPointer);
+    let message = unsafe { CStr::from_ptr(rt_scene_last_error(scene)) };
+    assert!(message.to_str().unwrap().contains("buffer"));
+    assert_eq!(rt_scene_add_sphere(scene, v(0.0, 0.0, 0.0), -1.0, 0), RtStatus::InvalidArgument);
+    // each of the field of view, focus distance and defocus angle out of range
+    let bad_cameras = [
+        (0.0, 0.0, 1.0),
+        (180.0, 0.0, 1.0),
+        (f32::NAN, 0.0, 1.0),
+        (90.0, -1.0, 1.0),
+        (90.0, 0.0, 0.0),
+        (90.0, 0.0, f32::INFINITY),
+    ];
+    for (vfov, defocus_angle, focus_distance) in bad_cameras {
+        let status = rt_scene_set_camera(
+            scene,
+            1.0,
+            4,
+            1,
+            1,
+            vfov,
+            v(0.0, 0.0, 1.0),
+            v(0.0, 0.0, 0.0),
+            v(0.0, 1.0, 0.0),
+            defocus_angle,
+            focus_distance
+        );
+        assert_eq!(status, RtStatus::InvalidArgument);
+    }
+    assert_eq!(rt_scene_add_dielectric(ptr::null_mut(), 1.5, ptr::null_mut()), RtStatus::NullPointer);
+    assert!(rt_scene_last_error(ptr::null()).is_null());
+    rt_scene_free(scene);
+    rt_scene_free(ptr::null_mut());
+}
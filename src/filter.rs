@@ -0,0 +1,139 @@
+use crate::float::{ Float, consts::PI };
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn parse(name: &str) -> Option<FilterKind> {
+        return match name {
+            "box" => Some(FilterKind::Box),
+            "tent" => Some(FilterKind::Tent),
+            "gaussian" => Some(FilterKind::Gaussian),
+            "mitchell" => Some(FilterKind::Mitchell),
+            "lanczos" => Some(FilterKind::Lanczos),
+            _ => None,
+        };
+    }
+    // a radius (in pixels) that suits the filter's shape
+    pub fn default_radius(&self) -> Float {
+        return match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        };
+    }
+}
+
+// A pixel reconstruction filter. Each camera sample is splatted into every pixel whose center lies
+// within radius of it, weighted by the filter, and a pixel's value is the weighted average of the
+// samples it received. Wider filters trade sharpness for less aliasing, the negative lobes of
+// mitchell and lanczos win some of that sharpness back at the cost of slight ringing.
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: Float,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: Float) -> Filter {
+        Filter { kind, radius }
+    }
+    pub fn with_default_radius(kind: FilterKind) -> Filter {
+        return Filter::new(kind, kind.default_radius());
+    }
+    // how many pixels past its own pixel a sample can be splatted
+    pub fn margin(&self) -> i32 {
+        return Float::ceil(self.radius) as i32;
+    }
+    // weight of a sample at offset (x, y) in pixels from a pixel center, the filters are separable
+    pub fn evaluate(&self, x: Float, y: Float) -> Float {
+        return self.evaluate_1d(x) * self.evaluate_1d(y);
+    }
+    fn evaluate_1d(&self, x: Float) -> Float {
+        let x = Float::abs(x);
+        if x >= self.radius {
+            return 0.0;
+        }
+        return match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - x,
+            FilterKind::Gaussian => {
+                // the gaussian is shifted down so it reaches zero at the radius instead of being cut off
+                let sigma = self.radius / 3.0;
+                let gaussian = |x: Float| Float::exp((-x * x) / (2.0 * sigma * sigma));
+                gaussian(x) - gaussian(self.radius)
+            }
+            FilterKind::Mitchell => Filter::mitchell_1d((2.0 * x) / self.radius, 1.0 / 3.0, 1.0 / 3.0),
+            FilterKind::Lanczos => Filter::sinc(x) * Filter::sinc(x / self.radius),
+        };
+    }
+    // mitchell-netravali cubic for x in [0, 2]
+    fn mitchell_1d(x: Float, b: Float, c: Float) -> Float {
+        let value = if x > 1.0 {
+            (-b - 6.0 * c) * x * x * x +
+                (6.0 * b + 30.0 * c) * x * x +
+                (-12.0 * b - 48.0 * c) * x +
+                (8.0 * b + 24.0 * c)
+        } else {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x +
+                (-18.0 + 12.0 * b + 6.0 * c) * x * x +
+                (6.0 - 2.0 * b)
+        };
+        return value / 6.0;
+    }
+    fn sinc(x: Float) -> Float {
+        if Float::abs(x) < 1e-5 {
+            return 1.0;
+        }
+        return Float::sin(PI * x) / (PI * x);
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        // a half pixel box only ever weighs a sample into its own pixel, i.e. a plain average
+        return Filter::with_default_radius(FilterKind::Box);
+    }
+}
+
+#[test]
+fn test_filters_vanish_at_radius() {
+    for kind in [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ] {
+        let filter = Filter::with_default_radius(kind);
+        assert_eq!(filter.evaluate(filter.radius, 0.0), 0.0);
+        assert_eq!(filter.evaluate(0.0, filter.radius + 1.0), 0.0);
+        assert!(filter.evaluate(0.0, 0.0) > 0.0);
+    }
+}
+
+#[test]
+fn test_filters_are_symmetric_and_peak_at_center() {
+    for kind in [FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos] {
+        let filter = Filter::with_default_radius(kind);
+        let center = filter.evaluate(0.0, 0.0);
+        assert_eq!(filter.evaluate(0.3, -0.2), filter.evaluate(-0.3, 0.2));
+        assert!(filter.evaluate(0.3, 0.0) < center);
+    }
+}
+
+#[test]
+fn test_negative_lobes() {
+    let mitchell = Filter::with_default_radius(FilterKind::Mitchell);
+    assert!(mitchell.evaluate(1.5, 0.0) < 0.0);
+    let lanczos = Filter::with_default_radius(FilterKind::Lanczos);
+    assert!(lanczos.evaluate(1.5, 0.0) < 0.0);
+}
@@ -0,0 +1,38 @@
+// The floating point type used for all geometry and color math, f32 by default and f64 with the
+// f64 feature. Large scenes like the radius 1000 ground sphere lose enough precision in f32 to show
+// in the image. Check both with
+//   cargo test && cargo test-f64
+// which is what ci runs too, cargo test-f64 being an alias in .cargo/config.toml
+
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+
+#[cfg(feature = "f64")]
+pub type Float = f64;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+// for the file formats and the c interface, which always use 32 bit floats. In the default build the
+// cast does nothing, which clippy would otherwise point out.
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f32(value: Float) -> f32 {
+    return value as f32;
+}
+// for working something out more precisely than the default build would, a no-op with the f64 feature
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f64(value: Float) -> f64 {
+    return value as f64;
+}
+#[allow(clippy::unnecessary_cast)]
+pub fn from_f64(value: f64) -> Float {
+    return value as Float;
+}
+
+// Bounds the rounding error of n floating point operations in a row: a value worked out with n
+// roundings is within gamma(n) times its magnitude of the exact result. From pbrt's error analysis.
+pub fn gamma(n: i32) -> Float {
+    let e = (n as Float) * Float::EPSILON * 0.5;
+    return e / (1.0 - e);
+}
@@ -0,0 +1,379 @@
+use std::{ fs::File, io::{ self, BufWriter, Error, ErrorKind, Read, Write } };
+
+use crate::{ color::Color, interval::Interval, filter::Filter, image::{ Image, PixelRect }, float::Float };
+
+// accumulates linear radiance per pixel so that samples can be added in any order
+// (one pixel at a time, or one pass over the whole image at a time)
+pub struct FrameBuffer {
+    width: i32,
+    height: i32,
+    // filter weighted sums of the samples splatted into each pixel and the sum of their weights
+    sums: Vec<Color>,
+    weights: Vec<Float>,
+    // number of camera samples taken for each pixel, which may differ from the number of samples
+    // that contributed to it once a filter spreads samples into neighbouring pixels
+    samples: Vec<u32>,
+    // running mean and sum of squared differences of each pixel's luminance (welford's algorithm),
+    // used to estimate how noisy a pixel still is
+    means: Vec<Float>,
+    m2s: Vec<Float>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: i32, height: i32) -> FrameBuffer {
+        let len = (width.max(0) as usize) * (height.max(0) as usize);
+        FrameBuffer {
+            width,
+            height,
+            sums: vec![Color::new(0.0, 0.0, 0.0); len],
+            weights: vec![0.0; len],
+            samples: vec![0; len],
+            means: vec![0.0; len],
+            m2s: vec![0.0; len],
+        }
+    }
+    pub fn width(&self) -> i32 {
+        return self.width;
+    }
+    pub fn height(&self) -> i32 {
+        return self.height;
+    }
+    fn index(&self, i: i32, j: i32) -> usize {
+        return (j * self.width + i) as usize;
+    }
+    // adds a sample taken for the pixel at i,j to that pixel only
+    pub fn add_sample(&mut self, i: i32, j: i32, color: Color) {
+        let index = self.index(i, j);
+        self.sums[index] += color;
+        self.weights[index] += 1.0;
+        self.record_sample(i, j, color);
+    }
+    // adds a sample taken for the pixel at i,j at the given offset from its center (in pixels),
+    // splatting it into every pixel within the filter's radius
+    pub fn add_filtered_sample(
+        &mut self,
+        i: i32,
+        j: i32,
+        offset: (Float, Float),
+        color: Color,
+        filter: &Filter
+    ) {
+        let x = (i as Float) + offset.0;
+        let y = (j as Float) + offset.1;
+        let min_i = i32::max(Float::ceil(x - filter.radius) as i32, 0);
+        let max_i = i32::min(Float::floor(x + filter.radius) as i32, self.width - 1);
+        let min_j = i32::max(Float::ceil(y - filter.radius) as i32, 0);
+        let max_j = i32::min(Float::floor(y + filter.radius) as i32, self.height - 1);
+        for pj in min_j..=max_j {
+            for pi in min_i..=max_i {
+                let weight = filter.evaluate((pi as Float) - x, (pj as Float) - y);
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = self.index(pi, pj);
+                self.sums[index] += weight * color;
+                self.weights[index] += weight;
+            }
+        }
+        self.record_sample(i, j, color);
+    }
+    // the per-pixel statistics only ever look at the pixel's own samples, not filtered neighbours
+    fn record_sample(&mut self, i: i32, j: i32, color: Color) {
+        let index = self.index(i, j);
+        self.samples[index] += 1;
+
+        let luminance = color.luminance();
+        let delta = luminance - self.means[index];
+        self.means[index] += delta / (self.samples[index] as Float);
+        self.m2s[index] += delta * (luminance - self.means[index]);
+    }
+    pub fn sample_count(&self, i: i32, j: i32) -> u32 {
+        return self.samples[self.index(i, j)];
+    }
+    // sample variance of the pixel's luminance, zero until there are at least two samples
+    pub fn variance(&self, i: i32, j: i32) -> Float {
+        let index = self.index(i, j);
+        if self.samples[index] < 2 {
+            return 0.0;
+        }
+        return self.m2s[index] / ((self.samples[index] - 1) as Float);
+    }
+    // standard error of the pixel's mean luminance relative to its brightness, with a floor on the
+    // brightness so that near-black pixels are not held to an impossible standard
+    pub fn relative_error(&self, i: i32, j: i32) -> Float {
+        let n = self.sample_count(i, j);
+        if n < 2 {
+            return Float::INFINITY;
+        }
+        let standard_error = Float::sqrt(self.variance(i, j) / (n as Float));
+        return standard_error / Float::max(self.means[self.index(i, j)], 0.05);
+    }
+    // the weighted average of all samples that reached the pixel at i,j so far
+    pub fn pixel(&self, i: i32, j: i32) -> Color {
+        let index = self.index(i, j);
+        // filters with negative lobes can leave a pixel with (nearly) cancelling weights early on
+        if Float::abs(self.weights[index]) < 1e-6 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        return self.sums[index] * (1.0 / self.weights[index]);
+    }
+    // the current estimate of every pixel
+    pub fn resolve(&self) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                image.set(i, j, self.pixel(i, j));
+            }
+        }
+        return image;
+    }
+    // a copy of the pixels in rect, as a framebuffer of rect's size
+    pub fn extract(&self, rect: &PixelRect) -> FrameBuffer {
+        let mut part = FrameBuffer::new(rect.width, rect.height);
+        for j in 0..rect.height {
+            for i in 0..rect.width {
+                let from = self.index(rect.x + i, rect.y + j);
+                let to = part.index(i, j);
+                part.sums[to] = self.sums[from];
+                part.weights[to] = self.weights[from];
+                part.samples[to] = self.samples[from];
+                part.means[to] = self.means[from];
+                part.m2s[to] = self.m2s[from];
+            }
+        }
+        return part;
+    }
+    // adds everything in other, whose top left pixel lands on x,y, as if its samples had been added
+    // here. The statistics are combined with chan et al.'s parallel form of welford's algorithm.
+    pub fn merge(&mut self, other: &FrameBuffer, x: i32, y: i32) {
+        for j in 0..other.height {
+            for i in 0..other.width {
+                let from = other.index(i, j);
+                let to = self.index(x + i, y + j);
+                self.sums[to] += other.sums[from];
+                self.weights[to] += other.weights[from];
+                let (n_a, n_b) = (self.samples[to] as Float, other.samples[from] as Float);
+                if n_b == 0.0 {
+                    continue;
+                }
+                let n = n_a + n_b;
+                let delta = other.means[from] - self.means[to];
+                self.means[to] += delta * n_b / n;
+                self.m2s[to] += other.m2s[from] + delta * delta * n_a * n_b / n;
+                self.samples[to] += other.samples[from];
+            }
+        }
+    }
+    // writes everything accumulated so far, little endian, so that adding samples to the
+    // framebuffer read back with read_raw carries on exactly where this one left off
+    pub fn write_raw(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&self.width.to_le_bytes())?;
+        out.write_all(&self.height.to_le_bytes())?;
+        for index in 0..self.sums.len() {
+            let sum = self.sums[index];
+            for value in [sum.x(), sum.y(), sum.z(), self.weights[index], self.means[index], self.m2s[index]] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            out.write_all(&self.samples[index].to_le_bytes())?;
+        }
+        return Ok(());
+    }
+    // how many bytes write_raw writes for a width x height framebuffer, None if that is more than a
+    // u64 can count
+    pub fn raw_len(width: i32, height: i32) -> Option<u64> {
+        // six floats and a u32 sample count per pixel
+        let pixel_len = 6 * (size_of::<Float>() as u64) + 4;
+        return (width.max(0) as u64)
+            .checked_mul(height.max(0) as u64)?
+            .checked_mul(pixel_len)?
+            .checked_add(8);
+    }
+    // Reads a framebuffer written by write_raw. The size at the start comes from a file or a socket,
+    // so a framebuffer that would take more than max_len bytes is refused before anything is
+    // allocated for it.
+    pub fn read_raw(input: &mut dyn Read, max_len: u64) -> io::Result<FrameBuffer> {
+        let mut word = [0u8; 4];
+        let mut read_word = |input: &mut dyn Read| -> io::Result<[u8; 4]> {
+            input.read_exact(&mut word)?;
+            Ok(word)
+        };
+        let width = i32::from_le_bytes(read_word(input)?);
+        let height = i32::from_le_bytes(read_word(input)?);
+        if width <= 0 || height <= 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "framebuffer has an invalid size"));
+        }
+        match FrameBuffer::raw_len(width, height) {
+            Some(len) if len <= max_len => {}
+            _ => {
+                return Err(
+                    Error::new(ErrorKind::InvalidData, format!("a {}x{} framebuffer is bigger than expected", width, height))
+                );
+            }
+        }
+        let mut framebuffer = FrameBuffer::new(width, height);
+        for index in 0..framebuffer.sums.len() {
+            let mut values = [0.0; 6];
+            for value in values.iter_mut() {
+                let mut bytes = [0u8; size_of::<Float>()];
+                input.read_exact(&mut bytes)?;
+                *value = Float::from_le_bytes(bytes);
+            }
+            framebuffer.sums[index] = Color::new(values[0], values[1], values[2]);
+            framebuffer.weights[index] = values[3];
+            framebuffer.means[index] = values[4];
+            framebuffer.m2s[index] = values[5];
+            framebuffer.samples[index] = u32::from_le_bytes(read_word(input)?);
+        }
+        return Ok(framebuffer);
+    }
+    // visualizes where samples went: black for none, then blue through green to red at max_samples
+    // only the pixels in rect are written
+    pub fn write_sample_heatmap(&self, path: &str, max_samples: u32, rect: &PixelRect) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(format!("P3\n{} {}\n255\n", rect.width, rect.height).as_bytes())?;
+        for j in rect.y..rect.y + rect.height {
+            for i in rect.x..rect.x + rect.width {
+                let count = self.sample_count(i, j);
+                let heat = if count == 0 {
+                    Color::new(0.0, 0.0, 0.0)
+                } else {
+                    FrameBuffer::heat_color((count as Float) / (max_samples.max(1) as Float))
+                };
+                let [r, g, b] = heat.as_i32();
+                file.write_all(format!("{} {} {}\n", r, g, b).as_bytes())?;
+            }
+        }
+        return file.flush();
+    }
+    fn heat_color(x: Float) -> Color {
+        let x = Interval::new(0.0, 1.0).clamp(x);
+        return if x < 0.5 {
+            Color::new(0.0, 2.0 * x, 1.0 - 2.0 * x)
+        } else {
+            Color::new(2.0 * x - 1.0, 2.0 - 2.0 * x, 0.0)
+        };
+    }
+}
+
+#[test]
+fn test_framebuffer_average() {
+    let mut fb = FrameBuffer::new(2, 2);
+    fb.add_sample(1, 0, Color::new(1.0, 0.0, 0.0));
+    fb.add_sample(1, 0, Color::new(0.0, 1.0, 0.0));
+    assert_eq!(fb.sample_count(1, 0), 2);
+    assert_eq!(fb.sample_count(0, 1), 0);
+    let c = fb.pixel(1, 0);
+    assert_eq!(c.x(), 0.5);
+    assert_eq!(c.y(), 0.5);
+    assert_eq!(c.z(), 0.0);
+    assert_eq!(fb.pixel(0, 1).x(), 0.0);
+}
+
+#[test]
+fn test_framebuffer_variance() {
+    let mut fb = FrameBuffer::new(1, 1);
+    assert_eq!(fb.variance(0, 0), 0.0);
+    assert_eq!(fb.relative_error(0, 0), Float::INFINITY);
+    fb.add_sample(0, 0, Color::new(1.0, 1.0, 1.0));
+    fb.add_sample(0, 0, Color::new(1.0, 1.0, 1.0));
+    assert!(fb.variance(0, 0).abs() < 1e-6);
+    assert!(fb.relative_error(0, 0) < 1e-3);
+    fb.add_sample(0, 0, Color::new(0.0, 0.0, 0.0));
+    fb.add_sample(0, 0, Color::new(0.0, 0.0, 0.0));
+    // luminance samples 1, 1, 0, 0 have a sample variance of 1/3
+    assert!((fb.variance(0, 0) - 1.0 / 3.0).abs() < 1e-5);
+}
+
+#[test]
+fn test_framebuffer_box_filter_matches_plain_average() {
+    let filter = Filter::default();
+    let mut filtered = FrameBuffer::new(3, 3);
+    let mut plain = FrameBuffer::new(3, 3);
+    for (offset, color) in [
+        ((0.1, -0.2), Color::new(1.0, 0.0, 0.0)),
+        ((-0.4, 0.3), Color::new(0.0, 1.0, 0.0)),
+    ] {
+        filtered.add_filtered_sample(1, 1, offset, color, &filter);
+        plain.add_sample(1, 1, color);
+    }
+    assert_eq!(filtered.pixel(1, 1).x(), plain.pixel(1, 1).x());
+    assert_eq!(filtered.pixel(1, 1).y(), plain.pixel(1, 1).y());
+    assert_eq!(filtered.pixel(0, 1).x(), 0.0);
+    assert_eq!(filtered.pixel(2, 1).y(), 0.0);
+}
+
+#[test]
+fn test_framebuffer_wide_filter_splats_into_neighbours() {
+    use crate::filter::FilterKind;
+    let filter = Filter::with_default_radius(FilterKind::Tent);
+    let mut fb = FrameBuffer::new(3, 1);
+    fb.add_filtered_sample(1, 0, (0.25, 0.0), Color::new(1.0, 1.0, 1.0), &filter);
+    assert_eq!(fb.sample_count(1, 0), 1);
+    assert_eq!(fb.sample_count(2, 0), 0);
+    // the sample is within one pixel of both pixel 1 and pixel 2, but not pixel 0
+    assert_eq!(fb.pixel(1, 0).x(), 1.0);
+    assert_eq!(fb.pixel(2, 0).x(), 1.0);
+    assert_eq!(fb.pixel(0, 0).x(), 0.0);
+}
+
+#[test]
+fn test_framebuffer_raw_round_trip() {
+    let mut fb = FrameBuffer::new(3, 2);
+    fb.add_sample(2, 1, Color::new(0.25, 0.5, 1.0));
+    fb.add_sample(2, 1, Color::new(0.75, 0.5, 0.0));
+    fb.add_sample(0, 0, Color::new(2.0, 2.0, 2.0));
+    let mut bytes = Vec::new();
+    fb.write_raw(&mut bytes).unwrap();
+    assert_eq!(FrameBuffer::raw_len(3, 2), Some(bytes.len() as u64));
+    let mut read = FrameBuffer::read_raw(&mut bytes.as_slice(), bytes.len() as u64).unwrap();
+    assert_eq!(read.width(), 3);
+    assert_eq!(read.sample_count(2, 1), 2);
+    assert_eq!(read.pixel(2, 1), fb.pixel(2, 1));
+    assert_eq!(read.variance(2, 1), fb.variance(2, 1));
+    // adding to the copy continues the statistics rather than starting them over
+    read.add_sample(0, 0, Color::new(0.0, 0.0, 0.0));
+    fb.add_sample(0, 0, Color::new(0.0, 0.0, 0.0));
+    assert_eq!(read.variance(0, 0), fb.variance(0, 0));
+    assert!(FrameBuffer::read_raw(&mut &bytes[..10], bytes.len() as u64).is_err());
+    // a size that doesn't fit in what is allowed is refused, however much data follows it
+    assert!(FrameBuffer::read_raw(&mut bytes.as_slice(), bytes.len() as u64 - 1).is_err());
+    let header = |width: i32, height: i32| [width.to_le_bytes(), height.to_le_bytes()].concat();
+    assert!(matches!(FrameBuffer::read_raw(&mut header(1 << 20, 1 << 20).as_slice(), 1 << 30), Err(e) if e.kind() == ErrorKind::InvalidData));
+    // and one too big to even count the bytes of
+    assert_eq!(FrameBuffer::raw_len(i32::MAX, i32::MAX), None);
+    assert!(matches!(FrameBuffer::read_raw(&mut header(i32::MAX, i32::MAX).as_slice(), u64::MAX), Err(e) if e.kind() == ErrorKind::InvalidData));
+}
+
+#[test]
+fn test_framebuffer_extract_and_merge() {
+    let filter = Filter::new(crate::filter::FilterKind::Tent, 1.0);
+    let samples = [(0, 0, 1.0), (1, 0, 0.5), (1, 0, 0.0), (2, 1, 2.0), (2, 1, 1.0), (1, 1, 0.25)];
+    let mut whole = FrameBuffer::new(3, 2);
+    // the same samples split between two framebuffers
+    let mut first = FrameBuffer::new(3, 2);
+    let mut second = FrameBuffer::new(3, 2);
+    for (n, (i, j, value)) in samples.into_iter().enumerate() {
+        let color = Color::new(value, value, value);
+        whole.add_filtered_sample(i, j, (0.2, -0.1), color, &filter);
+        let target = if n % 2 == 0 { &mut first } else { &mut second };
+        target.add_filtered_sample(i, j, (0.2, -0.1), color, &filter);
+    }
+    first.merge(&second, 0, 0);
+    for j in 0..2 {
+        for i in 0..3 {
+            assert_eq!(first.sample_count(i, j), whole.sample_count(i, j));
+            assert!((first.pixel(i, j).x() - whole.pixel(i, j).x()).abs() < 1e-5);
+            assert!((first.variance(i, j) - whole.variance(i, j)).abs() < 1e-5);
+        }
+    }
+
+    // a window cut out of one framebuffer lands in the same place when merged into an empty one
+    let window = PixelRect::new(1, 0, 2, 2);
+    let part = whole.extract(&window);
+    assert_eq!(part.width(), 2);
+    let mut empty = FrameBuffer::new(3, 2);
+    empty.merge(&part, window.x, window.y);
+    assert_eq!(empty.sample_count(0, 0), 0);
+    assert_eq!(empty.pixel(2, 1), whole.pixel(2, 1));
+    assert_eq!(empty.variance(2, 1), whole.variance(2, 1));
+}
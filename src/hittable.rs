@@ -1,35 +1,50 @@
-use std::rc::Rc;
-
 use crate::{
     ray::Ray,
     point3d::Point3D,
     vec3::{ Vec3, Dot },
     interval::Interval,
-    material::Material,
+    aabb::Aabb,
+    float::Float,
 };
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct HitRecord {
     pub p: Point3D,
     pub normal: Vec3,
-    pub t: f32,
+    pub t: Float,
+    // surface coordinates of the hit, used for texture lookups
+    pub u: Float,
+    pub v: Float,
     pub front_face: bool,
-    pub material: Rc<dyn Material>,
+    // how far p can be from the exact hit in each axis because of rounding, 0 unless the object
+    // works it out. spawn_ray uses it to start new rays safely off the surface.
+    pub error: Vec3,
+    // index of the surface's material in the world's materials
+    pub material: usize,
+    // index of the hit object in the world's list of objects, filled in by HittableList
+    pub object_id: usize,
 }
 
 impl HitRecord {
     pub fn new(
         p: Point3D,
         normal: Point3D,
-        t: f32,
+        t: Float,
+        u: Float,
+        v: Float,
         front_face: bool,
-        material: Rc<dyn Material>
+        material: usize
     ) -> HitRecord {
         HitRecord {
             p,
             normal,
             t,
+            u,
+            v,
             front_face,
+            error: Vec3::splat(0.0),
             material,
+            object_id: 0,
         }
     }
     // our normals always point against the ray, so we must store which side of the surface the ray is on
@@ -39,10 +54,37 @@ impl HitRecord {
         self.front_face = ray.direction().dot(*outward_normal) < 0.0;
         self.normal = if self.front_face { *outward_normal } else { -*outward_normal };
     }
+    // A ray leaving the surface in direction. It starts off the surface along the normal, on the
+    // side direction goes, just far enough that p's rounding error can't put it back on the wrong
+    // side, so it can't hit the surface it leaves. This is instead of ignoring hits closer than a
+    // fixed distance, which is far too much for tiny objects and not enough for huge ones.
+    pub fn spawn_ray(&self, direction: Vec3) -> Ray {
+        let distance = self.normal.abs().dot(self.error);
+        let offset = if direction.dot(self.normal) < 0.0 { -distance * self.normal } else { distance * self.normal };
+        let origin = self.p + offset;
+        // the addition rounds too, so step to the next float away from the surface
+        let away = |value: Float, offset: Float| -> Float {
+            return if offset > 0.0 {
+                value.next_up()
+            } else if offset < 0.0 {
+                value.next_down()
+            } else {
+                value
+            };
+        };
+        let origin = Point3D::new(away(origin.x(), offset.x()), away(origin.y(), offset.y()), away(origin.z(), offset.z()));
+        return Ray::new(origin, direction);
+    }
 }
 
 // this trait is intended to be implemented for any "object" that a ray might hit
 pub trait Hittable {
     // a ray only "counts" if it is within tmin and tmax
     fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord>;
+    // a box that every hit falls inside
+    fn bounding_box(&self) -> Aabb;
+    // how many objects hit records from this can come from, numbered from 0 in their object_id
+    fn object_count(&self) -> usize {
+        return 1;
+    }
 }
@@ -1,22 +1,37 @@
-use std::rc::Rc;
-
-use crate::{ hittable::{ Hittable, HitRecord }, ray::Ray, interval::Interval };
+use crate::{ hittable::{ Hittable, HitRecord }, ray::Ray, interval::Interval, primitive::Primitive, aabb::Aabb };
 
+// Objects are numbered one after the other, an object holding several takes up several numbers. The
+// first number of each object is worked out as it is added rather than on every ray.
+#[derive(Clone, Debug)]
 pub struct HittableList {
-    // Rc is similar to shared_ptr in c++
-    // shared pointers allow multiple geometries to share ea common instance (i.e. many spheres with same material)
-    pub objects: Vec<Rc<dyn Hittable>>,
+    objects: Vec<Primitive>,
+    first_ids: Vec<usize>,
+    object_count: usize,
 }
 
 impl HittableList {
     pub fn new() -> HittableList {
-        return HittableList { objects: Vec::new() };
+        return HittableList { objects: Vec::new(), first_ids: Vec::new(), object_count: 0 };
     }
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.first_ids.clear();
+        self.object_count = 0;
+    }
+    pub fn add(&mut self, object: impl Into<Primitive>) {
+        let object = object.into();
+        self.first_ids.push(self.object_count);
+        self.object_count += object.object_count();
+        self.objects.push(object);
+    }
+    pub fn objects(&self) -> &[Primitive] {
+        return &self.objects;
     }
-    pub fn add(&mut self, object: Rc<dyn Hittable>) {
-        self.objects.push(object)
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        return HittableList::new();
     }
 }
 
@@ -24,13 +39,45 @@ impl Hittable for HittableList {
     fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
         let mut closest_so_far = ray_t.max;
         let mut hit_record = None;
-        for object in &self.objects {
-            if let Some(hit) = object.hit(ray, &Interval::new(ray_t.min, closest_so_far)) {
+        for (object, first_id) in self.objects.iter().zip(&self.first_ids) {
+            if let Some(mut hit) = object.hit(ray, &Interval::new(ray_t.min, closest_so_far)) {
                 closest_so_far = hit.t;
+                hit.object_id += first_id;
                 hit_record = Some(hit);
             }
         }
 
         return hit_record;
     }
+    fn bounding_box(&self) -> Aabb {
+        return self.objects.iter().fold(Aabb::EMPTY, |bounds, object| bounds.union(&object.bounding_box()));
+    }
+    fn object_count(&self) -> usize {
+        return self.object_count;
+    }
+}
+
+#[cfg(test)]
+use crate::{ sphere::Sphere, sphere_batch::SphereBatch, point3d::Point3D, vec3::Vec3, float::Float };
+
+#[test]
+fn test_list_object_ids() {
+    let mut batch: SphereBatch = SphereBatch::new();
+    for i in 0..3 {
+        batch.add(Sphere::new(Point3D::new(i as Float * 3.0, 0.0, -5.0), 1.0, 0));
+    }
+    let mut list = HittableList::new();
+    list.add(batch);
+    list.add(Sphere::new(Point3D::new(0.0, 10.0, -5.0), 1.0, 1));
+    assert_eq!(list.object_count(), 4);
+    // the sphere after the batch is numbered after all three of the batch's spheres
+    let ray_t = Interval::new(0.001, Float::INFINITY);
+    let hit = list.hit(&Ray::new(Point3D::new(0.0, 10.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert_eq!(hit.object_id, 3);
+    let hit = list.hit(&Ray::new(Point3D::new(6.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert_eq!(hit.object_id, 2);
+    list.clear();
+    assert_eq!(list.object_count(), 0);
+    list.add(Sphere::new(Point3D::new(0.0, 10.0, -5.0), 1.0, 1));
+    assert_eq!(list.hit(&Ray::new(Point3D::new(0.0, 10.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap().object_id, 0);
 }
@@ -0,0 +1,136 @@
+use std::{ fs::File, io::{ self, BufWriter, Write } };
+
+use crate::{ color::Color, tonemap::DisplayTransform };
+
+// a rectangle of pixels, x and y are the top left corner
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PixelRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl PixelRect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> PixelRect {
+        PixelRect { x, y, width, height }
+    }
+    // every pixel of a width x height image
+    pub fn full(width: i32, height: i32) -> PixelRect {
+        return PixelRect::new(0, 0, width, height);
+    }
+    pub fn contains(&self, i: i32, j: i32) -> bool {
+        return i >= self.x && i < self.x + self.width && j >= self.y && j < self.y + self.height;
+    }
+    pub fn contains_rect(&self, other: &PixelRect) -> bool {
+        return other.x >= self.x &&
+            other.y >= self.y &&
+            other.x + other.width <= self.x + self.width &&
+            other.y + other.height <= self.y + self.height;
+    }
+    // the rect with margin more pixels on every side, cut down to what is inside bounds
+    pub fn grown(&self, margin: i32, bounds: &PixelRect) -> PixelRect {
+        let x0 = i32::max(self.x - margin, bounds.x);
+        let y0 = i32::max(self.y - margin, bounds.y);
+        let x1 = i32::min(self.x + self.width + margin, bounds.x + bounds.width);
+        let y1 = i32::min(self.y + self.height + margin, bounds.y + bounds.height);
+        return PixelRect::new(x0, y0, i32::max(x1 - x0, 0), i32::max(y1 - y0, 0));
+    }
+}
+
+// a finished grid of linear colors, stored row by row from the top left
+pub struct Image {
+    width: i32,
+    height: i32,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: i32, height: i32) -> Image {
+        Image { width, height, pixels: vec![Color::new(0.0, 0.0, 0.0); (width * height) as usize] }
+    }
+    pub fn width(&self) -> i32 {
+        return self.width;
+    }
+    pub fn height(&self) -> i32 {
+        return self.height;
+    }
+    pub fn pixels(&self) -> &[Color] {
+        return &self.pixels;
+    }
+    fn index(&self, i: i32, j: i32) -> usize {
+        return (j * self.width + i) as usize;
+    }
+    pub fn get(&self, i: i32, j: i32) -> Color {
+        return self.pixels[self.index(i, j)];
+    }
+    pub fn set(&mut self, i: i32, j: i32, color: Color) {
+        let index = self.index(i, j);
+        self.pixels[index] = color;
+    }
+    // a new image holding just the pixels inside rect
+    pub fn crop(&self, rect: &PixelRect) -> Image {
+        let mut cropped = Image::new(rect.width, rect.height);
+        for j in 0..rect.height {
+            for i in 0..rect.width {
+                cropped.set(i, j, self.get(rect.x + i, rect.y + j));
+            }
+        }
+        return cropped;
+    }
+    // sets every pixel outside rect to black
+    pub fn clear_outside(&mut self, rect: &PixelRect) {
+        for j in 0..self.height {
+            for i in 0..self.width {
+                if !rect.contains(i, j) {
+                    self.set(i, j, Color::new(0.0, 0.0, 0.0));
+                }
+            }
+        }
+    }
+    pub fn write_ppm(&self, path: &str, display: &DisplayTransform) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(format!("P3\n{} {}\n255\n", self.width, self.height).as_bytes())?;
+        for j in 0..self.height {
+            for i in 0..self.width {
+                // images are generally stored in gamma space, so here we convert our linear values into the gamma space
+                // for more accurate color intensity when viewing the image in image editors
+                let [r, g, b] = display.to_rgb8(self.get(i, j));
+                file.write_all(format!("{} {} {}\n", r, g, b).as_bytes())?;
+            }
+        }
+        return file.flush();
+    }
+}
+
+#[cfg(test)]
+use crate::float::Float;
+
+#[test]
+fn test_image_get_set() {
+    let mut image = Image::new(3, 2);
+    image.set(2, 1, Color::new(1.0, 2.0, 3.0));
+    assert_eq!(image.get(2, 1).y(), 2.0);
+    assert_eq!(image.get(1, 1).y(), 0.0);
+    assert_eq!(image.pixels().len(), 6);
+    assert_eq!(image.pixels()[5].z(), 3.0);
+}
+
+#[test]
+fn test_image_crop_and_clear() {
+    let mut image = Image::new(4, 3);
+    for j in 0..3 {
+        for i in 0..4 {
+            image.set(i, j, Color::new(i as Float, j as Float, 1.0));
+        }
+    }
+    let rect = PixelRect::new(1, 1, 2, 2);
+    let cropped = image.crop(&rect);
+    assert_eq!(cropped.width(), 2);
+    assert_eq!(cropped.get(0, 0).x(), 1.0);
+    assert_eq!(cropped.get(1, 1).y(), 2.0);
+    image.clear_outside(&rect);
+    assert_eq!(image.get(0, 0).z(), 0.0);
+    assert_eq!(image.get(3, 2).z(), 0.0);
+    assert_eq!(image.get(2, 2).z(), 1.0);
+}
@@ -0,0 +1,140 @@
+use std::{ io::{ self, Error, ErrorKind }, sync::Arc };
+
+use crate::{
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    matrix::Matrix4,
+    primitive::Primitive,
+    ray::Ray,
+    aabb::Aabb,
+    vec3::UnitVec,
+};
+
+// A transformed copy of an object. The object is shared, so a thousand instances of a detailed
+// model cost a thousand transforms rather than a thousand models. Rays are moved into the object's
+// own space to be tested and the hit is moved back out.
+#[derive(Clone, Debug)]
+pub struct Instance {
+    object: Arc<Primitive>,
+    transform: Matrix4,
+    inverse: Matrix4,
+    // the object's box in world space, worked out when the transform changes
+    bounds: Aabb,
+}
+
+impl Instance {
+    // fails if the transform can't be inverted, which would squash the object flat
+    pub fn new(object: Arc<Primitive>, transform: Matrix4) -> io::Result<Instance> {
+        let mut instance = Instance {
+            object,
+            transform: Matrix4::IDENTITY,
+            inverse: Matrix4::IDENTITY,
+            bounds: Aabb::EMPTY,
+        };
+        instance.set_transform(transform)?;
+        return Ok(instance);
+    }
+    pub fn object(&self) -> &Arc<Primitive> {
+        return &self.object;
+    }
+    pub fn transform(&self) -> Matrix4 {
+        return self.transform;
+    }
+    // leaves the instance where it was if the transform can't be inverted
+    pub fn set_transform(&mut self, transform: Matrix4) -> io::Result<()> {
+        let Some(inverse) = transform.inverse() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "instance transforms must be invertible"));
+        };
+        self.inverse = inverse;
+        self.transform = transform;
+        self.bounds = transform.transform_aabb(&self.object.bounding_box());
+        return Ok(());
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        // the direction isn't normalized afterwards, so t means the same distance along both rays
+        let local = Ray::new(self.inverse.transform_point(ray.origin()), self.inverse.transform_vector(ray.direction()));
+        let mut hit = self.object.hit(&local, ray_t)?;
+        hit.error = self.transform.transform_error(hit.p, hit.error);
+        hit.p = self.transform.transform_point(hit.p);
+        // the normal still faces the ray after the transform, so front_face carries over
+        hit.normal = self.inverse.transform_normal(hit.normal).unit_vec();
+        return Some(hit);
+    }
+    fn bounding_box(&self) -> Aabb {
+        return self.bounds;
+    }
+    fn object_count(&self) -> usize {
+        return self.object.object_count();
+    }
+}
+
+#[cfg(test)]
+use crate::{ sphere::Sphere, point3d::Point3D, vec3::{ Vec3, Dot }, float::Float };
+
+#[test]
+fn test_instance_hit() {
+    let sphere = Arc::new(Primitive::Sphere(Sphere::new(Point3D::new(0.0, 0.0, 0.0), 1.0, 3)));
+    // squashed to half height, then moved away down -z
+    let transform = Matrix4::translation(Vec3::new(0.0, 0.0, -5.0)) * Matrix4::scaling(Vec3::new(1.0, 0.5, 1.0));
+    let instance = Instance::new(sphere.clone(), transform).unwrap();
+    assert_eq!(instance.bounding_box(), Aabb::new(Point3D::new(-1.0, -0.5, -6.0), Point3D::new(1.0, 0.5, -4.0)));
+
+    let ray_t = Interval::new(0.001, Float::INFINITY);
+    let hit = instance.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0)), &ray_t).unwrap();
+    assert_eq!((hit.t, hit.p, hit.normal, hit.material), (2.0, Point3D::new(0.0, 0.0, -4.0), Vec3::new(0.0, 0.0, 1.0), 3));
+    assert!(hit.front_face);
+    // from above, the squashed top is half as high
+    let down = Ray::new(Point3D::new(0.0, 10.0, -5.0), Vec3::new(0.0, -1.0, 0.0));
+    assert!((instance.hit(&down, &ray_t).unwrap().t - 9.5).abs() < 1e-5);
+    // on a slope of the squashed sphere the normal is perpendicular to the surface, not the sphere's
+    let slanted = instance.hit(&Ray::new(Point3D::new(0.6, 10.0, -5.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).unwrap();
+    let tangent = Vec3::new(-slanted.p.y() * 4.0, 0.6, 0.0);
+    assert!(slanted.normal.dot(tangent).abs() < 1e-4);
+    assert!((slanted.normal.length() - 1.0).abs() < 1e-5);
+    // from inside, the normal faces back at the ray
+    let inside = instance.hit(&Ray::new(Point3D::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0)), &ray_t).unwrap();
+    assert!(!inside.front_face);
+    assert_eq!(inside.normal, Vec3::new(0.0, 0.0, -1.0));
+    assert_eq!(Arc::strong_count(&sphere), 2);
+}
+
+// rays leaving a transformed sphere, however big it was made, never hit it again on the way out
+#[test]
+fn test_instance_spawned_rays() {
+    let sphere = Arc::new(Primitive::Sphere(Sphere::new(Point3D::new(0.0, 0.0, 0.0), 1.0, 0)));
+    let ray_t = Interval::new(0.0, Float::INFINITY);
+    for scale in [1e-3, 1.0, 1e3, 1e5] {
+        let transform = Matrix4::translation(Vec3::new(0.0, 0.0, -4.0 * scale))
+            * Matrix4::rotation(Vec3::new(1.0, 2.0, 3.0), 0.8)
+            * Matrix4::scaling(Vec3::new(scale, 2.0 * scale, scale));
+        let instance = Instance::new(sphere.clone(), transform).unwrap();
+        for i in 0..100 {
+            let target = Point3D::new((i % 10) as Float * 0.1 - 0.45, (i / 10) as Float * 0.2 - 0.9, -4.0) * scale;
+            let hit = instance.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), target), &ray_t).unwrap();
+            for direction in [hit.normal, (hit.normal + target.unit_vec()).unit_vec(), (hit.normal - target.unit_vec()).unit_vec()] {
+                if direction.dot(hit.normal) > 0.0 {
+                    assert!(instance.hit(&hit.spawn_ray(direction), &ray_t).is_none(), "{} {:?}", scale, hit);
+                }
+            }
+            let inwards = instance.hit(&hit.spawn_ray(-hit.normal), &ray_t).unwrap();
+            assert!(!inwards.front_face);
+            assert!(inwards.t > 0.5 * scale);
+        }
+    }
+}
+
+#[test]
+fn test_instance_singular_transform() {
+    let sphere = Arc::new(Primitive::Sphere(Sphere::new(Point3D::new(0.0, 0.0, 0.0), 1.0, 0)));
+    let flat = Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0));
+    assert_eq!(Instance::new(sphere.clone(), flat).unwrap_err().kind(), ErrorKind::InvalidInput);
+    // a failed move keeps the instance where it was
+    let transform = Matrix4::translation(Vec3::new(0.0, 0.0, -5.0));
+    let mut instance = Instance::new(sphere, transform).unwrap();
+    assert!(instance.set_transform(flat).is_err());
+    assert_eq!(instance.transform(), transform);
+    assert_eq!(instance.bounding_box(), Aabb::new(Point3D::new(-1.0, -1.0, -6.0), Point3D::new(1.0, 1.0, -4.0)));
+}
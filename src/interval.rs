@@ -1,19 +1,21 @@
+use crate::float::Float;
+
 pub struct Interval {
-    pub min: f32,
-    pub max: f32,
+    pub min: Float,
+    pub max: Float,
 }
 
 impl Interval {
-    pub fn new(min: f32, max: f32) -> Interval {
+    pub fn new(min: Float, max: Float) -> Interval {
         Interval { min, max }
     }
-    pub fn contains(&self, x: f32) -> bool {
+    pub fn contains(&self, x: Float) -> bool {
         return self.min <= x && x <= self.max;
     }
-    pub fn surrounds(&self, x: f32) -> bool {
+    pub fn surrounds(&self, x: Float) -> bool {
         return self.min < x && x < self.max;
     }
-    pub fn clamp(&self, x: f32) -> f32 {
+    pub fn clamp(&self, x: Float) -> Float {
         if x < self.min {
             return self.min;
         }
@@ -24,8 +26,8 @@ impl Interval {
     }
 }
 
-pub const EMPTY: Interval = Interval { min: f32::INFINITY, max: f32::NEG_INFINITY };
-pub const UNIVERSE: Interval = Interval { min: f32::NEG_INFINITY, max: f32::INFINITY };
+pub const EMPTY: Interval = Interval { min: Float::INFINITY, max: Float::NEG_INFINITY };
+pub const UNIVERSE: Interval = Interval { min: Float::NEG_INFINITY, max: Float::INFINITY };
 
 #[test]
 fn test_new() {
@@ -37,17 +39,17 @@ fn test_new() {
 #[test]
 fn test_contains() {
     let i = Interval::new(0.0, 1.0);
-    assert_eq!(i.contains(0.0), true);
-    assert_eq!(i.contains(0.5), true);
-    assert_eq!(i.contains(1.1), false);
+    assert!(i.contains(0.0));
+    assert!(i.contains(0.5));
+    assert!(!i.contains(1.1));
 }
 
 #[test]
 fn test_surrounds() {
     let i = Interval::new(0.0, 1.0);
-    assert_eq!(i.surrounds(0.0), false);
-    assert_eq!(i.surrounds(0.5), true);
-    assert_eq!(i.surrounds(1.1), false);
+    assert!(!i.surrounds(0.0));
+    assert!(i.surrounds(0.5));
+    assert!(!i.surrounds(1.1));
 }
 
 #[test]
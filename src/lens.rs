@@ -0,0 +1,338 @@
+use std::{ fs, io::{ self, Error, ErrorKind } };
+
+use crate::{
+    point3d::Point3D,
+    ray::Ray,
+    vec3::{ Vec3, Dot, UnitVec },
+    sampler::sample_uniform_disk_concentric,
+    camera_model::{ CameraModel, CameraFrame },
+    float::{ Float, consts::PI },
+};
+
+// one spherical surface of a lens prescription, all lengths in millimeters
+#[derive(Clone, Copy, Debug)]
+pub struct LensElement {
+    // radius of curvature, positive when the surface bulges towards the scene, 0 for the aperture stop
+    pub radius: Float,
+    // distance along the optical axis to the next surface (or to the film for the last one)
+    pub thickness: Float,
+    // index of refraction of the medium between this surface and the next, 0 means air
+    pub ior: Float,
+    pub aperture_radius: Float,
+}
+
+impl LensElement {
+    fn ior(&self) -> Float {
+        return if self.ior == 0.0 { 1.0 } else { self.ior };
+    }
+    fn is_stop(&self) -> bool {
+        return self.radius == 0.0;
+    }
+}
+
+// Reads a lens data file in the common format used by published lens prescriptions: one surface per
+// line from the front of the lens to the back, each as radius, thickness, ior and aperture diameter,
+// separated by whitespace. Blank lines and everything after a # are ignored.
+pub fn parse_lens_file(text: &str) -> io::Result<Vec<LensElement>> {
+    let mut elements = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || Error::new(ErrorKind::InvalidData, format!("line {}: expected radius, thickness, ior and aperture", number + 1));
+        let values = line
+            .split_whitespace()
+            .map(|v| v.parse::<Float>())
+            .collect::<Result<Vec<Float>, _>>()
+            .map_err(|_| invalid())?;
+        if values.len() != 4 || values[3] <= 0.0 {
+            return Err(invalid());
+        }
+        elements.push(LensElement {
+            radius: values[0],
+            thickness: values[1],
+            ior: values[2],
+            aperture_radius: values[3] / 2.0,
+        });
+    }
+    if elements.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "lens file has no elements"));
+    }
+    return Ok(elements);
+}
+
+pub fn load_lens_file(path: &str) -> io::Result<Vec<LensElement>> {
+    return parse_lens_file(&fs::read_to_string(path)?);
+}
+
+// A camera that traces every ray through the actual glass of a lens rather than pretending the lens
+// is infinitely thin, so it gets the vignetting, distortion and bokeh of the real design for free.
+// Rays start on the film and aim at the rear element, and those the lens blocks return None.
+//
+// Internally the lens lives in its own space measured in millimeters with the film at z = 0 and the
+// scene towards -z, which lines up with camera space so only a scale is needed to leave it.
+pub struct RealisticCamera {
+    frame: CameraFrame,
+    elements: Vec<LensElement>,
+    // z of each surface's vertex on the optical axis
+    vertex_z: Vec<Float>,
+    film_width: Float,
+    film_height: Float,
+    // how many world units one millimeter is
+    world_per_mm: Float,
+    // 0 keeps the aperture stop round, 3 or more turns it into a regular polygon with that many blades
+    aperture_blades: u32,
+}
+
+impl RealisticCamera {
+    // film_diagonal is in millimeters and focus_distance in world units measured from the film,
+    // aperture_diameter (in millimeters) stops the lens down and is limited to the stop's own size
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        elements: Vec<LensElement>,
+        aspect_ratio: Float,
+        film_diagonal: Float,
+        world_per_mm: Float,
+        focus_distance: Float,
+        aperture_diameter: Option<Float>,
+        aperture_blades: u32,
+        look_from: Point3D,
+        look_at: Point3D,
+        vup: Vec3
+    ) -> io::Result<RealisticCamera> {
+        let mut elements = elements;
+        if let Some(diameter) = aperture_diameter {
+            let stop = elements
+                .iter_mut()
+                .find(|e| e.is_stop())
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "lens has no aperture stop to resize"))?;
+            stop.aperture_radius = Float::min(stop.aperture_radius, diameter / 2.0);
+        }
+        let film_width = film_diagonal * aspect_ratio / Float::sqrt(1.0 + aspect_ratio * aspect_ratio);
+        let mut camera = RealisticCamera {
+            frame: CameraFrame::new(look_from, look_at, vup),
+            vertex_z: vertex_positions(&elements),
+            elements,
+            film_width,
+            film_height: film_width / aspect_ratio,
+            world_per_mm,
+            aperture_blades,
+        };
+        camera.focus(focus_distance / world_per_mm)?;
+        return Ok(camera);
+    }
+
+    // moves the film until a point on the axis focus_distance millimeters away is imaged onto it.
+    // Moving the film changes the distance to the point, so this repeats until the film stays put.
+    fn focus(&mut self, focus_distance: Float) -> io::Result<()> {
+        let cannot_focus = || Error::new(ErrorKind::InvalidInput, "the lens cannot focus at that distance");
+        for _ in 0..16 {
+            let front_z = self.vertex_z[0];
+            let object_z = -focus_distance;
+            if object_z >= front_z {
+                return Err(cannot_focus());
+            }
+            // a ray close to the axis, so that aberrations don't move the focus
+            let height = 0.1 * self.elements[0].aperture_radius;
+            let direction = Vec3::new(height, 0.0, front_z - object_z).unit_vec();
+            let origin = Vec3::new(height, 0.0, front_z) - direction;
+            let (o, d) = self.trace(origin, direction, false).ok_or_else(cannot_focus)?;
+            if d.x() == 0.0 {
+                return Err(cannot_focus());
+            }
+            let image_z = o.z() - (o.x() / d.x()) * d.z();
+            let last = self.elements.len() - 1;
+            self.elements[last].thickness += image_z;
+            if self.elements[last].thickness <= 0.0 {
+                return Err(cannot_focus());
+            }
+            self.vertex_z = vertex_positions(&self.elements);
+            if image_z.abs() < 1e-4 {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    // follows a ray through every surface, from the film out of the front of the lens or from the
+    // scene out of the back, None if it is blocked by an aperture or totally internally reflected
+    fn trace(&self, origin: Vec3, direction: Vec3, from_film: bool) -> Option<(Vec3, Vec3)> {
+        let mut o = origin;
+        let mut d = direction;
+        let count = self.elements.len();
+        for step in 0..count {
+            let i = if from_film { count - 1 - step } else { step };
+            let element = &self.elements[i];
+            let z = self.vertex_z[i];
+            if element.is_stop() {
+                let t = (z - o.z()) / d.z();
+                if t < 0.0 {
+                    return None;
+                }
+                o += t * d;
+                if !self.inside_stop(o.x(), o.y(), element.aperture_radius) {
+                    return None;
+                }
+                continue;
+            }
+
+            let center = Vec3::new(0.0, 0.0, z + element.radius);
+            let oc = o - center;
+            let a = d.dot(d);
+            let b = oc.dot(d);
+            let c = oc.dot(oc) - element.radius * element.radius;
+            let discriminant = b * b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrt_d = Float::sqrt(discriminant);
+            // the sphere is hit twice, the lens surface is the half facing the vertex
+            let use_closer = (d.z() > 0.0) != (element.radius < 0.0);
+            let t = if use_closer { (-b - sqrt_d) / a } else { (-b + sqrt_d) / a };
+            if t <= 0.0 {
+                return None;
+            }
+            let p = o + t * d;
+            if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+
+            let mut normal = (p - center).unit_vec();
+            if normal.dot(d) > 0.0 {
+                normal = -normal;
+            }
+            let film_side = element.ior();
+            let scene_side = if i == 0 { 1.0 } else { self.elements[i - 1].ior() };
+            let eta = if from_film { film_side / scene_side } else { scene_side / film_side };
+            d = refract(d.unit_vec(), normal, eta)?;
+            o = p;
+        }
+        return Some((o, d));
+    }
+
+    fn inside_stop(&self, x: Float, y: Float, radius: Float) -> bool {
+        let r = Float::sqrt(x * x + y * y);
+        if self.aperture_blades < 3 {
+            return r <= radius;
+        }
+        // fold the angle into one blade's sector, then compare against that blade's straight edge
+        let sector = (2.0 * PI) / (self.aperture_blades as Float);
+        let angle = Float::atan2(y, x).rem_euclid(sector) - sector / 2.0;
+        return r * Float::cos(angle) <= radius * Float::cos(sector / 2.0);
+    }
+}
+
+impl CameraModel for RealisticCamera {
+    fn generate_ray(&self, film: (Float, Float), lens: (Float, Float)) -> Option<Ray> {
+        // the lens flips the image, so the top left of the picture is at the bottom right of the film
+        let film_point = Vec3::new(
+            (0.5 - film.0) * self.film_width,
+            (film.1 - 0.5) * self.film_height,
+            0.0
+        );
+        let last = self.elements.len() - 1;
+        let disk = sample_uniform_disk_concentric(lens) * self.elements[last].aperture_radius;
+        let rear_point = Vec3::new(disk.x(), disk.y(), self.vertex_z[last]);
+        let (o, d) = self.trace(film_point, (rear_point - film_point).unit_vec(), true)?;
+        let origin = self.frame.origin + self.frame.to_world(o * self.world_per_mm);
+        return Some(Ray::new(origin, self.frame.to_world(d)));
+    }
+}
+
+fn vertex_positions(elements: &[LensElement]) -> Vec<Float> {
+    let mut z = 0.0;
+    let mut positions = vec![0.0; elements.len()];
+    for (i, element) in elements.iter().enumerate().rev() {
+        z -= element.thickness;
+        positions[i] = z;
+    }
+    return positions;
+}
+
+// snell's law for a unit direction, eta is the ratio of the indices of refraction (incident over
+// transmitted), None on total internal reflection
+fn refract(d: Vec3, normal: Vec3, eta: Float) -> Option<Vec3> {
+    let cos_i = -d.dot(normal);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+    return Some(eta * d + (eta * cos_i - Float::sqrt(1.0 - sin2_t)) * normal);
+}
+
+#[cfg(test)]
+const DOUBLE_GAUSS: &str = include_str!("../lenses/dgauss.50mm.dat");
+
+#[cfg(test)]
+fn test_camera(focus_distance: Float, blades: u32) -> RealisticCamera {
+    return RealisticCamera::new(
+        parse_lens_file(DOUBLE_GAUSS).unwrap(),
+        1.5,
+        35.0,
+        0.001,
+        focus_distance,
+        None,
+        blades,
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0)
+    ).unwrap();
+}
+
+#[test]
+fn test_parse_lens_file() {
+    let elements = parse_lens_file("# a comment\n\n10.0 2.0 1.5 8.0\n0 1.0 0 4 # the stop\n-10 5 1 8\n").unwrap();
+    assert_eq!(elements.len(), 3);
+    assert_eq!(elements[0].aperture_radius, 4.0);
+    assert!(elements[1].is_stop());
+    assert_eq!(elements[1].ior(), 1.0);
+    assert_eq!(vertex_positions(&elements), vec![-8.0, -6.0, -5.0]);
+    assert!(parse_lens_file("10.0 2.0 1.5\n").is_err());
+    assert!(parse_lens_file("10.0 two 1.5 8.0\n").is_err());
+    assert!(parse_lens_file("# nothing\n").is_err());
+}
+
+#[test]
+fn test_realistic_camera_axis_ray() {
+    let camera = test_camera(1.0, 0);
+    let ray = camera.generate_ray((0.5, 0.5), (0.5, 0.5)).unwrap();
+    let d = ray.direction().unit_vec();
+    assert!(d.dot(Vec3::new(0.0, 0.0, -1.0)) > 0.9999);
+    // the image is upright: the top of the film looks up and the left looks left
+    assert!(camera.generate_ray((0.5, 0.1), (0.5, 0.5)).unwrap().direction().y() > 0.0);
+    assert!(camera.generate_ray((0.1, 0.5), (0.5, 0.5)).unwrap().direction().x() < 0.0);
+}
+
+#[test]
+fn test_realistic_camera_focuses() {
+    // every ray leaving the center of the film should cross the axis near the focus distance
+    let focus_distance = 2.0;
+    let camera = test_camera(focus_distance, 0);
+    let spread_at = |distance: Float| {
+        let mut spread: Float = 0.0;
+        for (u, v) in [(0.2, 0.5), (0.8, 0.5), (0.5, 0.3), (0.35, 0.65)] {
+            let ray = camera.generate_ray((0.5, 0.5), (u, v)).unwrap();
+            let t = (-distance - ray.origin().z()) / ray.direction().z();
+            let p = ray.at(t);
+            spread = spread.max(Float::sqrt(p.x() * p.x() + p.y() * p.y()));
+        }
+        spread
+    };
+    assert!(spread_at(focus_distance) < 0.002);
+    assert!(spread_at(focus_distance * 2.0) > 5.0 * spread_at(focus_distance));
+}
+
+#[test]
+fn test_polygonal_aperture() {
+    let round = test_camera(1.0, 0);
+    let hexagon = test_camera(1.0, 6);
+    let radius = 1.0;
+    // the hexagon's corners touch the circle but the middles of its edges are further in
+    assert!(round.inside_stop(0.99, 0.0, radius));
+    assert!(hexagon.inside_stop(0.99, 0.0, radius));
+    let edge_middle = (Float::cos(PI / 6.0), Float::sin(PI / 6.0));
+    assert!(round.inside_stop(0.95 * edge_middle.0, 0.95 * edge_middle.1, radius));
+    assert!(!hexagon.inside_stop(0.95 * edge_middle.0, 0.95 * edge_middle.1, radius));
+    assert!(hexagon.inside_stop(0.85 * edge_middle.0, 0.85 * edge_middle.1, radius));
+}
@@ -1,10 +1,49 @@
+pub mod float;
 pub mod vec3;
 pub mod point3d;
 pub mod color;
 pub mod ray;
 pub mod camera;
 pub mod hittable;
+pub mod aabb;
+pub mod bvh;
+pub mod matrix;
+pub mod instance;
+pub mod tlas;
 pub mod sphere;
+pub mod cylinder;
+pub mod cone;
+pub mod disk;
+pub mod torus;
+pub mod capsule;
+pub mod sweep;
+pub mod polynomial;
+pub mod sphere_batch;
+pub mod simd;
 pub mod hittable_list;
+pub mod primitive;
+pub mod world;
 pub mod interval;
 pub mod material;
+pub mod texture;
+pub mod cutout;
+pub mod framebuffer;
+pub mod render_settings;
+pub mod sampler;
+pub mod filter;
+pub mod tonemap;
+pub mod aov;
+pub mod image;
+pub mod denoise;
+pub mod camera_model;
+pub mod lens;
+pub mod animation;
+pub mod scene;
+pub mod checkpoint;
+pub mod distributed;
+pub mod terminal_preview;
+pub mod render_output;
+pub mod observer;
+pub mod ffi;
+#[cfg(test)]
+mod test_fixtures;
@@ -1,83 +1,763 @@
-use std::{ io::Error, rc::Rc };
+use std::{
+    env,
+    fs,
+    hash::Hasher,
+    io::{ self, Error, ErrorKind, Write },
+    net::{ TcpListener, TcpStream },
+    time::{ Duration, Instant },
+};
 
-use rand::Rng;
 use raytracer::{
     point3d::Point3D,
     camera::Camera,
-    hittable_list::HittableList,
-    sphere::Sphere,
-    material::{ Lambertian, Metal, Dielectric },
-    color::Color,
     vec3::Vec3,
+    render_settings::{ RenderSettings, ProgressiveSettings, AdaptiveSettings, CropWindow, CheckpointSettings },
+    sampler::SamplerKind,
+    filter::{ Filter, FilterKind },
+    tonemap::{ ToneMapper, TransferFunction },
+    aov::AovKind,
+    denoise::DenoiseSettings,
+    camera_model::{ CameraModel, ThinLensCamera, OrthographicCamera, EquirectangularCamera, FisheyeCamera },
+    lens::{ RealisticCamera, load_lens_file },
+    animation::{ CameraAnimation, CameraKeyframe, Interpolation, FrameSequence },
+    scene::SceneDescription,
+    checkpoint::FnvHasher,
+    terminal_preview::{ TerminalPreview, TerminalPreviewSettings },
+    render_output::RenderOutput,
+    observer::{ RenderObserver, RenderStart, RenderSnapshot, CancellationToken },
+    image::PixelRect,
+    float::Float,
 };
 
-fn main() -> Result<(), Error> {
-    println!("~~RUST RAYTRACER~~");
+// how this process takes part in rendering
+enum Role {
+    // renders the whole image itself
+    Local,
+    // listens on the address and hands tiles of the image out to workers
+    Coordinator(String),
+    // connects to the coordinator at the address and renders the tiles it is given
+    Worker(String),
+}
 
-    // create a list of hittable objects in our scene
-    let mut world = HittableList::new();
+// everything the command line controls
+struct Options {
+    settings: RenderSettings,
+    paths: OutputPaths,
+    // perspective, orthographic, equirectangular, fisheye or realistic
+    camera_model: String,
+    // angle across the image circle of the fisheye camera, in degrees
+    fisheye_fov: Float,
+    // lens prescription traced by the realistic camera
+    lens_file: String,
+    // stops the realistic camera's lens down to this diameter, in millimeters
+    aperture_diameter: Option<Float>,
+    // 0 for a round aperture, otherwise the number of straight blades shaping the bokeh
+    aperture_blades: u32,
+    // keyframes for the camera, when not set the camera stays where the scene puts it
+    animation_path: Option<String>,
+    interpolation: Interpolation,
+    // when set, every frame in the range is rendered to its own numbered file instead of one image
+    frames: Option<FrameSequence>,
+    // a scene file to render instead of the built in random spheres
+    scene_path: Option<String>,
+    // the scene hash is only known once the scene and camera are built, so these are kept here
+    // and turned into the render settings' checkpoint later
+    checkpoint_path: Option<String>,
+    checkpoint_interval: Duration,
+    resume: bool,
+    role: Role,
+    // when set, the render in progress is drawn into the terminal instead of printing the tiles left
+    terminal_preview: Option<TerminalPreviewSettings>,
+}
 
-    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(
-        Rc::new(Sphere::new(Point3D::new(0.0, -1000.0, 0.0), 1000.0, ground_material.clone()))
-    );
+// where the images end up, the library only hands them back
+#[derive(Clone)]
+struct OutputPaths {
+    image: String,
+    // intermediate progressive images are written here instead of over the image
+    preview: Option<String>,
+    // an image of how many samples each pixel received (adaptive only)
+    heatmap: Option<String>,
+    // aovs are written to <aov_prefix>_<name>.pfm
+    aov_prefix: String,
+}
 
-    let mut rng = rand::thread_rng();
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rng.gen::<f32>();
-            let center = Point3D::new(
-                (a as f32) + 0.9 * rng.gen::<f32>(),
-                0.2,
-                (b as f32) + 0.9 * rng.gen::<f32>()
-            );
+impl Default for OutputPaths {
+    fn default() -> Self {
+        OutputPaths {
+            image: String::from("./output/image.ppm"),
+            preview: None,
+            heatmap: None,
+            aov_prefix: String::from("./output/image"),
+        }
+    }
+}
 
-            if (center - Point3D::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                match choose_mat {
-                    v if v < 0.8 => {
-                        let albedo = Color::random() * Color::random();
-                        let material = Rc::new(Lambertian::new(albedo));
-                        world.add(Rc::new(Sphere::new(center, 0.2, material)));
-                    }
-                    v if v < 0.95 => {
-                        let albedo = Color::random_in_range(0.5, 1.0);
-                        let fuzziness = rng.gen_range(0.0..0.5);
-                        let material = Rc::new(Metal::new(albedo, fuzziness));
-                        world.add(Rc::new(Sphere::new(center, 0.2, material)));
-                    }
-                    _ => {
-                        let material = Rc::new(Dielectric::new(1.5));
-                        world.add(Rc::new(Sphere::new(center, 0.2, material)));
-                    }
+impl OutputPaths {
+    fn aov(&self, kind: AovKind) -> String {
+        return format!("{}_{}.pfm", self.aov_prefix, kind.name());
+    }
+}
+
+// four comma separated numbers, as taken by the crop flags
+fn parse_rect<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<[T; 4], Error> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("{} expects x0,y0,x1,y1", flag));
+    let value = value.ok_or_else(invalid)?;
+    let numbers = value
+        .split(',')
+        .map(|v| v.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<Vec<T>, Error>>()?;
+    return numbers.try_into().map_err(|_| invalid());
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, Error> {
+    return value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{} expects a value", flag)));
+}
+
+// a number of seconds, which has to be finite and not negative
+fn parse_seconds(flag: &str, value: Option<String>) -> Result<Duration, Error> {
+    let seconds: f32 = parse_value(flag, value)?;
+    return Duration::try_from_secs_f32(seconds).map_err(|_|
+        Error::new(ErrorKind::InvalidInput, format!("{} expects a number of seconds, not {}", flag, seconds))
+    );
+}
+
+// --progressive                render one sample pass over the whole image at a time
+// --refresh-passes <n>         rewrite the image every n passes (progressive only)
+// --refresh-seconds <s>        rewrite the image every s seconds (progressive only), with neither
+//                              refresh flag the image is rewritten after every pass
+// --preview <path>             write intermediate images to path instead of the output
+// --terminal-preview           draw the render into the terminal as it progresses, with its speed and eta
+// --terminal-columns <n>       width of the terminal preview in characters, 80 by default
+// --terminal-refresh-seconds <s> redraw the terminal preview every s seconds, 1 by default
+// --output <path>              where to write the final image
+// --crop <x0,y0,x1,y1>         only render the pixels from x0,y0 up to (not including) x1,y1
+// --crop-normalized <x0,y0,x1,y1> the same with the corners as fractions of the image size
+// --crop-full-frame            write the whole image with black outside the crop instead of just the crop
+// --preview-scale <n>          render at 1/n of the resolution with the same framing
+// --adaptive                   stop sampling pixels once they have converged
+// --min-samples <n>            samples every pixel takes before it may converge (adaptive only)
+// --noise-threshold <x>        relative error below which a pixel has converged (adaptive only)
+// --heatmap <path>             write an image of per-pixel sample counts (adaptive only)
+// --sampler <name>             independent, stratified, halton or sobol
+// --seed <n>                   scrambling seed for the stratified, halton and sobol samplers
+// --filter <name>              box, tent, gaussian, mitchell or lanczos reconstruction filter
+// --filter-radius <r>          filter radius in pixels, defaults to one that suits the filter
+// --exposure <ev>              brighten (positive) or darken (negative) the image by whole stops
+// --tonemap <name>             clamp, reinhard, extended-reinhard, aces or agx
+// --white <x>                  white point for extended-reinhard, 4 by default (extended-reinhard only)
+// --transfer <name>            gamma2 (plain square root) or srgb
+// --aov <name>                 also write normal, albedo, depth, material-id or object-id (repeatable)
+// --aov-prefix <path>          aovs are written to <path>_<name>.pfm
+// --denoise                    denoise the final image using the normal and albedo aovs as guides
+// --denoise-iterations <n>     more iterations remove lower frequency noise (denoise only)
+// --camera <model>             perspective, orthographic, equirectangular, fisheye or realistic
+// --fisheye-fov <degrees>      angle across the fisheye image circle (fisheye only)
+// --lens-file <path>           lens prescription to trace (realistic only)
+// --aperture-diameter <mm>     stop the lens down to this aperture (realistic only)
+// --aperture-blades <n>        polygonal aperture with n blades for shaped bokeh (realistic only)
+// --animation <path>           camera keyframes, one per line as time, look_from, look_at, vfov, focus distance
+// --interpolation <name>       linear or spline interpolation between keyframes
+// --frames <first-last>        render every frame in the range to a numbered file
+// --frame-prefix <path>        frames are written to <path>_0001.ppm, <path>_0002.ppm, ...
+// --skip-existing              do not render frames whose file already exists
+// --scene <path>               render a scene file instead of the built in random spheres
+// --checkpoint <path>          save the render's progress to path as it goes (frames are saved
+//                              to <frame-prefix>_0001.checkpoint, ... instead)
+// --checkpoint-seconds <s>     how often to save the checkpoint, 300 by default
+// --resume                     continue from the checkpoint if there is one
+// --coordinator <address>      listen on address (e.g. 0.0.0.0:7878) and have workers render the image
+// --worker <address>           render tiles for the coordinator at address, started with the same
+//                              scene and camera options as the coordinator
+// --tile-size <n>              size of the tiles rendered at a time and handed out to workers, 32 by default
+fn parse_options() -> Result<Options, Error> {
+    let mut settings = RenderSettings::default();
+    let mut paths = OutputPaths::default();
+    let mut camera_model = String::from("perspective");
+    let mut fisheye_fov = 180.0;
+    let mut lens_file = String::from("./lenses/dgauss.50mm.dat");
+    let mut aperture_diameter = None;
+    let mut aperture_blades = 0;
+    let mut animation_path = None;
+    let mut interpolation = Interpolation::Linear;
+    let mut frame_range = None;
+    let mut frame_prefix = String::from("./output/frame");
+    let mut skip_existing = false;
+    let mut scene_path = None;
+    let mut checkpoint_path = None;
+    let mut checkpoint_interval = Duration::from_secs(300);
+    let mut resume = false;
+    let mut role = Role::Local;
+    let mut progressive = ProgressiveSettings::default();
+    let mut is_progressive = false;
+    let mut adaptive = AdaptiveSettings::default();
+    let mut is_adaptive = false;
+    let mut filter_radius = None;
+    let mut tone_mapper = None;
+    let mut white = None;
+    let mut denoise = DenoiseSettings::default();
+    let mut is_denoised = false;
+    let mut terminal_preview = TerminalPreviewSettings::default();
+    let mut is_terminal_preview = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--progressive" => {
+                is_progressive = true;
+            }
+            "--refresh-passes" => {
+                let passes = parse_value(&arg, args.next())?;
+                if passes == 0 {
+                    return Err(Error::new(ErrorKind::InvalidInput, "--refresh-passes expects at least 1"));
+                }
+                progressive.refresh_passes = Some(passes);
+            }
+            "--refresh-seconds" => {
+                progressive.refresh_interval = Some(parse_seconds(&arg, args.next())?);
+            }
+            "--preview" => {
+                paths.preview = Some(parse_value(&arg, args.next())?);
+            }
+            "--terminal-preview" => {
+                is_terminal_preview = true;
+            }
+            "--terminal-columns" => {
+                let columns = parse_value(&arg, args.next())?;
+                if columns == 0 {
+                    return Err(Error::new(ErrorKind::InvalidInput, "--terminal-columns expects at least 1"));
+                }
+                terminal_preview.columns = columns;
+            }
+            "--terminal-refresh-seconds" => {
+                terminal_preview.refresh_interval = parse_seconds(&arg, args.next())?;
+            }
+            "--adaptive" => {
+                is_adaptive = true;
+            }
+            "--min-samples" => {
+                let samples = parse_value(&arg, args.next())?;
+                // a pixel's noise can't be estimated from fewer
+                if samples < 2 {
+                    return Err(Error::new(ErrorKind::InvalidInput, "--min-samples expects at least 2"));
+                }
+                adaptive.min_samples = samples;
+            }
+            "--noise-threshold" => {
+                let threshold: Float = parse_value(&arg, args.next())?;
+                // no pixel ever gets below a threshold of 0 or less, so none would stop early
+                if !(threshold.is_finite() && threshold > 0.0) {
+                    return Err(Error::new(ErrorKind::InvalidInput, "--noise-threshold expects a positive number"));
+                }
+                adaptive.noise_threshold = threshold;
+            }
+            "--heatmap" => {
+                paths.heatmap = Some(parse_value(&arg, args.next())?);
+            }
+            "--sampler" => {
+                let name: String = parse_value(&arg, args.next())?;
+                settings.sampler = SamplerKind::parse(&name).ok_or_else(||
+                    Error::new(ErrorKind::InvalidInput, format!("unknown sampler {}", name))
+                )?;
+            }
+            "--seed" => {
+                settings.seed = parse_value(&arg, args.next())?;
+            }
+            "--filter" => {
+                let name: String = parse_value(&arg, args.next())?;
+                let kind = FilterKind::parse(&name).ok_or_else(||
+                    Error::new(ErrorKind::InvalidInput, format!("unknown filter {}", name))
+                )?;
+                settings.filter = Filter::with_default_radius(kind);
+            }
+            "--filter-radius" => {
+                let radius: Float = parse_value(&arg, args.next())?;
+                // a filter with no width gives every sample zero weight, which renders black
+                if !(radius.is_finite() && radius > 0.0) {
+                    return Err(Error::new(ErrorKind::InvalidInput, "--filter-radius expects a positive number of pixels"));
+                }
+                filter_radius = Some(radius);
+            }
+            "--exposure" => {
+                settings.display.exposure_ev = parse_value(&arg, args.next())?;
+            }
+            "--tonemap" => {
+                let name: String = parse_value(&arg, args.next())?;
+                tone_mapper = Some(
+                    ToneMapper::parse(&name).ok_or_else(||
+                        Error::new(ErrorKind::InvalidInput, format!("unknown tone mapper {}", name))
+                    )?
+                );
+            }
+            "--white" => {
+                let value: Float = parse_value(&arg, args.next())?;
+                if !(value.is_finite() && value > 0.0) {
+                    return Err(Error::new(ErrorKind::InvalidInput, "--white expects a positive number"));
+                }
+                white = Some(value);
+            }
+            "--transfer" => {
+                let name: String = parse_value(&arg, args.next())?;
+                settings.display.transfer = TransferFunction::parse(&name).ok_or_else(||
+                    Error::new(ErrorKind::InvalidInput, format!("unknown transfer function {}", name))
+                )?;
+            }
+            "--aov" => {
+                let name: String = parse_value(&arg, args.next())?;
+                let kind = AovKind::parse(&name).ok_or_else(||
+                    Error::new(ErrorKind::InvalidInput, format!("unknown aov {}", name))
+                )?;
+                settings.aovs.push(kind);
+            }
+            "--aov-prefix" => {
+                paths.aov_prefix = parse_value(&arg, args.next())?;
+            }
+            "--denoise" => {
+                is_denoised = true;
+            }
+            "--denoise-iterations" => {
+                denoise.iterations = parse_value(&arg, args.next())?;
+            }
+            "--camera" => {
+                camera_model = parse_value(&arg, args.next())?;
+            }
+            "--fisheye-fov" => {
+                let fov: Float = parse_value(&arg, args.next())?;
+                // past 360 degrees the edge of the image circle would wrap round and see the middle again
+                if !(fov > 0.0 && fov <= 360.0) {
+                    return Err(
+                        Error::new(ErrorKind::InvalidInput, "--fisheye-fov expects more than 0 and at most 360 degrees")
+                    );
+                }
+                fisheye_fov = fov;
+            }
+            "--lens-file" => {
+                lens_file = parse_value(&arg, args.next())?;
+            }
+            "--aperture-diameter" => {
+                let diameter: Float = parse_value(&arg, args.next())?;
+                // a closed aperture lets no light through and renders black
+                if !(diameter.is_finite() && diameter > 0.0) {
+                    return Err(
+                        Error::new(ErrorKind::InvalidInput, "--aperture-diameter expects a positive number of millimeters")
+                    );
+                }
+                aperture_diameter = Some(diameter);
+            }
+            "--aperture-blades" => {
+                aperture_blades = parse_value(&arg, args.next())?;
+            }
+            "--animation" => {
+                animation_path = Some(parse_value(&arg, args.next())?);
+            }
+            "--interpolation" => {
+                let name: String = parse_value(&arg, args.next())?;
+                interpolation = Interpolation::parse(&name).ok_or_else(||
+                    Error::new(ErrorKind::InvalidInput, format!("unknown interpolation {}", name))
+                )?;
+            }
+            "--frames" => {
+                let range: String = parse_value(&arg, args.next())?;
+                frame_range = Some(
+                    FrameSequence::parse_range(&range).ok_or_else(||
+                        Error::new(ErrorKind::InvalidInput, format!("invalid frame range {}", range))
+                    )?
+                );
+            }
+            "--frame-prefix" => {
+                frame_prefix = parse_value(&arg, args.next())?;
+            }
+            "--skip-existing" => {
+                skip_existing = true;
+            }
+            "--scene" => {
+                scene_path = Some(parse_value(&arg, args.next())?);
+            }
+            "--checkpoint" => {
+                checkpoint_path = Some(parse_value(&arg, args.next())?);
+            }
+            "--checkpoint-seconds" => {
+                checkpoint_interval = parse_seconds(&arg, args.next())?;
+            }
+            "--resume" => {
+                resume = true;
+            }
+            "--coordinator" => {
+                role = Role::Coordinator(parse_value(&arg, args.next())?);
+            }
+            "--worker" => {
+                role = Role::Worker(parse_value(&arg, args.next())?);
+            }
+            "--tile-size" => {
+                let size = parse_value(&arg, args.next())?;
+                if size < 1 {
+                    return Err(Error::new(ErrorKind::InvalidInput, "--tile-size expects at least 1"));
                 }
+                settings.tile_size = size;
+            }
+            "--crop" => {
+                let [x0, y0, x1, y1] = parse_rect(&arg, args.next())?;
+                settings.crop = Some(CropWindow::Pixels(x0, y0, x1, y1));
+            }
+            "--crop-normalized" => {
+                let [x0, y0, x1, y1] = parse_rect(&arg, args.next())?;
+                settings.crop = Some(CropWindow::Normalized(x0, y0, x1, y1));
+            }
+            "--crop-full-frame" => {
+                settings.crop_full_frame = true;
+            }
+            "--preview-scale" => {
+                let scale = parse_value(&arg, args.next())?;
+                if scale == 0 {
+                    return Err(Error::new(ErrorKind::InvalidInput, "--preview-scale expects at least 1"));
+                }
+                settings.preview_scale = scale;
+            }
+            "--output" => {
+                paths.image = parse_value(&arg, args.next())?;
+            }
+            _ => {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("unknown argument {}", arg)));
             }
         }
     }
+    if is_progressive {
+        // without either refresh asked for, the image is rewritten after every pass
+        if progressive.refresh_passes.is_none() && progressive.refresh_interval.is_none() {
+            progressive.refresh_passes = Some(1);
+        }
+        settings.progressive = Some(progressive);
+    }
+    if is_adaptive {
+        settings.adaptive = Some(adaptive);
+    }
+    if is_denoised {
+        settings.denoise = Some(denoise);
+    }
+    if let Some(radius) = filter_radius {
+        settings.filter.radius = radius;
+    }
+    // built once all the flags are in, so the white point applies whichever order they came in
+    let tone_mapper = tone_mapper.unwrap_or(settings.display.tone_mapper);
+    settings.display.tone_mapper = match (tone_mapper, white) {
+        (ToneMapper::ExtendedReinhard { .. }, Some(white)) => ToneMapper::ExtendedReinhard { white },
+        (_, Some(_)) => {
+            return Err(Error::new(ErrorKind::InvalidInput, "--white only applies to --tonemap extended-reinhard"));
+        }
+        (tone_mapper, None) => tone_mapper,
+    };
+    let frames = frame_range.map(|(first, last)| FrameSequence {
+        first,
+        last,
+        prefix: frame_prefix,
+        skip_existing,
+    });
+    return Ok(Options {
+        settings,
+        paths,
+        camera_model,
+        fisheye_fov,
+        lens_file,
+        aperture_diameter,
+        aperture_blades,
+        animation_path,
+        interpolation,
+        frames,
+        scene_path,
+        checkpoint_path,
+        checkpoint_interval,
+        resume,
+        role,
+        terminal_preview: if is_terminal_preview { Some(terminal_preview) } else { None },
+    });
+}
 
-    let material_1 = Rc::new(Dielectric::new(1.5));
-    world.add(Rc::new(Sphere::new(Point3D::new(0.0, 1.0, 0.0), 1.0, material_1)));
+// the camera model chosen on the command line, placed where the keyframe puts it
+fn build_model(options: &Options, aspect_ratio: Float, key: &CameraKeyframe) -> Result<Box<dyn CameraModel>, Error> {
+    let CameraKeyframe { look_from, look_at, vfov, focus_distance, .. } = *key;
+    // camera-relative "up" direction, allowing for rotation
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let defocus_angle = 0.6;
+    let model: Box<dyn CameraModel> = match options.camera_model.as_str() {
+        "perspective" =>
+            Box::new(
+                ThinLensCamera::new(
+                    aspect_ratio,
+                    vfov,
+                    look_from,
+                    look_at,
+                    vup,
+                    defocus_angle,
+                    focus_distance
+                )
+            ),
+        "orthographic" => {
+            // frame the focus plane the same way the perspective camera does
+            let view_height = 2.0 * Float::tan(Float::to_radians(vfov) / 2.0) * focus_distance;
+            Box::new(OrthographicCamera::new(aspect_ratio, view_height, look_from, look_at, vup))
+        }
+        "equirectangular" => Box::new(EquirectangularCamera::new(look_from, look_at, vup)),
+        "fisheye" =>
+            Box::new(FisheyeCamera::new(aspect_ratio, options.fisheye_fov, look_from, look_at, vup)),
+        "realistic" => {
+            // a 35mm diagonal film with the scene's units taken as meters, the focus distance is
+            // measured from the film rather than from the lens
+            Box::new(
+                RealisticCamera::new(
+                    load_lens_file(&options.lens_file)?,
+                    aspect_ratio,
+                    35.0,
+                    0.001,
+                    focus_distance,
+                    options.aperture_diameter,
+                    options.aperture_blades,
+                    look_from,
+                    look_at,
+                    vup
+                )?
+            )
+        }
+        other => {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("unknown camera model {}", other)));
+        }
+    };
+    return Ok(model);
+}
 
-    let material_2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Rc::new(Sphere::new(Point3D::new(-4.0, 1.0, 0.0), 1.0, material_2)));
+// identifies what a render sees: the scene and everything about the camera, including the lens
+// prescription itself rather than the name of its file, which can be edited without being renamed
+fn scene_hash(scene: &SceneDescription, options: &Options, key: &CameraKeyframe) -> io::Result<u64> {
+    let mut hasher = FnvHasher::new();
+    hasher.write(&scene.hash().to_le_bytes());
+    let lens = if options.camera_model == "realistic" { load_lens_file(&options.lens_file)? } else { Vec::new() };
+    let camera = format!(
+        "{} {:?} {:?} {:?} {} {:?} {:?} {:?} {:?}",
+        options.camera_model,
+        key.look_from,
+        key.look_at,
+        key.vfov,
+        key.focus_distance,
+        options.fisheye_fov,
+        lens,
+        options.aperture_diameter,
+        options.aperture_blades
+    );
+    hasher.write(camera.as_bytes());
+    return Ok(hasher.finish());
+}
 
-    let material_3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Rc::new(Sphere::new(Point3D::new(4.0, 1.0, 0.0), 1.0, material_3)));
+// the checkpoint settings for rendering the camera at key, if checkpointing is enabled
+fn checkpoint_settings(
+    scene: &SceneDescription,
+    options: &Options,
+    key: &CameraKeyframe,
+    path: Option<String>
+) -> io::Result<Option<CheckpointSettings>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let mut checkpoint = CheckpointSettings::new(&path, scene_hash(scene, options, key)?);
+    checkpoint.interval = options.checkpoint_interval;
+    checkpoint.resume = options.resume;
+    return Ok(Some(checkpoint));
+}
 
-    let camera = Camera::new(
-        16.0 / 9.0,
-        1200,
-        500,
-        50,
-        20.0,
-        Point3D::new(13.0, 2.0, 3.0),
-        Point3D::new(0.0, 0.0, 0.0),
-        // camera-relative "up" direction, allowing for rotation
-        Vec3::new(0.0, 1.0, 0.0),
-        0.6,
-        10.0
-    );
+// Reports on a render in the console: the tiles or passes left, or the terminal preview when it is
+// enabled, and writes the progressive preview images.
+struct ConsoleObserver<'a> {
+    settings: &'a RenderSettings,
+    paths: &'a OutputPaths,
+    terminal_preview: Option<TerminalPreview>,
+    last_terminal_preview: Instant,
+    last_refresh: Instant,
+}
 
-    camera.render(&world);
+impl ConsoleObserver<'_> {
+    fn new<'a>(
+        settings: &'a RenderSettings,
+        paths: &'a OutputPaths,
+        terminal_preview: Option<TerminalPreviewSettings>
+    ) -> ConsoleObserver<'a> {
+        ConsoleObserver {
+            settings,
+            paths,
+            terminal_preview: terminal_preview.map(TerminalPreview::new),
+            last_terminal_preview: Instant::now(),
+            last_refresh: Instant::now(),
+        }
+    }
+
+    // redraws the terminal preview if it is due
+    fn update_terminal_preview(&mut self, snapshot: &RenderSnapshot) {
+        let Some(preview) = &mut self.terminal_preview else {
+            return;
+        };
+        if self.last_terminal_preview.elapsed() < preview.settings().refresh_interval {
+            return;
+        }
+        // a preview that can't be drawn is not worth stopping the render for
+        let _ = preview.draw(&mut io::stdout(), &snapshot.image(), &self.settings.display, &snapshot.status);
+        self.last_terminal_preview = Instant::now();
+    }
+}
+
+impl RenderObserver for ConsoleObserver<'_> {
+    fn on_start(&mut self, start: &RenderStart) {
+        let region = start.region;
+        if region == PixelRect::full(start.width, start.height) {
+            println!("Beginning render for {}x{}", start.width, start.height);
+        } else {
+            println!(
+                "Beginning render for {}x{} of {}x{} at {},{}",
+                region.width,
+                region.height,
+                start.width,
+                start.height,
+                region.x,
+                region.y
+            );
+        }
+        if start.resumed_samples > 0 {
+            println!("Resuming with {} samples from the checkpoint", start.resumed_samples);
+        }
+    }
+    fn on_tile_complete(&mut self, _tile: &PixelRect, tiles_left: usize, snapshot: &RenderSnapshot) {
+        if self.terminal_preview.is_some() {
+            self.update_terminal_preview(snapshot);
+        } else if self.settings.progressive.is_none() {
+            print!("Tiles remaining: {}    \r", tiles_left);
+            io::stdout().flush().unwrap();
+        }
+    }
+    fn on_pass_complete(&mut self, pass: u32, snapshot: &RenderSnapshot) {
+        let Some(progressive) = &self.settings.progressive else {
+            return;
+        };
+        if self.terminal_preview.is_none() {
+            print!("Pass {} done, {:.1} spp\r", pass, snapshot.status.samples_per_pixel());
+            io::stdout().flush().unwrap();
+        }
+        if progressive.should_refresh(pass, self.last_refresh.elapsed()) {
+            let path = self.paths.preview.as_ref().unwrap_or(&self.paths.image);
+            if let Err(e) = snapshot.image().write_ppm(path, &self.settings.display) {
+                eprintln!("Could not write the preview to {}: {}", path, e);
+            }
+            self.last_refresh = Instant::now();
+        }
+    }
+    fn on_finish(&mut self, output: &RenderOutput) {
+        // the last look at the render, denoised like the written image
+        if let Some(preview) = &mut self.terminal_preview {
+            let _ = preview.draw(&mut io::stdout(), &output.image, &self.settings.display, &output.metadata.status());
+        }
+    }
+}
+
+// writes the image, the heatmap and the aovs to where paths put them
+fn write_outputs(output: &RenderOutput, settings: &RenderSettings, paths: &OutputPaths) -> Result<(), Error> {
+    output.image.write_ppm(&paths.image, &settings.display)?;
+    let rect = output.metadata.rect;
+    if let (Some(_), Some(heatmap)) = (&settings.adaptive, &paths.heatmap) {
+        output.framebuffer.write_sample_heatmap(heatmap, output.metadata.samples_per_pixel, &rect)?;
+    }
+    if let Some(aovs) = &output.aovs {
+        for kind in &settings.aovs {
+            aovs.write_pfm(*kind, &paths.aov(*kind), &rect)?;
+        }
+    }
+    println!("\nFinished render in {:.1}s", output.metadata.elapsed.as_secs_f32());
+    return Ok(());
+}
+
+fn main() -> Result<(), Error> {
+    println!("~~RUST RAYTRACER~~");
+    let mut options = parse_options()?;
+
+    // the built in scene is the same every run, which resuming from a checkpoint relies on
+    let scene = match &options.scene_path {
+        Some(path) => SceneDescription::load(path)?,
+        None => SceneDescription::random_spheres(0),
+    };
+    let world = scene.build();
+
+    let image_width = 1200;
+    let image_height = Camera::image_height(16.0 / 9.0, image_width);
+    let aspect_ratio = (image_width as Float) / (image_height as Float);
+    let vfov = 20.0;
+    let look_from = Point3D::new(13.0, 2.0, 3.0);
+    let look_at = Point3D::new(0.0, 0.0, 0.0);
+    let focus_distance = 10.0;
+
+    let still = CameraKeyframe { time: 0.0, look_from, look_at, vfov, focus_distance };
+    let animation = match &options.animation_path {
+        Some(path) => CameraAnimation::load(path, options.interpolation)?,
+        None => CameraAnimation::new(vec![still], Interpolation::Linear),
+    };
+
+    match options.frames.clone() {
+        None => {
+            let key = animation.evaluate(0.0);
+            options.settings.checkpoint = checkpoint_settings(&scene, &options, &key, options.checkpoint_path.clone())?;
+            let model = build_model(&options, aspect_ratio, &key)?;
+            let camera = Camera::with_model(image_width, image_height, 500, 50, model);
+            match &options.role {
+                Role::Local => {
+                    let mut observer = ConsoleObserver::new(&options.settings, &options.paths, options.terminal_preview);
+                    let output = camera.render_observed(&world, &options.settings, &mut observer, &CancellationToken::new())?;
+                    write_outputs(&output, &options.settings, &options.paths)?;
+                }
+                Role::Coordinator(address) => {
+                    if !options.settings.aovs.is_empty() || options.settings.denoise.is_some() {
+                        return Err(
+                            Error::new(ErrorKind::InvalidInput, "aovs and denoising are not available when rendering with workers")
+                        );
+                    }
+                    let listener = TcpListener::bind(address)?;
+                    let hash = scene_hash(&scene, &options, &key)?;
+                    println!("Waiting for workers on {}", listener.local_addr()?);
+                    let mut observer = ConsoleObserver::new(&options.settings, &options.paths, options.terminal_preview);
+                    let cancel = CancellationToken::new();
+                    let output = camera.render_distributed(&options.settings, listener, hash, &mut observer, &cancel)?;
+                    write_outputs(&output, &options.settings, &options.paths)?;
+                }
+                Role::Worker(address) => {
+                    println!("Rendering tiles for {}", address);
+                    let stream = TcpStream::connect(address)?;
+                    let tiles = camera.serve_tiles(&world, &options.settings, stream, scene_hash(&scene, &options, &key)?)?;
+                    println!("Rendered {} tiles", tiles);
+                }
+            }
+        }
+        Some(_) if !matches!(options.role, Role::Local) => {
+            return Err(Error::new(ErrorKind::InvalidInput, "workers render a single frame, not a frame range"));
+        }
+        Some(frames) => {
+            for frame in frames.pending_frames() {
+                println!("Frame {} of {}-{}", frame, frames.first, frames.last);
+                // write under a temporary name so a frame interrupted halfway is not mistaken for a
+                // finished one by --skip-existing
+                let path = frames.frame_path(frame, "ppm");
+                options.paths.image = format!("{}.partial", path);
+                options.paths.aov_prefix = format!("{}_{:04}", frames.prefix, frame);
+                let key = animation.evaluate(frame as Float);
+                let checkpoint_path = options.checkpoint_path.as_ref().map(|_| frames.frame_path(frame, "checkpoint"));
+                options.settings.checkpoint = checkpoint_settings(&scene, &options, &key, checkpoint_path)?;
+                let model = build_model(&options, aspect_ratio, &key)?;
+                let camera = Camera::with_model(image_width, image_height, 500, 50, model);
+                let mut observer = ConsoleObserver::new(&options.settings, &options.paths, options.terminal_preview);
+                let output = camera.render_observed(&world, &options.settings, &mut observer, &CancellationToken::new())?;
+                write_outputs(&output, &options.settings, &options.paths)?;
+                fs::rename(&options.paths.image, &path)?;
+            }
+        }
+    }
 
     Ok(())
 }
+
+#[test]
+fn test_aov_path() {
+    let paths = OutputPaths::default();
+    assert_eq!(paths.aov(AovKind::MaterialId), "./output/image_material-id.pfm");
+}
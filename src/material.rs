@@ -1,13 +1,56 @@
-use rand::random;
+use std::{ fmt::Debug, sync::Arc };
+
+use crate::{
+    ray::Ray,
+    hittable::HitRecord,
+    color::Color,
+    vec3::{ Vec3, UnitVec, Dot },
+    sampler::{ Sampler, sample_uniform_sphere },
+    float::Float,
+};
+
+// This is implemented by anything that scatters rays, the built in materials and any material a user
+// of the library defines. Any randomness must be drawn from the sampler so it follows the camera
+// sample's sequence.
+pub trait Scatter: Debug + Send + Sync {
+    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)>;
+    // the surface color before lighting, used for the albedo output and denoising
+    fn albedo(&self, _hit_rec: &HitRecord) -> Color {
+        return Color::new(1.0, 1.0, 1.0);
+    }
+}
 
-use crate::{ ray::Ray, hittable::HitRecord, color::Color, vec3::{ Vec3, UnitVec, Dot } };
+// Every material the world's arena can hold. The built in ones are variants so a hit only carries
+// an index and scattering them is a plain match, anything else implements Scatter and goes in Custom
+// behind an Arc so the world stays cheap to clone and can still be shared between threads.
+#[derive(Clone, Debug)]
+pub enum Material {
+    Lambertian(Lambertian),
+    Metal(Metal),
+    Dielectric(Dielectric),
+    Custom(Arc<dyn Scatter>),
+}
 
-// This is intended to be implemented in any struct that describes a material and scatters rays
-pub trait Material {
-    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord) -> Option<(Ray, Color)>;
+impl Scatter for Material {
+    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        return match self {
+            Material::Lambertian(material) => material.scatter(ray_in, hit_rec, sampler),
+            Material::Metal(material) => material.scatter(ray_in, hit_rec, sampler),
+            Material::Dielectric(material) => material.scatter(ray_in, hit_rec, sampler),
+            Material::Custom(material) => material.scatter(ray_in, hit_rec, sampler),
+        };
+    }
+    fn albedo(&self, hit_rec: &HitRecord) -> Color {
+        return match self {
+            Material::Lambertian(material) => material.albedo(hit_rec),
+            Material::Metal(material) => material.albedo(hit_rec),
+            Material::Dielectric(material) => material.albedo(hit_rec),
+            Material::Custom(material) => material.albedo(hit_rec),
+        };
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Lambertian {
     pub albedo: Color,
 }
@@ -18,43 +61,53 @@ impl Lambertian {
     }
 }
 
-impl Material for Lambertian {
+impl Scatter for Lambertian {
     // we choose to always scatter and attenuate by R (reflectance)
     // alternatively, we can sometimes scatter (with probabilty 1 − R) with no attenuation
     // or scatter with some fixed probability p and have attenuation be albedo/p
-    fn scatter(&self, _ray_in: &Ray, hit_rec: &HitRecord) -> Option<(Ray, Color)> {
-        let mut scatter_dir = hit_rec.normal + Vec3::random_unit_vector();
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        hit_rec: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
+        let mut scatter_dir = hit_rec.normal + sample_uniform_sphere(sampler.get_2d());
 
         // catch the case where our random unit vector is opposite to the normal causing 0 scatter direction
         if scatter_dir.near_zero() {
             scatter_dir = hit_rec.normal;
         }
 
-        let scattered = Ray::new(hit_rec.p, scatter_dir);
+        let scattered = hit_rec.spawn_ray(scatter_dir);
         let attenuation = self.albedo;
         return Some((scattered, attenuation));
     }
+    fn albedo(&self, _hit_rec: &HitRecord) -> Color {
+        return self.albedo;
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Metal {
     pub albedo: Color,
-    pub fuzziness: f32,
+    pub fuzziness: Float,
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzziness: f32) -> Metal {
+    pub fn new(albedo: Color, fuzziness: Float) -> Metal {
         Metal { albedo, fuzziness }
     }
 }
 
-impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord) -> Option<(Ray, Color)> {
+impl Scatter for Metal {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_rec: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
         let reflected = Vec3::reflect(ray_in.direction().unit_vec(), hit_rec.normal);
-        let scattered = Ray::new(
-            hit_rec.p,
-            reflected + self.fuzziness * Vec3::random_unit_vector()
-        );
+        let scattered = hit_rec.spawn_ray(reflected + self.fuzziness * sample_uniform_sphere(sampler.get_2d()));
         let attenuation = self.albedo;
         return if scattered.direction().dot(hit_rec.normal) > 0.0 {
             Some((scattered, attenuation))
@@ -62,18 +115,21 @@ impl Material for Metal {
             None
         };
     }
+    fn albedo(&self, _hit_rec: &HitRecord) -> Color {
+        return self.albedo;
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Dielectric {
-    pub refraction_index: f32,
+    pub refraction_index: Float,
 }
 
 impl Dielectric {
-    pub fn new(refraction_index: f32) -> Dielectric {
+    pub fn new(refraction_index: Float) -> Dielectric {
         Dielectric { refraction_index }
     }
-    pub fn reflectance(cosine: f32, refraction_index: f32) -> f32 {
+    pub fn reflectance(cosine: Float, refraction_index: Float) -> Float {
         // use schlick's approximation for reflectance allowing for a material with reflectivity that varies with angle
         let r0 =
             ((1.0 - refraction_index) / (1.0 + refraction_index)) *
@@ -82,8 +138,13 @@ impl Dielectric {
     }
 }
 
-impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord) -> Option<(Ray, Color)> {
+impl Scatter for Dielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_rec: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let refraction_ratio = if hit_rec.front_face {
             1.0 / self.refraction_index
@@ -92,22 +153,22 @@ impl Material for Dielectric {
         };
 
         let unit_direction = ray_in.direction().unit_vec();
-        let cos_theta = f32::min(-unit_direction.dot(hit_rec.normal), 1.0);
-        let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+        let cos_theta = Float::min(-unit_direction.dot(hit_rec.normal), 1.0);
+        let sin_theta = Float::sqrt(1.0 - cos_theta * cos_theta);
 
         // when the ray is in the material with the higher refractive index there is no real solution
         // to snell's law, so the material must reflect (total internal reflection)
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
         let direction = if
             cannot_refract ||
-            Dielectric::reflectance(cos_theta, refraction_ratio) > random::<f32>()
+            Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
         {
             Vec3::reflect(unit_direction, hit_rec.normal)
         } else {
             Vec3::refract(unit_direction, hit_rec.normal, refraction_ratio)
         };
 
-        let scattered = Ray::new(hit_rec.p, direction);
+        let scattered = hit_rec.spawn_ray(direction);
         return Some((scattered, attenuation));
     }
 }
@@ -0,0 +1,199 @@
+use std::ops;
+
+use crate::{ aabb::Aabb, point3d::Point3D, vec3::{ Vec3, UnitVec }, float::{ Float, gamma } };
+
+// A 4x4 matrix in row major order, used for affine transforms of points and directions: the last row
+// is always 0 0 0 1, which every constructor and product here keeps.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Matrix4 {
+    pub rows: [[Float; 4]; 4],
+}
+
+impl Matrix4 {
+    pub const IDENTITY: Matrix4 = Matrix4 {
+        rows: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+    };
+
+    pub fn translation(offset: Vec3) -> Matrix4 {
+        let mut m = Matrix4::IDENTITY;
+        m.rows[0][3] = offset.x();
+        m.rows[1][3] = offset.y();
+        m.rows[2][3] = offset.z();
+        return m;
+    }
+    pub fn scaling(scale: Vec3) -> Matrix4 {
+        let mut m = Matrix4::IDENTITY;
+        m.rows[0][0] = scale.x();
+        m.rows[1][1] = scale.y();
+        m.rows[2][2] = scale.z();
+        return m;
+    }
+    // a rotation by angle radians around axis, counterclockwise looking down the axis towards the origin
+    pub fn rotation(axis: Vec3, angle: Float) -> Matrix4 {
+        let a = axis.unit_vec();
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        return Matrix4 {
+            rows: [
+                [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+                [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+                [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        };
+    }
+
+    pub fn transform_point(&self, p: Point3D) -> Point3D {
+        let r = &self.rows;
+        return Point3D::new(
+            r[0][0] * p.x() + r[0][1] * p.y() + r[0][2] * p.z() + r[0][3],
+            r[1][0] * p.x() + r[1][1] * p.y() + r[1][2] * p.z() + r[1][3],
+            r[2][0] * p.x() + r[2][1] * p.y() + r[2][2] * p.z() + r[2][3]
+        );
+    }
+    // directions ignore the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let r = &self.rows;
+        return Vec3::new(
+            r[0][0] * v.x() + r[0][1] * v.y() + r[0][2] * v.z(),
+            r[1][0] * v.x() + r[1][1] * v.y() + r[1][2] * v.z(),
+            r[2][0] * v.x() + r[2][1] * v.y() + r[2][2] * v.z()
+        );
+    }
+    // Bounds the error in transform_point(p) for a p that was already up to error off in each axis:
+    // that error carried through the matrix, plus the rounding of the transform itself.
+    pub fn transform_error(&self, p: Point3D, error: Vec3) -> Vec3 {
+        let r = &self.rows;
+        let abs_row = |row: usize, v: Vec3| -> Float {
+            return r[row][0].abs() * v.x() + r[row][1].abs() * v.y() + r[row][2].abs() * v.z();
+        };
+        let p = p.abs();
+        let carried = Vec3::new(abs_row(0, error), abs_row(1, error), abs_row(2, error));
+        let rounding = Vec3::new(abs_row(0, p) + r[0][3].abs(), abs_row(1, p) + r[1][3].abs(), abs_row(2, p) + r[2][3].abs());
+        return (1.0 + gamma(3)) * carried + gamma(3) * rounding;
+    }
+    // Normals stay perpendicular to their surface only when transformed by the inverse transpose, so
+    // this is called on the inverse of the transform the surface went through and multiplies by its
+    // transpose. The result is not unit length.
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let r = &self.rows;
+        return Vec3::new(
+            r[0][0] * n.x() + r[1][0] * n.y() + r[2][0] * n.z(),
+            r[0][1] * n.x() + r[1][1] * n.y() + r[2][1] * n.z(),
+            r[0][2] * n.x() + r[1][2] * n.y() + r[2][2] * n.z()
+        );
+    }
+    // the box around the transformed corners of a box
+    pub fn transform_aabb(&self, bounds: &Aabb) -> Aabb {
+        if bounds.is_empty() {
+            return Aabb::EMPTY;
+        }
+        let mut transformed = Aabb::EMPTY;
+        for corner in 0..8 {
+            let p = Point3D::new(
+                if corner & 1 == 0 { bounds.min.x() } else { bounds.max.x() },
+                if corner & 2 == 0 { bounds.min.y() } else { bounds.max.y() },
+                if corner & 4 == 0 { bounds.min.z() } else { bounds.max.z() }
+            );
+            let p = self.transform_point(p);
+            transformed = transformed.union(&Aabb::new(p, p));
+        }
+        return transformed;
+    }
+
+    // The inverse of an affine transform, from the inverse of its 3x3 part and the translation run
+    // backwards through it, or None when the transform squashes space flat and can't be undone.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let r = &self.rows;
+        // cofactors of the 3x3 part, which make up its inverse once transposed and divided by the determinant
+        let c00 = r[1][1] * r[2][2] - r[1][2] * r[2][1];
+        let c01 = r[1][2] * r[2][0] - r[1][0] * r[2][2];
+        let c02 = r[1][0] * r[2][1] - r[1][1] * r[2][0];
+        let determinant = r[0][0] * c00 + r[0][1] * c01 + r[0][2] * c02;
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        let d = 1.0 / determinant;
+        let mut inverse = Matrix4::IDENTITY;
+        inverse.rows[0] = [c00 * d, (r[0][2] * r[2][1] - r[0][1] * r[2][2]) * d, (r[0][1] * r[1][2] - r[0][2] * r[1][1]) * d, 0.0];
+        inverse.rows[1] = [c01 * d, (r[0][0] * r[2][2] - r[0][2] * r[2][0]) * d, (r[0][2] * r[1][0] - r[0][0] * r[1][2]) * d, 0.0];
+        inverse.rows[2] = [c02 * d, (r[0][1] * r[2][0] - r[0][0] * r[2][1]) * d, (r[0][0] * r[1][1] - r[0][1] * r[1][0]) * d, 0.0];
+        let translation = inverse.transform_vector(Vec3::new(r[0][3], r[1][3], r[2][3]));
+        for row in 0..3 {
+            inverse.rows[row][3] = -translation.axis(row);
+        }
+        return Some(inverse);
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        return Matrix4::IDENTITY;
+    }
+}
+
+// a * b transforms by b first and then by a
+impl ops::Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, rhs: Matrix4) -> Self::Output {
+        let mut product = Matrix4 { rows: [[0.0; 4]; 4] };
+        for row in 0..4 {
+            for column in 0..4 {
+                product.rows[row][column] = (0..4).map(|k| self.rows[row][k] * rhs.rows[k][column]).sum();
+            }
+        }
+        return product;
+    }
+}
+
+#[cfg(test)]
+use crate::{ vec3::Dot, float::consts };
+
+#[cfg(test)]
+fn assert_near(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn test_matrix_transforms() {
+    let p = Point3D::new(1.0, 2.0, 3.0);
+    assert_eq!(Matrix4::IDENTITY.transform_point(p), p);
+    assert_eq!(Matrix4::translation(Vec3::new(1.0, 0.0, -1.0)).transform_point(p), Point3D::new(2.0, 2.0, 2.0));
+    assert_eq!(Matrix4::translation(Vec3::new(1.0, 0.0, -1.0)).transform_vector(p), p);
+    assert_eq!(Matrix4::scaling(Vec3::new(2.0, 3.0, 4.0)).transform_point(p), Point3D::new(2.0, 6.0, 12.0));
+    let quarter_turn = Matrix4::rotation(Vec3::new(0.0, 0.0, 2.0), consts::FRAC_PI_2);
+    assert_near(quarter_turn.transform_point(Point3D::new(1.0, 0.0, 0.0)), Point3D::new(0.0, 1.0, 0.0));
+    // scaling then moving, in that order
+    let m = Matrix4::translation(Vec3::new(0.0, 0.0, 1.0)) * Matrix4::scaling(Vec3::splat(2.0));
+    assert_eq!(m.transform_point(p), Point3D::new(2.0, 4.0, 7.0));
+}
+
+#[test]
+fn test_matrix_inverse() {
+    let m = Matrix4::translation(Vec3::new(3.0, -1.0, 2.0))
+        * Matrix4::rotation(Vec3::new(1.0, 1.0, 0.0), 0.7)
+        * Matrix4::scaling(Vec3::new(2.0, 0.5, -1.0));
+    let inverse = m.inverse().unwrap();
+    let p = Point3D::new(1.0, 2.0, 3.0);
+    assert_near(inverse.transform_point(m.transform_point(p)), p);
+    assert_near((m * inverse).transform_point(p), p);
+    assert!(Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+
+    // a normal of the plane x + y = 0 stays perpendicular to it after a non uniform scale
+    let squash = Matrix4::scaling(Vec3::new(4.0, 1.0, 1.0));
+    let normal = squash.inverse().unwrap().transform_normal(Vec3::new(1.0, 1.0, 0.0));
+    let in_plane = squash.transform_vector(Vec3::new(1.0, -1.0, 0.0));
+    assert!(normal.dot(in_plane).abs() < 1e-6);
+}
+
+#[test]
+fn test_matrix_transform_aabb() {
+    let bounds = Aabb::new(Point3D::new(-1.0, -1.0, -1.0), Point3D::new(1.0, 1.0, 1.0));
+    let m = Matrix4::translation(Vec3::new(5.0, 0.0, 0.0)) * Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), consts::FRAC_PI_4);
+    let transformed = m.transform_aabb(&bounds);
+    let half = Float::sqrt(2.0);
+    assert_near(transformed.min, Point3D::new(5.0 - half, -1.0, -half));
+    assert_near(transformed.max, Point3D::new(5.0 + half, 1.0, half));
+    assert!(m.transform_aabb(&Aabb::EMPTY).is_empty());
+}
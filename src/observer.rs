@@ -0,0 +1,252 @@
+use std::{ fmt, io::{ Error, ErrorKind }, sync::{ atomic::{ AtomicBool, Ordering }, Arc }, time::Duration };
+
+use crate::{ framebuffer::FrameBuffer, image::{ Image, PixelRect }, render_output::RenderOutput };
+
+// Gets told how a render is getting on. Renders are split into tiles, in progressive mode every pass
+// goes over all the tiles, and the observer hears about each tile and pass as it completes. All the
+// methods do nothing by default so an observer only implements the events it cares about.
+pub trait RenderObserver {
+    fn on_start(&mut self, _start: &RenderStart) {}
+    // tiles_left counts the tiles still to go in the current pass, or in the whole render when it
+    // isn't progressive
+    fn on_tile_complete(&mut self, _tile: &PixelRect, _tiles_left: usize, _snapshot: &RenderSnapshot) {}
+    fn on_pass_complete(&mut self, _pass: u32, _snapshot: &RenderSnapshot) {}
+    fn on_finish(&mut self, _output: &RenderOutput) {}
+}
+
+// for renders nobody is watching
+pub struct NullObserver;
+
+impl RenderObserver for NullObserver {}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderStart {
+    // the resolution being rendered
+    pub width: i32,
+    pub height: i32,
+    // the pixels that take samples
+    pub region: PixelRect,
+    pub samples_per_pixel: u32,
+    pub tiles: usize,
+    // samples already in the framebuffer when the render starts, from a checkpoint
+    pub resumed_samples: u64,
+}
+
+// the render so far
+pub struct RenderSnapshot<'a> {
+    pub framebuffer: &'a FrameBuffer,
+    // the pixels that take samples, and the part of the render that ends up in the output image
+    pub region: PixelRect,
+    pub rect: PixelRect,
+    pub status: RenderStatus,
+}
+
+impl RenderSnapshot<'_> {
+    // what the output image would look like if the render stopped now, without denoising
+    pub fn image(&self) -> Image {
+        let mut image = self.framebuffer.resolve();
+        image.clear_outside(&self.region);
+        return image.crop(&self.rect);
+    }
+}
+
+// how far along a render is
+#[derive(Clone, Copy, Debug)]
+pub struct RenderStatus {
+    pub samples: u64,
+    // the part of samples that was already there when the render started, e.g. from a checkpoint
+    pub resumed_samples: u64,
+    // the samples the render takes if no pixel converges early
+    pub total_samples: u64,
+    pub pixels: u64,
+    pub elapsed: Duration,
+}
+
+impl RenderStatus {
+    // the average number of samples per pixel so far
+    pub fn samples_per_pixel(&self) -> f32 {
+        return (self.samples as f32) / (self.pixels.max(1) as f32);
+    }
+    pub fn samples_per_second(&self) -> f32 {
+        let seconds = self.elapsed.as_secs_f32();
+        let taken = self.samples.saturating_sub(self.resumed_samples);
+        return if seconds > 0.0 { (taken as f32) / seconds } else { 0.0 };
+    }
+    // assumes the remaining samples go as fast as the ones so far, adaptive renders may finish sooner,
+    // and is None until there is a rate to go by or when the wait is too long for a Duration
+    pub fn eta(&self) -> Option<Duration> {
+        let rate = self.samples_per_second();
+        if rate <= 0.0 {
+            return None;
+        }
+        let remaining = self.total_samples.saturating_sub(self.samples) as f32;
+        return Duration::try_from_secs_f32(remaining / rate).ok();
+    }
+}
+
+impl fmt::Display for RenderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rate = self.samples_per_second();
+        let rate = if rate >= 1e6 {
+            format!("{:.2}M", rate / 1e6)
+        } else if rate >= 1e3 {
+            format!("{:.1}k", rate / 1e3)
+        } else {
+            format!("{:.0}", rate)
+        };
+        let eta = match self.eta() {
+            Some(eta) => format_duration(eta),
+            None => String::from("--"),
+        };
+        return write!(
+            f,
+            "{:.1} spp | {} samples/s | elapsed {} | eta {}",
+            self.samples_per_pixel(),
+            rate,
+            format_duration(self.elapsed),
+            eta
+        );
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    return if seconds >= 3600 {
+        format!("{}h{:02}m{:02}s", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+    } else {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    };
+}
+
+// Lets another thread, or an observer, stop a render. The render checks it between tiles, saves its
+// checkpoint if it has one and returns an Interrupted error. Clones share the same flag.
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken(Arc::new(AtomicBool::new(false)))
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        return self.0.load(Ordering::Relaxed);
+    }
+}
+
+// the error a cancelled render returns
+pub fn cancelled_error() -> Error {
+    return Error::new(ErrorKind::Interrupted, "the render was cancelled");
+}
+
+#[cfg(test)]
+use crate::{
+    camera::Camera,
+    render_settings::{ RenderSettings, ProgressiveSettings },
+    scene::SceneDescription,
+    test_fixtures::spheres_camera,
+};
+
+#[cfg(test)]
+fn test_camera() -> Camera {
+    return spheres_camera(2.0, 20, 2, 0.0);
+}
+
+// records the events it gets, and cancels the render after cancel_after tiles
+#[cfg(test)]
+#[derive(Default)]
+struct RecordingObserver {
+    starts: Vec<RenderStart>,
+    tiles: Vec<(PixelRect, usize, u64)>,
+    passes: Vec<(u32, u64)>,
+    finishes: usize,
+    cancel_after: Option<(usize, CancellationToken)>,
+}
+
+#[cfg(test)]
+impl RenderObserver for RecordingObserver {
+    fn on_start(&mut self, start: &RenderStart) {
+        self.starts.push(*start);
+    }
+    fn on_tile_complete(&mut self, tile: &PixelRect, tiles_left: usize, snapshot: &RenderSnapshot) {
+        self.tiles.push((*tile, tiles_left, snapshot.status.samples));
+        if let Some((count, token)) = &self.cancel_after {
+            if self.tiles.len() >= *count {
+                token.cancel();
+            }
+        }
+    }
+    fn on_pass_complete(&mut self, pass: u32, snapshot: &RenderSnapshot) {
+        self.passes.push((pass, snapshot.status.samples));
+    }
+    fn on_finish(&mut self, _output: &RenderOutput) {
+        self.finishes += 1;
+    }
+}
+
+#[test]
+fn test_observer_events() {
+    let camera = test_camera();
+    let world = SceneDescription::random_spheres(0).build();
+    let settings = RenderSettings { tile_size: 8, ..Default::default() };
+    let mut observer = RecordingObserver::default();
+    let output = camera.render_observed(&world, &settings, &mut observer, &CancellationToken::new()).unwrap();
+    // 20x10 in 8x8 tiles is 3 by 2 tiles
+    assert_eq!(observer.starts.len(), 1);
+    assert_eq!(observer.starts[0].tiles, 6);
+    assert_eq!(observer.tiles.len(), 6);
+    assert_eq!(observer.tiles[0].0, PixelRect::new(0, 0, 8, 8));
+    assert_eq!(observer.tiles[0].1, 5);
+    assert_eq!(observer.tiles[0].2, 8 * 8 * 2);
+    assert_eq!(observer.tiles[5].1, 0);
+    assert_eq!(observer.tiles[5].2, 20 * 10 * 2);
+    assert!(observer.passes.is_empty());
+    assert_eq!(observer.finishes, 1);
+    assert_eq!(output.metadata.samples, 20 * 10 * 2);
+
+    let settings = RenderSettings { tile_size: 8, progressive: Some(ProgressiveSettings::default()), ..Default::default() };
+    let mut observer = RecordingObserver::default();
+    camera.render_observed(&world, &settings, &mut observer, &CancellationToken::new()).unwrap();
+    assert_eq!(observer.tiles.len(), 12);
+    assert_eq!(observer.passes, vec![(1, 200), (2, 400)]);
+}
+
+#[test]
+fn test_cancel_render() {
+    let camera = test_camera();
+    let world = SceneDescription::random_spheres(0).build();
+    let settings = RenderSettings { tile_size: 8, ..Default::default() };
+    let token = CancellationToken::new();
+    let mut observer = RecordingObserver { cancel_after: Some((2, token.clone())), ..Default::default() };
+    let result = camera.render_observed(&world, &settings, &mut observer, &token);
+    assert!(matches!(result, Err(e) if e.kind() == ErrorKind::Interrupted));
+    // the render stops at the first check after the token is cancelled
+    assert_eq!(observer.tiles.len(), 2);
+    assert_eq!(observer.finishes, 0);
+
+    // a token cancelled up front stops the render before its first tile
+    let mut observer = RecordingObserver::default();
+    assert!(camera.render_observed(&world, &settings, &mut observer, &token).is_err());
+    assert!(observer.tiles.is_empty());
+}
+
+#[test]
+fn test_render_status() {
+    let status = RenderStatus {
+        samples: 3_000_000,
+        resumed_samples: 1_000_000,
+        total_samples: 10_000_000,
+        pixels: 100_000,
+        elapsed: Duration::from_secs(4),
+    };
+    // the samples resumed from a checkpoint count towards the spp but not the speed
+    assert_eq!(status.samples_per_pixel(), 30.0);
+    assert_eq!(status.samples_per_second(), 500_000.0);
+    assert_eq!(status.eta(), Some(Duration::from_secs(14)));
+    assert_eq!(status.to_string(), "30.0 spp | 500.0k samples/s | elapsed 0m04s | eta 0m14s");
+    // one sample in a very long time leaves more to go than a Duration can hold
+    let slow = RenderStatus { samples: 1, resumed_samples: 0, total_samples: u64::MAX, elapsed: Duration::from_secs(1 << 40), ..status };
+    assert_eq!(slow.eta(), None);
+    assert!(slow.to_string().ends_with("eta --"));
+}
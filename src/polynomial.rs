@@ -0,0 +1,182 @@
+use crate::float::Float;
+
+// The roots of a t^2 + 2 half_b t + c, nearest first, given its discriminant half_b^2 - a c, which
+// callers can often work out more accurately from the geometry than from the coefficients. The root
+// that would subtract two similar numbers comes from the product of the roots, c / a, instead. When
+// a is 0 the one root of the line is returned twice.
+pub fn quadratic_roots(a: Float, half_b: Float, c: Float, discriminant: Float) -> Option<(Float, Float)> {
+    if a == 0.0 {
+        if half_b == 0.0 {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some((t, t));
+    }
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = Float::sqrt(discriminant);
+    let sum = half_b + (if half_b < 0.0 { -sqrtd } else { sqrtd });
+    if sum == 0.0 {
+        // half_b and the discriminant are both 0, so the line just touches 0
+        return Some((0.0, 0.0));
+    }
+    let t0 = sum / -a;
+    let t1 = -c / sum;
+    return Some(if t1 < t0 { (t1, t0) } else { (t0, t1) });
+}
+
+// at most 4 roots, without allocating
+#[derive(Clone, Copy, Debug)]
+pub struct Roots {
+    values: [f64; 4],
+    len: usize,
+}
+
+impl Roots {
+    fn new() -> Roots {
+        Roots { values: [0.0; 4], len: 0 }
+    }
+    fn push(&mut self, value: f64) {
+        // a root at the boundary between two searched ranges is found from both sides, and rounding
+        // can make a polynomial that is close to 0 everywhere look like it has a root at the ends of
+        // every piece, one more than it can have
+        if (self.len > 0 && self.values[self.len - 1] == value) || self.len == self.values.len() {
+            return;
+        }
+        self.values[self.len] = value;
+        self.len += 1;
+    }
+    pub fn as_slice(&self) -> &[f64] {
+        return &self.values[..self.len];
+    }
+}
+
+fn evaluate(coefficients: &[f64], t: f64) -> f64 {
+    return coefficients.iter().fold(0.0, |value, &coefficient| value * t + coefficient);
+}
+
+// The value at t, or exactly 0 when it is no bigger than the rounding error of working it out, which
+// for Horner's rule is bounded by 2 n epsilon times the value with every term made positive.
+fn evaluate_or_zero(coefficients: &[f64], t: f64) -> f64 {
+    let value = evaluate(coefficients, t);
+    let magnitude = coefficients.iter().fold(0.0, |value, &coefficient| value * t.abs() + coefficient.abs());
+    let error = 2.0 * (coefficients.len() as f64) * f64::EPSILON * magnitude;
+    return if value.abs() <= error { 0.0 } else { value };
+}
+
+// The real roots between lo and hi, in increasing order, of a polynomial of degree at most 4 with
+// coefficients from the highest power down. Rather than a closed form, which loses badly to rounding
+// for quartics, the roots of the derivative split the range into pieces where the polynomial only
+// rises or only falls, and each piece that changes sign holds one root found by bisection. A root
+// where the polynomial only touches 0 without crossing it, like a ray grazing a torus, is at one of
+// the derivative's roots, so those count as roots when the polynomial there is 0 to within rounding.
+pub fn real_roots(coefficients: &[f64], lo: f64, hi: f64) -> Roots {
+    let mut roots = Roots::new();
+    // a zero leading coefficient lowers the degree
+    let first = coefficients.iter().position(|&c| c != 0.0).unwrap_or(coefficients.len());
+    let coefficients = &coefficients[first..];
+    let degree = coefficients.len().saturating_sub(1);
+    if degree == 0 || lo.is_nan() || hi.is_nan() || lo > hi {
+        return roots;
+    }
+    if degree == 1 {
+        let t = -coefficients[1] / coefficients[0];
+        if lo <= t && t <= hi {
+            roots.push(t);
+        }
+        return roots;
+    }
+
+    let mut derivative = [0.0; 4];
+    for (i, &c) in coefficients[..degree].iter().enumerate() {
+        derivative[i] = c * ((degree - i) as f64);
+    }
+    let critical = real_roots(&derivative[..degree], lo, hi);
+    let mut start = lo;
+    for &end in critical.as_slice().iter().chain([hi].iter()) {
+        let (mut a, mut b) = (start, end);
+        let (fa, fb) = (evaluate_or_zero(coefficients, a), evaluate_or_zero(coefficients, b));
+        start = end;
+        // a piece only rises or falls, so one that starts or ends at 0 has no other root in it
+        if fa == 0.0 {
+            roots.push(a);
+        }
+        if fb == 0.0 {
+            roots.push(b);
+        }
+        if fa == 0.0 || fb == 0.0 || (fa < 0.0) == (fb < 0.0) {
+            continue;
+        }
+        // until the range can't be halved any more
+        loop {
+            let middle = 0.5 * (a + b);
+            if middle <= a || middle >= b {
+                break;
+            }
+            if (evaluate(coefficients, middle) < 0.0) == (fa < 0.0) {
+                a = middle;
+            } else {
+                b = middle;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    return roots;
+}
+
+#[test]
+fn test_quadratic_roots() {
+    // (t - 1)(t - 3) = t^2 - 4t + 3
+    assert_eq!(quadratic_roots(1.0, -2.0, 3.0, 1.0), Some((1.0, 3.0)));
+    assert_eq!(quadratic_roots(-1.0, 2.0, -3.0, 1.0), Some((1.0, 3.0)));
+    assert_eq!(quadratic_roots(1.0, 0.0, 1.0, -1.0), None);
+    assert_eq!(quadratic_roots(0.0, 1.0, -4.0, 1.0), Some((2.0, 2.0)));
+    assert_eq!(quadratic_roots(0.0, 0.0, 1.0, 0.0), None);
+    // roots 1e-4 and 1e4, where the naive formula loses the small one entirely in f32
+    let (near, far) = quadratic_roots(1.0, -0.5 * (1e4 + 1e-4), 1.0, 0.25 * (1e4 - 1e-4) * (1e4 - 1e-4)).unwrap();
+    assert!((near - 1e-4).abs() < 1e-9);
+    assert!((far - 1e4).abs() < 1e-2);
+}
+
+#[test]
+fn test_real_roots() {
+    // (t - 1)(t - 2)(t - 3)(t - 4)
+    let quartic = [1.0, -10.0, 35.0, -50.0, 24.0];
+    let roots = real_roots(&quartic, -10.0, 10.0);
+    assert_eq!(roots.as_slice().len(), 4);
+    for (root, expected) in roots.as_slice().iter().zip([1.0, 2.0, 3.0, 4.0]) {
+        assert!((root - expected).abs() < 1e-12);
+    }
+    assert_eq!(real_roots(&quartic, 1.5, 3.5).as_slice().len(), 2);
+    assert_eq!(real_roots(&quartic, 4.5, 10.0).as_slice().len(), 0);
+    // t^4 + 1 has no real roots, and a quartic with a zero leading coefficient is a cubic
+    assert_eq!(real_roots(&[1.0, 0.0, 0.0, 0.0, 1.0], -10.0, 10.0).as_slice().len(), 0);
+    let cubic = real_roots(&[0.0, 1.0, 0.0, -1.0, 0.0], -10.0, 10.0);
+    assert_eq!(cubic.as_slice().len(), 3);
+    for (root, expected) in cubic.as_slice().iter().zip([-1.0, 0.0, 1.0]) {
+        assert!((root - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_real_double_roots() {
+    // (t - 1)^2 (t - 3)(t - 4) only touches 0 at 1
+    let touching = real_roots(&[1.0, -9.0, 27.0, -31.0, 12.0], -10.0, 10.0);
+    assert_eq!(touching.as_slice().len(), 3);
+    for (root, expected) in touching.as_slice().iter().zip([1.0, 3.0, 4.0]) {
+        assert!((root - expected).abs() < 1e-9, "{:?}", touching);
+    }
+    // (t + 1)^2 (t - 2)^2 never crosses 0 at all
+    let squared = real_roots(&[1.0, -2.0, -3.0, 4.0, 4.0], -10.0, 10.0);
+    assert_eq!(squared.as_slice().len(), 2);
+    for (root, expected) in squared.as_slice().iter().zip([-1.0, 2.0]) {
+        assert!((root - expected).abs() < 1e-9, "{:?}", squared);
+    }
+    // a double root scaled and shifted away from whole numbers, (t - 0.3)^2 (t^2 + 1) times 1e3
+    let shifted = real_roots(&[1e3, -600.0, 1090.0, -600.0, 90.0], -10.0, 10.0);
+    assert_eq!(shifted.as_slice().len(), 1);
+    assert!((shifted.as_slice()[0] - 0.3).abs() < 1e-7, "{:?}", shifted);
+    // and a minimum just above 0 is still a miss, (t - 1)^2 + 1e-6
+    assert_eq!(real_roots(&[1.0, -2.0, 1.0 + 1e-6], -10.0, 10.0).as_slice().len(), 0);
+}
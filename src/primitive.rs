@@ -0,0 +1,254 @@
+use crate::{
+    hittable::{ Hittable, HitRecord },
+    hittable_list::HittableList,
+    interval::Interval,
+    ray::Ray,
+    sphere::Sphere,
+    cylinder::Cylinder,
+    cone::Cone,
+    disk::Disk,
+    torus::Torus,
+    capsule::Capsule,
+    sphere_batch::SphereBatch,
+    cutout::Cutout,
+    bvh::Bvh,
+    instance::Instance,
+    tlas::Tlas,
+    aabb::Aabb,
+};
+
+// Every kind of object a world can hold. Hittable calls on a primitive are a match rather than a
+// trip through a vtable, and since primitives are Send + Sync so are the lists and worlds made of
+// them. A new kind of object implements Hittable and gets a variant here.
+#[derive(Clone, Debug)]
+pub enum Primitive {
+    Sphere(Sphere),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Disk(Disk),
+    Torus(Torus),
+    Capsule(Capsule),
+    SphereBatch(SphereBatch),
+    Cutout(Cutout),
+    List(HittableList),
+    Bvh(Bvh),
+    Instance(Instance),
+    Tlas(Tlas),
+}
+
+impl Hittable for Primitive {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        return match self {
+            Primitive::Sphere(sphere) => sphere.hit(ray, ray_t),
+            Primitive::Cylinder(cylinder) => cylinder.hit(ray, ray_t),
+            Primitive::Cone(cone) => cone.hit(ray, ray_t),
+            Primitive::Disk(disk) => disk.hit(ray, ray_t),
+            Primitive::Torus(torus) => torus.hit(ray, ray_t),
+            Primitive::Capsule(capsule) => capsule.hit(ray, ray_t),
+            Primitive::SphereBatch(spheres) => spheres.hit(ray, ray_t),
+            Primitive::Cutout(cutout) => cutout.hit(ray, ray_t),
+            Primitive::List(list) => list.hit(ray, ray_t),
+            Primitive::Bvh(bvh) => bvh.hit(ray, ray_t),
+            Primitive::Instance(instance) => instance.hit(ray, ray_t),
+            Primitive::Tlas(tlas) => tlas.hit(ray, ray_t),
+        };
+    }
+    fn bounding_box(&self) -> Aabb {
+        return match self {
+            Primitive::Sphere(sphere) => sphere.bounding_box(),
+            Primitive::Cylinder(cylinder) => cylinder.bounding_box(),
+            Primitive::Cone(cone) => cone.bounding_box(),
+            Primitive::Disk(disk) => disk.bounding_box(),
+            Primitive::Torus(torus) => torus.bounding_box(),
+            Primitive::Capsule(capsule) => capsule.bounding_box(),
+            Primitive::SphereBatch(spheres) => spheres.bounding_box(),
+            Primitive::Cutout(cutout) => cutout.bounding_box(),
+            Primitive::List(list) => list.bounding_box(),
+            Primitive::Bvh(bvh) => bvh.bounding_box(),
+            Primitive::Instance(instance) => instance.bounding_box(),
+            Primitive::Tlas(tlas) => tlas.bounding_box(),
+        };
+    }
+    fn object_count(&self) -> usize {
+        return match self {
+            Primitive::Sphere(sphere) => sphere.object_count(),
+            Primitive::Cylinder(cylinder) => cylinder.object_count(),
+            Primitive::Cone(cone) => cone.object_count(),
+            Primitive::Disk(disk) => disk.object_count(),
+            Primitive::Torus(torus) => torus.object_count(),
+            Primitive::Capsule(capsule) => capsule.object_count(),
+            Primitive::SphereBatch(spheres) => spheres.object_count(),
+            Primitive::Cutout(cutout) => cutout.object_count(),
+            Primitive::List(list) => list.object_count(),
+            Primitive::Bvh(bvh) => bvh.object_count(),
+            Primitive::Instance(instance) => instance.object_count(),
+            Primitive::Tlas(tlas) => tlas.object_count(),
+        };
+    }
+}
+
+impl From<Sphere> for Primitive {
+    fn from(sphere: Sphere) -> Primitive {
+        Primitive::Sphere(sphere)
+    }
+}
+
+impl From<Cylinder> for Primitive {
+    fn from(cylinder: Cylinder) -> Primitive {
+        Primitive::Cylinder(cylinder)
+    }
+}
+
+impl From<Cone> for Primitive {
+    fn from(cone: Cone) -> Primitive {
+        Primitive::Cone(cone)
+    }
+}
+
+impl From<Disk> for Primitive {
+    fn from(disk: Disk) -> Primitive {
+        Primitive::Disk(disk)
+    }
+}
+
+impl From<Torus> for Primitive {
+    fn from(torus: Torus) -> Primitive {
+        Primitive::Torus(torus)
+    }
+}
+
+impl From<Capsule> for Primitive {
+    fn from(capsule: Capsule) -> Primitive {
+        Primitive::Capsule(capsule)
+    }
+}
+
+impl From<SphereBatch> for Primitive {
+    fn from(spheres: SphereBatch) -> Primitive {
+        Primitive::SphereBatch(spheres)
+    }
+}
+
+impl From<Cutout> for Primitive {
+    fn from(cutout: Cutout) -> Primitive {
+        Primitive::Cutout(cutout)
+    }
+}
+
+impl From<HittableList> for Primitive {
+    fn from(list: HittableList) -> Primitive {
+        Primitive::List(list)
+    }
+}
+
+impl From<Bvh> for Primitive {
+    fn from(bvh: Bvh) -> Primitive {
+        Primitive::Bvh(bvh)
+    }
+}
+
+impl From<Instance> for Primitive {
+    fn from(instance: Instance) -> Primitive {
+        Primitive::Instance(instance)
+    }
+}
+
+impl From<Tlas> for Primitive {
+    fn from(tlas: Tlas) -> Primitive {
+        Primitive::Tlas(tlas)
+    }
+}
+
+#[cfg(test)]
+use std::sync::Arc;
+#[cfg(test)]
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+#[cfg(test)]
+use crate::{ sweep::Sweep, matrix::Matrix4, point3d::Point3D, vec3::Vec3, float::{ Float, consts } };
+
+// one of each kind of shape, whole and cut open, in a row along x
+#[cfg(test)]
+fn shapes() -> Vec<Primitive> {
+    let at = |i: usize| Point3D::new(3.0 * (i as Float) - 15.0, -0.5, -2.0);
+    let half = Sweep::new(consts::PI * 1.25).unwrap();
+    return vec![
+        Cylinder::new(at(0), 1.0, 1.5, 0).unwrap().into(),
+        Cylinder::new(at(1), 1.0, 1.5, 0).unwrap().with_sweep(half).without_caps().into(),
+        Cone::new(at(2), 1.0, 2.0, 1).unwrap().into(),
+        Cone::new(at(3), 1.0, 2.0, 1).unwrap().with_sweep(half).into(),
+        Disk::new(at(4), 1.2, 2).unwrap().with_inner_radius(0.4).unwrap().into(),
+        Disk::new(at(5), 1.2, 2).unwrap().with_sweep(half).into(),
+        Torus::new(at(6), 1.0, 0.3, 3).unwrap().into(),
+        Torus::new(at(7), 1.0, 0.3, 3).unwrap().with_sweep(half).into(),
+        Capsule::new(at(8), 0.6, 1.0, 4).unwrap().into(),
+        Capsule::new(at(9), 0.6, 1.0, 4).unwrap().with_sweep(half).into(),
+        Sphere::new(at(10), 1.0, 5).into(),
+    ];
+}
+
+#[cfg(test)]
+fn rays(seed: u64) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    return (0..4000)
+        .map(|_| {
+            let origin = Point3D::new(rng.gen_range(-15.0..15.0), rng.gen_range(-2.0..10.0), 8.0);
+            let target = Point3D::new(rng.gen_range(-15.0..15.0), rng.gen_range(-2.0..2.0), -2.0);
+            Ray::new(origin, target - origin)
+        })
+        .collect();
+}
+
+// Every hit is inside the box of the object it is on, rays leaving it along its normal don't hit it
+// again at once, and the acceleration structure finds what testing every object would.
+#[cfg(test)]
+fn check_hits(world: &dyn Hittable, objects: &[Primitive], seed: u64) {
+    let ray_t = Interval::new(0.0, Float::INFINITY);
+    let mut list = HittableList::new();
+    for object in objects {
+        list.add(object.clone());
+    }
+    let mut hit_ids = vec![0; objects.len()];
+    for ray in rays(seed) {
+        let hit = world.hit(&ray, &ray_t);
+        assert_eq!(hit, list.hit(&ray, &ray_t));
+        let Some(hit) = hit else {
+            continue;
+        };
+        hit_ids[hit.object_id] += 1;
+        let object = &objects[hit.object_id];
+        let bounds = object.bounding_box();
+        let slack = Vec3::splat(1e-4);
+        assert_eq!(Aabb::new(bounds.min - slack, bounds.max + slack).union(&Aabb::new(hit.p, hit.p)), Aabb::new(bounds.min - slack, bounds.max + slack));
+        assert!((hit.normal.length() - 1.0).abs() < 1e-4);
+        assert!(hit.u >= 0.0 && hit.u <= 1.0 && hit.v >= 0.0 && hit.v <= 1.0, "{:?}", hit);
+        for direction in [hit.normal, -hit.normal] {
+            if let Some(again) = object.hit(&hit.spawn_ray(direction), &ray_t) {
+                assert!(again.t > 1e-3, "{:?} {:?}", hit, again);
+            }
+        }
+    }
+    // every shape was seen
+    assert!(hit_ids.iter().all(|&count| count > 20), "{:?}", hit_ids);
+}
+
+#[test]
+fn test_shapes_in_bvh() {
+    let shapes = shapes();
+    check_hits(&Bvh::new(shapes.clone()), &shapes, 12);
+}
+
+#[test]
+fn test_shapes_as_instances() {
+    // each shape turned to face the rays a different way, from a copy of it at the origin
+    let instances: Vec<Primitive> = shapes()
+        .into_iter()
+        .enumerate()
+        .map(|(i, shape)| {
+            let center = shape.bounding_box().centroid();
+            let moved_to_origin = Instance::new(Arc::new(shape), Matrix4::translation(-center)).unwrap();
+            let transform = Matrix4::translation(center) * Matrix4::rotation(Vec3::new(1.0, 0.5, (i as Float) - 4.5), 1.1);
+            Instance::new(Arc::new(moved_to_origin.into()), transform).unwrap().into()
+        })
+        .collect();
+    check_hits(&Bvh::new(instances.clone()), &instances, 13);
+}
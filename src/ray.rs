@@ -1,4 +1,4 @@
-use crate::{ point3d::Point3D, vec3::Vec3 };
+use crate::{ point3d::Point3D, vec3::Vec3, float::Float };
 
 pub struct Ray {
     origin: Point3D,
@@ -15,7 +15,7 @@ impl Ray {
     pub fn direction(&self) -> Vec3 {
         return self.direction;
     }
-    pub fn at(&self, t: f32) -> Point3D {
+    pub fn at(&self, t: Float) -> Point3D {
         return self.origin + t * self.direction;
     }
 }
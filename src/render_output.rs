@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use crate::{ framebuffer::FrameBuffer, aov::AovBuffers, image::{ Image, PixelRect }, observer::RenderStatus, float::Float };
+
+// Everything a finished render produced, kept in memory so the caller decides what to write where.
+// The framebuffer and aovs cover the whole resolution that was rendered, and image is the part of
+// it at metadata.rect.
+pub struct RenderOutput {
+    // linear colors, denoised when denoising is enabled and black outside the render region
+    pub image: Image,
+    pub framebuffer: FrameBuffer,
+    // only collected when aovs or denoising were asked for
+    pub aovs: Option<AovBuffers>,
+    pub metadata: RenderMetadata,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderMetadata {
+    // the resolution that was rendered, smaller than the camera's when previewing
+    pub width: i32,
+    pub height: i32,
+    // where image sits within the rendered resolution
+    pub rect: PixelRect,
+    // the pixels that took samples
+    pub region: PixelRect,
+    // the most samples any pixel takes
+    pub samples_per_pixel: u32,
+    // samples in the region, including any resumed from a checkpoint
+    pub samples: u64,
+    pub resumed_samples: u64,
+    pub elapsed: Duration,
+    // identifies the settings that decide which samples get taken, see Camera::settings_hash
+    pub settings_hash: u64,
+}
+
+impl RenderMetadata {
+    // how the render went, as it would have been reported while it ran
+    pub fn status(&self) -> RenderStatus {
+        let pixels = (self.region.width as u64) * (self.region.height as u64);
+        return RenderStatus {
+            samples: self.samples,
+            resumed_samples: self.resumed_samples,
+            total_samples: pixels * (self.samples_per_pixel as u64),
+            pixels,
+            elapsed: self.elapsed,
+        };
+    }
+}
+
+impl RenderOutput {
+    // how much of the pixel's samples budget each pixel of image used, from 0 to 1
+    pub fn sample_fraction(&self, i: i32, j: i32) -> Float {
+        let count = self.framebuffer.sample_count(self.metadata.rect.x + i, self.metadata.rect.y + j);
+        return (count as Float) / (self.metadata.samples_per_pixel.max(1) as Float);
+    }
+}
+
+#[cfg(test)]
+use crate::{
+    render_settings::{ RenderSettings, CropWindow },
+    scene::SceneDescription,
+    test_fixtures::spheres_camera,
+};
+
+#[test]
+fn test_render_to_buffer() {
+    let camera = spheres_camera(2.0, 16, 3, 0.0);
+    let world = SceneDescription::random_spheres(0).build();
+    let settings = RenderSettings { crop: Some(CropWindow::Pixels(4, 2, 12, 6)), ..Default::default() };
+    let output = camera.render_to_buffer(&world, &settings).unwrap();
+
+    assert_eq!((output.metadata.width, output.metadata.height), (16, 8));
+    assert_eq!(output.metadata.rect, PixelRect::new(4, 2, 8, 4));
+    assert_eq!((output.image.width(), output.image.height()), (8, 4));
+    assert_eq!(output.framebuffer.width(), 16);
+    assert_eq!(output.metadata.samples, 8 * 4 * 3);
+    assert_eq!(output.metadata.resumed_samples, 0);
+    assert_eq!(output.sample_fraction(0, 0), 1.0);
+    assert!(output.aovs.is_none());
+    // the image is in linear colors straight from the framebuffer, before any display transform
+    assert_eq!(output.image.get(3, 1), output.framebuffer.resolve().get(7, 3));
+    // a pixel whose few samples all run out of bounces is black, but the image as a whole isn't
+    assert!(output.image.pixels().iter().all(|c| c.x() >= 0.0));
+    assert!(output.image.pixels().iter().map(|c| c.x()).sum::<Float>() > 0.0);
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use crate::{
+    sampler::SamplerKind,
+    filter::Filter,
+    tonemap::DisplayTransform,
+    aov::AovKind,
+    denoise::DenoiseSettings,
+    image::PixelRect,
+    float::Float,
+};
+
+// options that change how Camera::render_to_buffer produces its output without changing what the camera sees
+pub struct RenderSettings {
+    // when set, the image is rendered one sample pass at a time instead of one pixel at a time
+    pub progressive: Option<ProgressiveSettings>,
+    // when set, pixels stop taking samples once they are no longer noisy
+    pub adaptive: Option<AdaptiveSettings>,
+    // where the pixel offsets, lens positions and bounce directions of each sample come from
+    pub sampler: SamplerKind,
+    // changes the scrambling of the deterministic samplers, so two renders with different seeds
+    // have independent noise
+    pub seed: u64,
+    // how samples are weighted into the pixels around them
+    pub filter: Filter,
+    // exposure, tone curve and transfer function used when writing images
+    pub display: DisplayTransform,
+    // extra images to gather alongside the beauty image
+    pub aovs: Vec<AovKind>,
+    // when set, the final image is denoised before it is written
+    pub denoise: Option<DenoiseSettings>,
+    // when set, only this part of the image is rendered
+    pub crop: Option<CropWindow>,
+    // write the whole image with black outside the crop instead of just the cropped part
+    pub crop_full_frame: bool,
+    // renders at 1 / preview_scale of the camera's resolution with the same framing, for quick looks
+    pub preview_scale: u32,
+    // when set, the accumulated samples are saved as the render goes so it can be resumed
+    pub checkpoint: Option<CheckpointSettings>,
+    // renders go tile by tile, which is how often observers hear about progress and cancellation is
+    // checked, and how big the pieces handed out to workers are. Pixel by pixel renders take their
+    // pixels tile by tile too
+    pub tile_size: i32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            progressive: None,
+            adaptive: None,
+            sampler: SamplerKind::Independent,
+            seed: 0,
+            filter: Filter::default(),
+            display: DisplayTransform::default(),
+            aovs: Vec::new(),
+            denoise: None,
+            crop: None,
+            crop_full_frame: false,
+            preview_scale: 1,
+            checkpoint: None,
+            tile_size: 32,
+        }
+    }
+}
+
+impl RenderSettings {
+    // the resolution actually rendered for a camera of the given resolution
+    pub fn render_size(&self, width: i32, height: i32) -> (i32, i32) {
+        let scale = self.preview_scale.max(1) as i32;
+        return (i32::max(width / scale, 1), i32::max(height / scale, 1));
+    }
+    // the pixels of a render_size image that get rendered
+    pub fn render_region(&self, width: i32, height: i32) -> PixelRect {
+        let (render_width, render_height) = self.render_size(width, height);
+        return match &self.crop {
+            Some(crop) => crop.to_rect(width, height, render_width, render_height),
+            None => PixelRect::full(render_width, render_height),
+        };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CropWindow {
+    // x0, y0, x1, y1 in pixels of the camera's full resolution, the end is exclusive
+    Pixels(i32, i32, i32, i32),
+    // x0, y0, x1, y1 as fractions of the image's width and height, from the top left
+    Normalized(Float, Float, Float, Float),
+}
+
+impl CropWindow {
+    // the pixels the window covers in a render_width x render_height image of a camera with the given
+    // full resolution, rounded outwards so a scaled down preview still covers the whole window
+    pub fn to_rect(&self, width: i32, height: i32, render_width: i32, render_height: i32) -> PixelRect {
+        let ((x, w), (y, h)) = match *self {
+            // integer math so that a full resolution render covers exactly the requested pixels
+            CropWindow::Pixels(x0, y0, x1, y1) => {
+                let to_pixels = |start: i32, end: i32, size: i32, render_size: i32| {
+                    let start = (start * render_size).div_euclid(size).clamp(0, render_size);
+                    let end = (end * render_size + size - 1).div_euclid(size).clamp(start, render_size);
+                    (start, end - start)
+                };
+                (to_pixels(x0, x1, width, render_width), to_pixels(y0, y1, height, render_height))
+            }
+            CropWindow::Normalized(x0, y0, x1, y1) => {
+                let to_pixels = |start: Float, end: Float, render_size: i32| {
+                    let start = ((start * (render_size as Float)).floor() as i32).clamp(0, render_size);
+                    let end = ((end * (render_size as Float)).ceil() as i32).clamp(start, render_size);
+                    (start, end - start)
+                };
+                (to_pixels(x0, x1, render_width), to_pixels(y0, y1, render_height))
+            }
+        };
+        return PixelRect::new(x, y, w, h);
+    }
+}
+
+// with neither refresh set, only the finished image is written
+#[derive(Default)]
+pub struct ProgressiveSettings {
+    // rewrite the image after every this many passes
+    pub refresh_passes: Option<u32>,
+    // rewrite the image once at least this much time has passed since the last refresh
+    pub refresh_interval: Option<Duration>,
+}
+
+impl ProgressiveSettings {
+    // whether an intermediate image should be written after finishing the given (1-based) pass
+    pub fn should_refresh(&self, pass: u32, since_last_refresh: Duration) -> bool {
+        let by_passes = match self.refresh_passes {
+            Some(passes) => passes > 0 && pass % passes == 0,
+            None => false,
+        };
+        let by_time = match self.refresh_interval {
+            Some(interval) => since_last_refresh >= interval,
+            None => false,
+        };
+        return by_passes || by_time;
+    }
+}
+
+// the camera's samples_per_pixel acts as the maximum number of samples for a pixel
+pub struct AdaptiveSettings {
+    pub min_samples: u32,
+    // a pixel has converged once the relative standard error of its luminance falls below this
+    pub noise_threshold: Float,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        AdaptiveSettings {
+            min_samples: 16,
+            noise_threshold: 0.01,
+        }
+    }
+}
+
+impl AdaptiveSettings {
+    pub fn has_converged(&self, samples: u32, relative_error: Float) -> bool {
+        return samples >= self.min_samples && relative_error < self.noise_threshold;
+    }
+}
+
+pub struct CheckpointSettings {
+    pub path: String,
+    // save at most this often, and once more when the render finishes
+    pub interval: Duration,
+    // start from the samples already in the checkpoint file instead of from nothing, if it exists
+    pub resume: bool,
+    // identifies what is being rendered (the scene and the camera), a checkpoint saved for a
+    // different scene is refused rather than mixed into this one
+    pub scene_hash: u64,
+}
+
+impl CheckpointSettings {
+    pub fn new(path: &str, scene_hash: u64) -> CheckpointSettings {
+        CheckpointSettings {
+            path: String::from(path),
+            interval: Duration::from_secs(300),
+            resume: false,
+            scene_hash,
+        }
+    }
+    pub fn should_save(&self, since_last_save: Duration) -> bool {
+        return since_last_save >= self.interval;
+    }
+}
+
+#[test]
+fn test_preview_scale_and_crop() {
+    let mut settings = RenderSettings { preview_scale: 4, ..Default::default() };
+    assert_eq!(settings.render_size(1200, 675), (300, 168));
+    assert_eq!(settings.render_region(1200, 675), PixelRect::full(300, 168));
+    // a pixel crop is given at full resolution and shrinks with the preview
+    settings.crop = Some(CropWindow::Pixels(100, 40, 503, 200));
+    assert_eq!(settings.render_region(1200, 675), PixelRect::new(25, 9, 101, 41));
+    settings.preview_scale = 1;
+    assert_eq!(settings.render_region(1200, 675), PixelRect::new(100, 40, 403, 160));
+    settings.crop = Some(CropWindow::Normalized(0.5, 0.0, 1.5, 0.25));
+    assert_eq!(settings.render_region(1200, 675), PixelRect::new(600, 0, 600, 169));
+}
+
+#[test]
+fn test_has_converged() {
+    let settings = AdaptiveSettings { min_samples: 8, noise_threshold: 0.05 };
+    assert!(!settings.has_converged(4, 0.0));
+    assert!(!settings.has_converged(8, 0.1));
+    assert!(settings.has_converged(8, 0.01));
+}
+
+#[test]
+fn test_should_refresh_by_passes() {
+    let settings = ProgressiveSettings { refresh_passes: Some(4), ..Default::default() };
+    assert!(!settings.should_refresh(3, Duration::ZERO));
+    assert!(settings.should_refresh(4, Duration::ZERO));
+    assert!(settings.should_refresh(8, Duration::ZERO));
+}
+
+#[test]
+fn test_should_refresh_by_time() {
+    // only by time, however many passes go by
+    let settings = ProgressiveSettings { refresh_interval: Some(Duration::from_secs(5)), ..Default::default() };
+    assert!(!settings.should_refresh(1, Duration::from_secs(1)));
+    assert!(settings.should_refresh(2, Duration::from_secs(6)));
+    assert!(!ProgressiveSettings::default().should_refresh(1, Duration::from_secs(1000)));
+}
@@ -0,0 +1,451 @@
+use rand::random;
+
+use crate::{ vec3::Vec3, float::{ Float, consts::PI } };
+
+// the largest Float below 1, samples are clamped to it so that [0, 1) is never violated by rounding
+pub const ONE_MINUS_EPSILON: Float = 1.0 - Float::EPSILON / 2.0;
+
+// A sampler hands out the random numbers for one camera sample. Every number used while tracing
+// the sample (pixel offset, lens position, bounce directions, ...) comes from the same sequence,
+// so well distributed samplers spread all of them out together instead of just the pixel offset.
+pub trait Sampler {
+    // called before each camera sample, sample_index counts the samples already taken for the pixel
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32);
+    fn get_1d(&mut self) -> Float;
+    fn get_2d(&mut self) -> (Float, Float);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn parse(name: &str) -> Option<SamplerKind> {
+        return match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        };
+    }
+    pub fn create(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        return match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new()),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        };
+    }
+}
+
+// plain uniform random numbers, every dimension of every sample is independent
+pub struct IndependentSampler;
+
+impl IndependentSampler {
+    pub fn new() -> IndependentSampler {
+        IndependentSampler
+    }
+}
+
+impl Default for IndependentSampler {
+    fn default() -> Self {
+        return IndependentSampler::new();
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, _i: i32, _j: i32, _sample_index: u32) {}
+    fn get_1d(&mut self) -> Float {
+        return random::<Float>();
+    }
+    fn get_2d(&mut self) -> (Float, Float) {
+        return (random::<Float>(), random::<Float>());
+    }
+}
+
+// jittered sampling: each dimension is split into one stratum per sample and every sample lands in
+// a different stratum, the order strata are visited in is shuffled per pixel and dimension so that
+// dimensions do not correlate with each other
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> StratifiedSampler {
+        StratifiedSampler {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel_hash: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+    fn next_stratum(&mut self, strata: u32) -> u32 {
+        let hash = mix_bits(self.pixel_hash ^ mix_bits(self.dimension as u64));
+        self.dimension += 1;
+        return permutation_element(self.sample_index % strata, strata, hash as u32);
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32) {
+        self.pixel_hash = hash_pixel(i, j, self.seed);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+    fn get_1d(&mut self) -> Float {
+        let stratum = self.next_stratum(self.samples_per_pixel);
+        let jitter = random::<Float>();
+        return Float::min(((stratum as Float) + jitter) / (self.samples_per_pixel as Float), ONE_MINUS_EPSILON);
+    }
+    fn get_2d(&mut self) -> (Float, Float) {
+        // lay the strata out in a grid that is as square as possible and has a cell for every sample
+        let x_strata = Float::ceil(Float::sqrt(self.samples_per_pixel as Float)) as u32;
+        let y_strata = self.samples_per_pixel.div_ceil(x_strata);
+        let stratum = self.next_stratum(x_strata * y_strata);
+        let x = ((stratum % x_strata) as Float + random::<Float>()) / (x_strata as Float);
+        let y = ((stratum / x_strata) as Float + random::<Float>()) / (y_strata as Float);
+        return (Float::min(x, ONE_MINUS_EPSILON), Float::min(y, ONE_MINUS_EPSILON));
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101,
+    103, 107, 109, 113, 127, 131,
+];
+
+// the halton sequence uses the radical inverse in a different prime base for each dimension,
+// digits are owen scrambled with a per-pixel seed so neighbouring pixels do not share the same points
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler { seed, pixel_hash: 0, sample_index: 0, dimension: 0 }
+    }
+    fn next(&mut self) -> Float {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        // past the prime table, reuse the bases with a fresh scramble rather than run out of dimensions
+        let base = PRIMES[(dimension as usize) % PRIMES.len()];
+        let hash = mix_bits(self.pixel_hash ^ mix_bits(dimension as u64)) as u32;
+        return owen_scrambled_radical_inverse(base, self.sample_index as u64, hash);
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32) {
+        self.pixel_hash = hash_pixel(i, j, self.seed);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+    fn get_1d(&mut self) -> Float {
+        return self.next();
+    }
+    fn get_2d(&mut self) -> (Float, Float) {
+        return (self.next(), self.next());
+    }
+}
+
+// a "padded" owen scrambled sobol sampler: every 2d request is served by the first two sobol
+// dimensions, which form a (0, 2)-sequence, with the sample order shuffled and the points owen
+// scrambled independently per pixel and dimension (burley 2020, practical hash-based owen scrambling)
+pub struct SobolSampler {
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler { seed, pixel_hash: 0, sample_index: 0, dimension: 0 }
+    }
+    fn next_hash(&mut self) -> u32 {
+        let hash = mix_bits(self.pixel_hash ^ mix_bits(self.dimension as u64));
+        self.dimension += 1;
+        return hash as u32;
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32) {
+        self.pixel_hash = hash_pixel(i, j, self.seed);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+    fn get_1d(&mut self) -> Float {
+        let hash = self.next_hash();
+        let index = nested_uniform_scramble(self.sample_index, hash);
+        let x = nested_uniform_scramble(index.reverse_bits(), mix_bits(hash as u64) as u32);
+        return to_unit_float(x);
+    }
+    fn get_2d(&mut self) -> (Float, Float) {
+        let hash = self.next_hash();
+        let index = nested_uniform_scramble(self.sample_index, hash);
+        let seed = mix_bits(hash as u64);
+        let x = nested_uniform_scramble(index.reverse_bits(), seed as u32);
+        let y = nested_uniform_scramble(sobol_second_dimension(index), (seed >> 32) as u32);
+        return (to_unit_float(x), to_unit_float(y));
+    }
+}
+
+// maps a 2d sample to a direction uniformly distributed over the unit sphere
+pub fn sample_uniform_sphere(u: (Float, Float)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = Float::sqrt(Float::max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * u.1;
+    return Vec3::new(r * Float::cos(phi), r * Float::sin(phi), z);
+}
+
+// maps a 2d sample to a point in the unit disk (z = 0) using shirley's concentric mapping,
+// which keeps neighbouring samples neighbours and so preserves the sampler's stratification
+pub fn sample_uniform_disk_concentric(u: (Float, Float)) -> Vec3 {
+    let ox = 2.0 * u.0 - 1.0;
+    let oy = 2.0 * u.1 - 1.0;
+    if ox == 0.0 && oy == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let (r, theta) = if Float::abs(ox) > Float::abs(oy) {
+        (ox, (PI / 4.0) * (oy / ox))
+    } else {
+        (oy, PI / 2.0 - (PI / 4.0) * (ox / oy))
+    };
+    return Vec3::new(r * Float::cos(theta), r * Float::sin(theta), 0.0);
+}
+
+fn to_unit_float(x: u32) -> Float {
+    return Float::min((x as Float) * (1.0 / 4294967296.0), ONE_MINUS_EPSILON);
+}
+
+fn hash_pixel(i: i32, j: i32, seed: u64) -> u64 {
+    return mix_bits(((i as u32 as u64) << 32) ^ (j as u32 as u64) ^ mix_bits(seed));
+}
+
+// 64 bit finalizer that spreads every input bit across the output
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    return v;
+}
+
+// returns element i of a pseudo-random permutation of 0..l chosen by p (kensler 2013)
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | (p >> 27));
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    return i.wrapping_add(p) % l;
+}
+
+fn owen_scrambled_radical_inverse(base: u32, mut a: u64, hash: u32) -> Float {
+    let base = base as u64;
+    let inv_base = 1.0 / (base as Float);
+    let mut inv_base_m: Float = 1.0;
+    let mut reversed_digits: u64 = 0;
+    // keep adding digits until they are too small to change a Float in [0, 1)
+    while 1.0 - inv_base_m < 1.0 {
+        let next = a / base;
+        let digit = (a - next * base) as u32;
+        // each digit's permutation depends on all the digits before it, which is what makes it owen scrambling
+        let digit_hash = mix_bits((hash as u64) ^ reversed_digits) as u32;
+        let digit = permutation_element(digit, base as u32, digit_hash);
+        reversed_digits = reversed_digits * base + (digit as u64);
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    return Float::min(inv_base_m * (reversed_digits as Float), ONE_MINUS_EPSILON);
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    return x;
+}
+
+// owen scrambling of a 32 bit fixed point value, bits are reversed so that each bit is only
+// influenced by the more significant ones
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    return laine_karras_permutation(x.reverse_bits(), seed).reverse_bits();
+}
+
+// the second sobol dimension as 32 bit fixed point, its direction numbers are v_k = v_(k-1) ^ (v_(k-1) >> 1)
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut v: u32 = 1 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    return result;
+}
+
+#[cfg(test)]
+fn is_stratified_1d(values: &[Float]) -> bool {
+    let n = values.len();
+    let mut seen = vec![false; n];
+    for v in values {
+        let stratum = (v * (n as Float)) as usize;
+        if seen[stratum] {
+            return false;
+        }
+        seen[stratum] = true;
+    }
+    return true;
+}
+
+#[test]
+fn test_sobol_second_dimension() {
+    let expected = [0.0, 0.5, 0.75, 0.25, 0.625, 0.125, 0.375, 0.875];
+    for (index, e) in expected.iter().enumerate() {
+        assert_eq!(to_unit_float(sobol_second_dimension(index as u32)), *e);
+    }
+}
+
+#[test]
+fn test_permutation_element_is_permutation() {
+    for l in [1, 2, 5, 16, 100] {
+        let mut seen = vec![false; l as usize];
+        for i in 0..l {
+            let p = permutation_element(i, l, 0x1234567);
+            assert!(!seen[p as usize]);
+            seen[p as usize] = true;
+        }
+    }
+}
+
+#[test]
+fn test_samplers_stay_in_unit_range() {
+    for kind in [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+        let mut sampler = kind.create(16, 7);
+        for index in 0..16 {
+            sampler.start_pixel_sample(3, 5, index);
+            for _ in 0..8 {
+                let x = sampler.get_1d();
+                let (u, v) = sampler.get_2d();
+                assert!((0.0..1.0).contains(&x));
+                assert!((0.0..1.0).contains(&u));
+                assert!((0.0..1.0).contains(&v));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_stratified_sampler() {
+    let n = 16;
+    let mut sampler = StratifiedSampler::new(n, 11);
+    let mut xs = Vec::new();
+    let mut cells = vec![false; n as usize];
+    for index in 0..n {
+        sampler.start_pixel_sample(1, 2, index);
+        let (u, v) = sampler.get_2d();
+        // 16 samples are laid out on a 4x4 grid, each cell should be used once
+        let cell = ((v * 4.0) as usize) * 4 + ((u * 4.0) as usize);
+        assert!(!cells[cell]);
+        cells[cell] = true;
+        xs.push(sampler.get_1d());
+    }
+    assert!(is_stratified_1d(&xs));
+}
+
+#[test]
+fn test_sobol_sampler() {
+    // a (0, 2)-sequence puts exactly one of n samples in each 1/n interval of either axis
+    let n = 16;
+    let mut sampler = SobolSampler::new(11);
+    let mut xs = Vec::new();
+    let mut us = Vec::new();
+    let mut vs = Vec::new();
+    for index in 0..n {
+        sampler.start_pixel_sample(1, 2, index);
+        let _ = sampler.get_2d();
+        xs.push(sampler.get_1d());
+        let (u, v) = sampler.get_2d();
+        us.push(u);
+        vs.push(v);
+    }
+    assert!(is_stratified_1d(&xs));
+    assert!(is_stratified_1d(&us));
+    assert!(is_stratified_1d(&vs));
+}
+
+#[test]
+fn test_halton_sampler() {
+    // base 2 and base 3 points are stratified at powers of their base
+    let mut sampler = HaltonSampler::new(3);
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    for index in 0..9 {
+        sampler.start_pixel_sample(0, 0, index);
+        let (x, y) = sampler.get_2d();
+        xs.push(x);
+        ys.push(y);
+    }
+    assert!(is_stratified_1d(&xs[..8]));
+    assert!(is_stratified_1d(&ys));
+}
+
+#[test]
+fn test_sample_uniform_disk_concentric() {
+    assert_eq!(sample_uniform_disk_concentric((0.5, 0.5)).length(), 0.0);
+    for u in [(0.0, 0.0), (1.0, 0.5), (0.3, 0.9), (0.99, 0.01)] {
+        let p = sample_uniform_disk_concentric(u);
+        assert!(p.length() <= 1.0 + 1e-6);
+        assert_eq!(p.z(), 0.0);
+    }
+}
+
+#[test]
+fn test_sample_uniform_sphere() {
+    for u in [(0.0, 0.0), (1.0, 0.5), (0.3, 0.9), (0.5, 0.25)] {
+        assert!((sample_uniform_sphere(u).length() - 1.0).abs() < 1e-5);
+    }
+}
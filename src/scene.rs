@@ -0,0 +1,248 @@
+use std::{ fmt::Write as _, fs, hash::Hasher, io::{ self, Error, ErrorKind } };
+
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+
+use crate::{
+    point3d::Point3D,
+    color::Color,
+    world::World,
+    sphere::Sphere,
+    bvh::Bvh,
+    sphere_batch::batch_neighbours,
+    primitive::Primitive,
+    material::{ Material, Lambertian, Metal, Dielectric },
+    checkpoint::FnvHasher,
+    float::Float,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MaterialDescription {
+    Lambertian(Color),
+    // albedo and fuzziness
+    Metal(Color, Float),
+    // index of refraction
+    Dielectric(Float),
+}
+
+impl MaterialDescription {
+    pub fn build(&self) -> Material {
+        return match *self {
+            MaterialDescription::Lambertian(albedo) => Material::Lambertian(Lambertian::new(albedo)),
+            MaterialDescription::Metal(albedo, fuzziness) => Material::Metal(Metal::new(albedo, fuzziness)),
+            MaterialDescription::Dielectric(ior) => Material::Dielectric(Dielectric::new(ior)),
+        };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SphereDescription {
+    pub center: Point3D,
+    pub radius: Float,
+    // index into the scene's materials
+    pub material: usize,
+}
+
+// A scene in the form it is described in, so it can be read from a text file, hashed to tell
+// whether two renders see the same thing, and built into a World wherever one is needed.
+//
+// The text format has one item per line, blank lines and everything after a # are ignored:
+//   material lambertian <r> <g> <b>
+//   material metal <r> <g> <b> <fuzziness>
+//   material dielectric <ior>
+//   sphere <x> <y> <z> <radius> <material>
+// where materials are numbered from 0 in the order they appear.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SceneDescription {
+    pub materials: Vec<MaterialDescription>,
+    pub spheres: Vec<SphereDescription>,
+}
+
+impl SceneDescription {
+    // the cover scene of ray tracing in one weekend, the same seed always gives the same scene
+    pub fn random_spheres(seed: u64) -> SceneDescription {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut scene = SceneDescription::default();
+
+        let ground_material = scene.add_material(MaterialDescription::Lambertian(Color::new(0.5, 0.5, 0.5)));
+        scene.add_sphere(Point3D::new(0.0, -1000.0, 0.0), 1000.0, ground_material);
+
+        for a in -11..11 {
+            for b in -11..11 {
+                let choose_mat = rng.gen::<Float>();
+                let center = Point3D::new(
+                    (a as Float) + 0.9 * rng.gen::<Float>(),
+                    0.2,
+                    (b as Float) + 0.9 * rng.gen::<Float>()
+                );
+
+                if (center - Point3D::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                    let material = match choose_mat {
+                        v if v < 0.8 => {
+                            let albedo = Color::new(
+                                rng.gen::<Float>() * rng.gen::<Float>(),
+                                rng.gen::<Float>() * rng.gen::<Float>(),
+                                rng.gen::<Float>() * rng.gen::<Float>()
+                            );
+                            MaterialDescription::Lambertian(albedo)
+                        }
+                        v if v < 0.95 => {
+                            let albedo = Color::new(
+                                rng.gen_range(0.5..1.0),
+                                rng.gen_range(0.5..1.0),
+                                rng.gen_range(0.5..1.0)
+                            );
+                            MaterialDescription::Metal(albedo, rng.gen_range(0.0..0.5))
+                        }
+                        _ => MaterialDescription::Dielectric(1.5),
+                    };
+                    let material = scene.add_material(material);
+                    scene.add_sphere(center, 0.2, material);
+                }
+            }
+        }
+
+        let material_1 = scene.add_material(MaterialDescription::Dielectric(1.5));
+        scene.add_sphere(Point3D::new(0.0, 1.0, 0.0), 1.0, material_1);
+
+        let material_2 = scene.add_material(MaterialDescription::Lambertian(Color::new(0.4, 0.2, 0.1)));
+        scene.add_sphere(Point3D::new(-4.0, 1.0, 0.0), 1.0, material_2);
+
+        let material_3 = scene.add_material(MaterialDescription::Metal(Color::new(0.7, 0.6, 0.5), 0.0));
+        scene.add_sphere(Point3D::new(4.0, 1.0, 0.0), 1.0, material_3);
+
+        return scene;
+    }
+
+    // returns the index to refer to the material by
+    pub fn add_material(&mut self, material: MaterialDescription) -> usize {
+        self.materials.push(material);
+        return self.materials.len() - 1;
+    }
+
+    pub fn add_sphere(&mut self, center: Point3D, radius: Float, material: usize) {
+        self.spheres.push(SphereDescription { center, radius, material });
+    }
+
+    pub fn parse(text: &str) -> io::Result<SceneDescription> {
+        let mut scene = SceneDescription::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("line {}: {}", number + 1, message));
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or("");
+            let kind = if keyword == "material" { words.next().unwrap_or("") } else { keyword };
+            let values = words
+                .map(|v| v.parse::<Float>())
+                .collect::<Result<Vec<Float>, _>>()
+                .map_err(|_| invalid("expected numbers"))?;
+            let expect = |count: usize| {
+                if values.len() == count { Ok(()) } else { Err(invalid(&format!("expected {} numbers", count))) }
+            };
+            match (keyword, kind) {
+                ("material", "lambertian") => {
+                    expect(3)?;
+                    scene.add_material(MaterialDescription::Lambertian(Color::new(values[0], values[1], values[2])));
+                }
+                ("material", "metal") => {
+                    expect(4)?;
+                    let albedo = Color::new(values[0], values[1], values[2]);
+                    scene.add_material(MaterialDescription::Metal(albedo, values[3]));
+                }
+                ("material", "dielectric") => {
+                    expect(1)?;
+                    scene.add_material(MaterialDescription::Dielectric(values[0]));
+                }
+                ("sphere", _) => {
+                    expect(5)?;
+                    let material = values[4] as usize;
+                    if values[4] < 0.0 || values[4].fract() != 0.0 || material >= scene.materials.len() {
+                        return Err(invalid("sphere refers to a material that has not been defined"));
+                    }
+                    scene.add_sphere(Point3D::new(values[0], values[1], values[2]), values[3], material);
+                }
+                _ => {
+                    return Err(invalid(&format!("unknown item {}", line)));
+                }
+            }
+        }
+        return Ok(scene);
+    }
+
+    pub fn load(path: &str) -> io::Result<SceneDescription> {
+        return SceneDescription::parse(&fs::read_to_string(path)?);
+    }
+
+    // floats are written in their shortest form that reads back as exactly the same value
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for material in &self.materials {
+            let _ = match material {
+                MaterialDescription::Lambertian(c) => writeln!(text, "material lambertian {} {} {}", c.x(), c.y(), c.z()),
+                MaterialDescription::Metal(c, fuzziness) =>
+                    writeln!(text, "material metal {} {} {} {}", c.x(), c.y(), c.z(), fuzziness),
+                MaterialDescription::Dielectric(ior) => writeln!(text, "material dielectric {}", ior),
+            };
+        }
+        for sphere in &self.spheres {
+            let c = sphere.center;
+            let _ = writeln!(text, "sphere {} {} {} {} {}", c.x(), c.y(), c.z(), sphere.radius, sphere.material);
+        }
+        return text;
+    }
+
+    // identifies the scene's contents, two scenes with the same hash render the same
+    pub fn hash(&self) -> u64 {
+        let mut hasher = FnvHasher::new();
+        hasher.write(self.to_text().as_bytes());
+        return hasher.finish();
+    }
+
+    // a world for rendering, with the spheres in a Bvh and the materials in the same order as here,
+    // so the scene's material indices carry over
+    pub fn build(&self) -> World {
+        let mut world = World::new();
+        for material in &self.materials {
+            world.add_material(material.build());
+        }
+        let batches = batch_neighbours(self.build_spheres());
+        world.add(Bvh::new(batches.into_iter().map(Primitive::SphereBatch).collect()));
+        return world;
+    }
+
+    pub fn build_spheres(&self) -> Vec<Sphere> {
+        return self.spheres.iter().map(|sphere| Sphere::new(sphere.center, sphere.radius, sphere.material)).collect();
+    }
+}
+
+#[cfg(test)]
+use crate::hittable::Hittable;
+
+#[test]
+fn test_random_spheres_is_seeded() {
+    let scene = SceneDescription::random_spheres(7);
+    assert_eq!(scene, SceneDescription::random_spheres(7));
+    assert_eq!(scene.hash(), SceneDescription::random_spheres(7).hash());
+    assert_ne!(scene.hash(), SceneDescription::random_spheres(8).hash());
+    assert_eq!(scene.build().object_count(), scene.spheres.len());
+}
+
+#[test]
+fn test_scene_text_round_trip() {
+    let scene = SceneDescription::random_spheres(1);
+    let parsed = SceneDescription::parse(&scene.to_text()).unwrap();
+    assert_eq!(parsed, scene);
+    assert_eq!(parsed.hash(), scene.hash());
+}
+
+#[test]
+fn test_parse_scene_errors() {
+    let scene = SceneDescription::parse("# ground\nmaterial lambertian 0.5 0.5 0.5\nsphere 0 -1000 0 1000 0\n").unwrap();
+    assert_eq!(scene.spheres[0].radius, 1000.0);
+    assert!(SceneDescription::parse("sphere 0 0 0 1 0\n").is_err());
+    assert!(SceneDescription::parse("material metal 1 1 1\n").is_err());
+    assert!(SceneDescription::parse("material glass 1.5\n").is_err());
+    assert!(SceneDescription::parse("cube 0 0 0 1\n").is_err());
+}
@@ -0,0 +1,449 @@
+use std::ops::{ Add, Sub, Mul, Div, BitAnd };
+
+use crate::{ vec3::Vec3, float::Float };
+
+// Several Floats worked on at once, one instruction per operation for all the lanes. On x86_64 the
+// lanes are sse registers, or avx registers when the build enables avx (for example with
+// RUSTFLAGS="-C target-cpu=native"), so Lanes holds 4 or 8 f32s, or 2 or 4 f64s with the f64
+// feature. Anywhere else it falls back to ScalarLanes, a plain array. Builds that don't enable avx
+// can still use it where the cpu turns out to have it, see AVX_AT_RUNTIME. Lanes can only be made
+// with the Support that detect hands out, so avx lanes can't be made on a cpu without avx.
+//
+// Vec3 itself stays scalar, loading one vector's three components into lanes and storing them back
+// costs more than the arithmetic saves. Code that wants the lanes busy works on several vectors at
+// once instead, a structure of arrays in Vec3Lanes.
+pub trait FloatLanes: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> {
+    // the result of a comparison, set in the lanes where it holds
+    type Mask: Copy + BitAnd<Output = Self::Mask>;
+    // never more than MAX_LANES
+    const LANES: usize;
+    // whether code generic over the lanes should check has_avx and switch to avx lanes when it can,
+    // which only the widest lanes of a build that doesn't already target avx do
+    const AVX_AT_RUNTIME: bool = false;
+    // proof that the cpu has the instructions the lanes use, () for lanes every cpu the build runs
+    // on has
+    type Support: Copy;
+    // None when the cpu running the code lacks the instructions
+    fn detect() -> Option<Self::Support>;
+    fn splat(support: Self::Support, value: Float) -> Self;
+    // the first LANES values of values
+    fn load(support: Self::Support, values: &[Float]) -> Self;
+    // writes the lanes to the first LANES values of out
+    fn store(self, out: &mut [Float]);
+    fn sqrt(self) -> Self;
+    // never set in lanes holding nan
+    fn lt(self, rhs: Self) -> Self::Mask;
+    fn le(self, rhs: Self) -> Self::Mask;
+    // whether any lane of mask is set
+    fn any(mask: Self::Mask) -> bool;
+    // a in the lanes where mask is set, b in the others
+    fn select(mask: Self::Mask, a: Self, b: Self) -> Self;
+}
+
+pub const MAX_LANES: usize = 8;
+
+// three FloatLanes making LANES vectors, lane i of x, y and z is vector i
+#[derive(Clone, Copy)]
+pub struct Vec3Lanes<L: FloatLanes> {
+    pub x: L,
+    pub y: L,
+    pub z: L,
+}
+
+// Always inlined, so that in a function enabling avx for avx lanes they turn into plain avx
+// instructions. A function without avx can't have the intrinsics inlined into it, so anything left
+// as a call would be a call per operation.
+impl<L: FloatLanes> Vec3Lanes<L> {
+    // v in every lane
+    #[inline(always)]
+    pub fn splat(support: L::Support, v: Vec3) -> Vec3Lanes<L> {
+        Vec3Lanes { x: L::splat(support, v.x()), y: L::splat(support, v.y()), z: L::splat(support, v.z()) }
+    }
+    #[inline(always)]
+    pub fn load(support: L::Support, x: &[Float], y: &[Float], z: &[Float]) -> Vec3Lanes<L> {
+        Vec3Lanes { x: L::load(support, x), y: L::load(support, y), z: L::load(support, z) }
+    }
+    // same order of operations as Vec3, so each lane gets exactly what Vec3 would
+    #[inline(always)]
+    pub fn dot(self, rhs: Vec3Lanes<L>) -> L {
+        return self.x * rhs.x + self.y * rhs.y + self.z * rhs.z;
+    }
+    #[inline(always)]
+    pub fn length_squared(self) -> L {
+        return self.dot(self);
+    }
+    // each lane's vector times that lane of s, multiplied as s * v like a Float times a Vec3
+    #[inline(always)]
+    pub fn scale(self, s: L) -> Vec3Lanes<L> {
+        Vec3Lanes { x: s * self.x, y: s * self.y, z: s * self.z }
+    }
+}
+
+impl<L: FloatLanes> Add for Vec3Lanes<L> {
+    type Output = Vec3Lanes<L>;
+    #[inline(always)]
+    fn add(self, rhs: Vec3Lanes<L>) -> Vec3Lanes<L> {
+        Vec3Lanes { x: self.x + rhs.x, y: self.y + rhs.y, z: self.z + rhs.z }
+    }
+}
+
+impl<L: FloatLanes> Sub for Vec3Lanes<L> {
+    type Output = Vec3Lanes<L>;
+    #[inline(always)]
+    fn sub(self, rhs: Vec3Lanes<L>) -> Vec3Lanes<L> {
+        Vec3Lanes { x: self.x - rhs.x, y: self.y - rhs.y, z: self.z - rhs.z }
+    }
+}
+
+// the fallback, which the compiler may still vectorize on its own. The lanes are used from generic
+// code that can end up in other crates, so every operation is marked inline to keep it from turning
+// into a function call there.
+#[derive(Clone, Copy, Debug)]
+pub struct ScalarLanes([Float; 4]);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScalarMask([bool; 4]);
+
+impl BitAnd for ScalarMask {
+    type Output = ScalarMask;
+    #[inline]
+    fn bitand(self, rhs: ScalarMask) -> ScalarMask {
+        ScalarMask(std::array::from_fn(|i| self.0[i] && rhs.0[i]))
+    }
+}
+
+macro_rules! scalar_lanes_op {
+    ($trait:ident, $method:ident, $op:tt) => {
+        impl $trait for ScalarLanes {
+            type Output = ScalarLanes;
+            #[inline]
+            fn $method(self, rhs: ScalarLanes) -> ScalarLanes {
+                ScalarLanes(std::array::from_fn(|i| self.0[i] $op rhs.0[i]))
+            }
+        }
+    };
+}
+
+scalar_lanes_op!(Add, add, +);
+scalar_lanes_op!(Sub, sub, -);
+scalar_lanes_op!(Mul, mul, *);
+scalar_lanes_op!(Div, div, /);
+
+impl FloatLanes for ScalarLanes {
+    type Mask = ScalarMask;
+    const LANES: usize = 4;
+    type Support = ();
+    #[inline]
+    fn detect() -> Option<()> {
+        return Some(());
+    }
+    #[inline]
+    fn splat(_: (), value: Float) -> ScalarLanes {
+        ScalarLanes([value; 4])
+    }
+    #[inline]
+    fn load(_: (), values: &[Float]) -> ScalarLanes {
+        ScalarLanes(std::array::from_fn(|i| values[i]))
+    }
+    #[inline]
+    fn store(self, out: &mut [Float]) {
+        out[..4].copy_from_slice(&self.0);
+    }
+    #[inline]
+    fn sqrt(self) -> ScalarLanes {
+        ScalarLanes(self.0.map(Float::sqrt))
+    }
+    #[inline]
+    fn lt(self, rhs: ScalarLanes) -> ScalarMask {
+        ScalarMask(std::array::from_fn(|i| self.0[i] < rhs.0[i]))
+    }
+    #[inline]
+    fn le(self, rhs: ScalarLanes) -> ScalarMask {
+        ScalarMask(std::array::from_fn(|i| self.0[i] <= rhs.0[i]))
+    }
+    #[inline]
+    fn any(mask: ScalarMask) -> bool {
+        return mask.0.contains(&true);
+    }
+    #[inline]
+    fn select(mask: ScalarMask, a: ScalarLanes, b: ScalarLanes) -> ScalarLanes {
+        ScalarLanes(std::array::from_fn(|i| if mask.0[i] { a.0[i] } else { b.0[i] }))
+    }
+}
+
+// Implements FloatLanes for one register type. Masks are registers too, with every bit of a lane set
+// where the comparison holds, so select is (mask & a) | (!mask & b). The intrinsics are unsafe only
+// because the cpu might lack the instructions: sse and sse2 are part of x86_64, and avx lanes can
+// only be made with a Support from detect, which checks for avx unless the build targets it.
+#[cfg(target_arch = "x86_64")]
+macro_rules! x86_lanes {
+    (
+        $name:ident, $mask:ident, $register:ty, $lanes:expr, avx_at_runtime: $avx_at_runtime:expr,
+        support: $support:ty, detect: $detect:expr,
+        set1: $set1:ident, load: $load:ident, store: $store:ident,
+        add: $add:ident, sub: $sub:ident, mul: $mul:ident, div: $div:ident, sqrt: $sqrt:ident,
+        lt: $lt:expr, le: $le:expr, movemask: $movemask:ident,
+        and: $and:ident, andnot: $andnot:ident, or: $or:ident
+    ) => {
+        #[derive(Clone, Copy, Debug)]
+        pub struct $name($register);
+
+        #[derive(Clone, Copy, Debug)]
+        pub struct $mask($register);
+
+        impl BitAnd for $mask {
+            type Output = $mask;
+            #[inline]
+            fn bitand(self, rhs: $mask) -> $mask {
+                $mask(unsafe { $and(self.0, rhs.0) })
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+            #[inline]
+            fn add(self, rhs: $name) -> $name {
+                $name(unsafe { $add(self.0, rhs.0) })
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+            #[inline]
+            fn sub(self, rhs: $name) -> $name {
+                $name(unsafe { $sub(self.0, rhs.0) })
+            }
+        }
+
+        impl Mul for $name {
+            type Output = $name;
+            #[inline]
+            fn mul(self, rhs: $name) -> $name {
+                $name(unsafe { $mul(self.0, rhs.0) })
+            }
+        }
+
+        impl Div for $name {
+            type Output = $name;
+            #[inline]
+            fn div(self, rhs: $name) -> $name {
+                $name(unsafe { $div(self.0, rhs.0) })
+            }
+        }
+
+        impl FloatLanes for $name {
+            type Mask = $mask;
+            const LANES: usize = $lanes;
+            const AVX_AT_RUNTIME: bool = $avx_at_runtime;
+            type Support = $support;
+            #[inline]
+            fn detect() -> Option<$support> {
+                return $detect;
+            }
+            #[inline]
+            fn splat(_: $support, value: Float) -> $name {
+                $name(unsafe { $set1(value) })
+            }
+            #[inline]
+            fn load(_: $support, values: &[Float]) -> $name {
+                let values = &values[..$lanes];
+                // safety: as above, and values holds LANES floats, which the load doesn't need aligned
+                return $name(unsafe { $load(values.as_ptr()) });
+            }
+            #[inline]
+            fn store(self, out: &mut [Float]) {
+                let out = &mut out[..$lanes];
+                // safety: as above, and out holds LANES floats, which the store doesn't need aligned
+                unsafe { $store(out.as_mut_ptr(), self.0) };
+            }
+            #[inline]
+            fn sqrt(self) -> $name {
+                $name(unsafe { $sqrt(self.0) })
+            }
+            #[inline]
+            fn lt(self, rhs: $name) -> $mask {
+                $mask(unsafe { $lt(self.0, rhs.0) })
+            }
+            #[inline]
+            fn le(self, rhs: $name) -> $mask {
+                $mask(unsafe { $le(self.0, rhs.0) })
+            }
+            #[inline]
+            fn any(mask: $mask) -> bool {
+                // one bit per lane, from the lane's sign bit
+                return unsafe { $movemask(mask.0) } != 0;
+            }
+            #[inline]
+            fn select(mask: $mask, a: $name, b: $name) -> $name {
+                $name(unsafe { $or($and(mask.0, a.0), $andnot(mask.0, b.0)) })
+            }
+        }
+    };
+}
+
+// sse and sse2 are part of every x86_64 cpu
+#[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+mod sse {
+    use std::arch::x86_64::*;
+
+    use super::*;
+
+    x86_lanes!(
+        SseLanes, SseMask, __m128, 4, avx_at_runtime: cfg!(not(target_feature = "avx")),
+        support: (), detect: Some(()),
+        set1: _mm_set1_ps, load: _mm_loadu_ps, store: _mm_storeu_ps,
+        add: _mm_add_ps, sub: _mm_sub_ps, mul: _mm_mul_ps, div: _mm_div_ps, sqrt: _mm_sqrt_ps,
+        lt: _mm_cmplt_ps, le: _mm_cmple_ps, movemask: _mm_movemask_ps,
+        and: _mm_and_ps, andnot: _mm_andnot_ps, or: _mm_or_ps
+    );
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "f64"))]
+mod sse {
+    use std::arch::x86_64::*;
+
+    use super::*;
+
+    x86_lanes!(
+        SseLanes, SseMask, __m128d, 2, avx_at_runtime: cfg!(not(target_feature = "avx")),
+        support: (), detect: Some(()),
+        set1: _mm_set1_pd, load: _mm_loadu_pd, store: _mm_storeu_pd,
+        add: _mm_add_pd, sub: _mm_sub_pd, mul: _mm_mul_pd, div: _mm_div_pd, sqrt: _mm_sqrt_pd,
+        lt: _mm_cmplt_pd, le: _mm_cmple_pd, movemask: _mm_movemask_pd,
+        and: _mm_and_pd, andnot: _mm_andnot_pd, or: _mm_or_pd
+    );
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+mod avx {
+    use std::arch::x86_64::*;
+
+    use super::*;
+
+    x86_lanes!(
+        AvxLanes, AvxMask, __m256, 8, avx_at_runtime: false,
+        support: AvxSupport, detect: AvxSupport::detect(),
+        set1: _mm256_set1_ps, load: _mm256_loadu_ps, store: _mm256_storeu_ps,
+        add: _mm256_add_ps, sub: _mm256_sub_ps, mul: _mm256_mul_ps, div: _mm256_div_ps, sqrt: _mm256_sqrt_ps,
+        lt: _mm256_cmp_ps::<_CMP_LT_OQ>, le: _mm256_cmp_ps::<_CMP_LE_OQ>, movemask: _mm256_movemask_ps,
+        and: _mm256_and_ps, andnot: _mm256_andnot_ps, or: _mm256_or_ps
+    );
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "f64"))]
+mod avx {
+    use std::arch::x86_64::*;
+
+    use super::*;
+
+    x86_lanes!(
+        AvxLanes, AvxMask, __m256d, 4, avx_at_runtime: false,
+        support: AvxSupport, detect: AvxSupport::detect(),
+        set1: _mm256_set1_pd, load: _mm256_loadu_pd, store: _mm256_storeu_pd,
+        add: _mm256_add_pd, sub: _mm256_sub_pd, mul: _mm256_mul_pd, div: _mm256_div_pd, sqrt: _mm256_sqrt_pd,
+        lt: _mm256_cmp_pd::<_CMP_LT_OQ>, le: _mm256_cmp_pd::<_CMP_LE_OQ>, movemask: _mm256_movemask_pd,
+        and: _mm256_and_pd, andnot: _mm256_andnot_pd, or: _mm256_or_pd
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+pub use sse::{ SseLanes, SseMask };
+#[cfg(target_arch = "x86_64")]
+pub use avx::{ AvxLanes, AvxMask };
+
+// whether the cpu running the code has avx, which std only works out once
+#[cfg(target_arch = "x86_64")]
+pub fn has_avx() -> bool {
+    return is_x86_feature_detected!("avx");
+}
+
+// Proof that the cpu has avx, the Support of AvxLanes. It can only be had from detect, which is
+// free in builds that target avx.
+#[cfg(target_arch = "x86_64")]
+#[derive(Clone, Copy, Debug)]
+pub struct AvxSupport(());
+
+#[cfg(target_arch = "x86_64")]
+impl AvxSupport {
+    #[inline]
+    pub fn detect() -> Option<AvxSupport> {
+        return if has_avx() { Some(AvxSupport(())) } else { None };
+    }
+}
+
+// the widest lanes the build can use
+#[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+pub type Lanes = AvxLanes;
+#[cfg(all(target_arch = "x86_64", not(target_feature = "avx")))]
+pub type Lanes = SseLanes;
+#[cfg(not(target_arch = "x86_64"))]
+pub type Lanes = ScalarLanes;
+
+const _: () = assert!(Lanes::LANES <= MAX_LANES);
+
+#[cfg(test)]
+use crate::vec3::Dot;
+
+#[cfg(test)]
+fn check_against_scalar<L: FloatLanes>() {
+    let support = L::detect().unwrap();
+    let a: Vec<Float> = (0..L::LANES).map(|i| (i as Float) * 1.7 - 3.1).collect();
+    let b: Vec<Float> = (0..L::LANES).map(|i| 2.3 - (i as Float) * 0.9).collect();
+    let (la, lb) = (L::load(support, &a), L::load(support, &b));
+    let mut out = [0.0; MAX_LANES];
+    let mut check = |lanes: L, expected: &dyn Fn(usize) -> Float| {
+        lanes.store(&mut out);
+        for (i, value) in out[..L::LANES].iter().enumerate() {
+            let expected = expected(i);
+            assert!(value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan()));
+        }
+    };
+    check(la + lb, &|i| a[i] + b[i]);
+    check(la - lb, &|i| a[i] - b[i]);
+    check(la * lb, &|i| a[i] * b[i]);
+    check(la / lb, &|i| a[i] / b[i]);
+    check(la.sqrt(), &|i| Float::sqrt(a[i]));
+    check(L::splat(support, 4.5), &|_| 4.5);
+    let mask = la.lt(lb) & L::splat(support, 0.0).le(lb);
+    check(L::select(mask, la, lb), &|i| if a[i] < b[i] && 0.0 <= b[i] { a[i] } else { b[i] });
+    assert!(L::any(la.le(L::splat(support, a[L::LANES - 1]))));
+    assert!(!L::any(la.lt(L::splat(support, a[0]))));
+    // nan never compares as less
+    let nan = L::splat(support, Float::NAN);
+    check(L::select(nan.lt(lb) & lb.lt(nan), la, lb), &|i| b[i]);
+    assert!(!L::any(nan.le(nan)));
+}
+
+#[test]
+fn test_lanes_match_scalar() {
+    check_against_scalar::<ScalarLanes>();
+    check_against_scalar::<Lanes>();
+    // the avx lanes a build without avx switches to at runtime, which can't be made without avx
+    #[cfg(target_arch = "x86_64")]
+    if has_avx() {
+        check_against_scalar::<AvxLanes>();
+    } else {
+        assert!(AvxSupport::detect().is_none());
+    }
+}
+
+#[cfg(test)]
+fn check_vec3_lanes<L: FloatLanes>() {
+    let a = Vec3::new(1.5, -2.0, 0.25);
+    let b = Vec3::new(-0.5, 3.0, 8.0);
+    let support = L::detect().unwrap();
+    let lanes = Vec3Lanes::<L>::splat(support, a) - Vec3Lanes::splat(support, b);
+    let mut out = [0.0; MAX_LANES];
+    lanes.dot(Vec3Lanes::splat(support, b)).store(&mut out);
+    assert_eq!(out[0], (a - b).dot(b));
+    assert_eq!(out[L::LANES - 1], (a - b).dot(b));
+    lanes.length_squared().store(&mut out);
+    assert_eq!(out[0], (a - b).length_squared());
+    lanes.scale(L::splat(support, 0.3)).x.store(&mut out);
+    assert_eq!(out[0], (0.3 * (a - b)).x());
+}
+
+#[test]
+fn test_vec3_lanes() {
+    check_vec3_lanes::<Lanes>();
+}
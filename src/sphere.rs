@@ -1,66 +1,170 @@
-use std::rc::Rc;
-
 use crate::{
     point3d::Point3D,
     hittable::{ Hittable, HitRecord },
     vec3::Dot,
     interval::Interval,
+    aabb::Aabb,
     ray::Ray,
-    material::Material,
+    float::{ Float, consts, gamma },
 };
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Sphere {
     center: Point3D,
-    radius: f32,
-    material: Rc<dyn Material>,
+    radius: Float,
+    // index into the world's materials
+    material: usize,
 }
 
 impl Sphere {
-    pub fn new(center: Point3D, radius: f32, material: Rc<dyn Material>) -> Sphere {
+    pub fn new(center: Point3D, radius: Float, material: usize) -> Sphere {
         Sphere { center, radius, material }
     }
+    pub fn center(&self) -> Point3D {
+        return self.center;
+    }
+    pub fn radius(&self) -> Float {
+        return self.radius;
+    }
+    pub fn material(&self) -> usize {
+        return self.material;
+    }
+    // p is a point on the unit sphere centered at the origin
+    // u is the angle around the y axis from x=-1, v is the angle from y=-1 to y=+1, both normalized to [0, 1]
+    pub fn get_sphere_uv(p: &Point3D) -> (Float, Float) {
+        let theta = Float::acos(-p.y());
+        let phi = Float::atan2(-p.z(), p.x()) + consts::PI;
+        return (phi / (2.0 * consts::PI), theta / consts::PI);
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
         // formula for ray-sphere intersection
-        // note, for now there is an intentional bug where the camera+scene cannot tell if the sphere is
-        // in front of the camera (-z) or behind the camera (+z), so a sphere with z +1 and -1 will look the same
         let oc = ray.origin() - self.center;
         let a = ray.direction().length_squared();
         let half_b = oc.dot(ray.direction());
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
+        let radius_squared = self.radius * self.radius;
+        // -c, where c is oc.length_squared() - radius_squared
+        let negative_c = radius_squared - oc.length_squared();
+        // The discriminant half_b^2 - a*c cancels catastrophically for a ray from far away, where both
+        // terms are huge and nearly equal. Written as a * (r^2 - d^2), with d the distance from the
+        // center to the closest point on the ray's line, it only cancels where the ray grazes the
+        // sphere, and then by far less.
+        let closest_point = oc - (half_b / a) * ray.direction();
+        let discriminant = a * (radius_squared - closest_point.length_squared());
         if discriminant < 0.0 {
             return None;
         }
-        let sqrtd = f32::sqrt(discriminant);
+        let sqrtd = Float::sqrt(discriminant);
+
+        // The roots are (-half_b -+ sqrtd) / a. One of the two adds numbers of the same sign, the other
+        // subtracts them and loses precision, so it comes from the product of the roots, c / a, instead.
+        let sum = half_b + (if half_b < 0.0 { -sqrtd } else { sqrtd });
+        let t0 = sum / -a;
+        let t1 = negative_c / sum;
+        let (near, far) = if t1 < t0 { (t1, t0) } else { (t0, t1) };
 
         // Find the nearest root that lies in the acceptable range
-        let mut root = (-half_b - sqrtd) / a;
+        let mut root = near;
         if !ray_t.surrounds(root) {
-            root = (-half_b + sqrtd) / a;
+            root = far;
             if !ray_t.surrounds(root) {
                 return None;
             }
         }
-        let p = ray.at(root);
+        // move the hit onto the sphere, after which it is only off by the rounding of these few operations
+        let mut local = ray.at(root) - self.center;
+        local *= Float::abs(self.radius) / local.length();
+        let p = self.center + local;
         // our normals always point against the ray, so we must store which side of the surface the ray is on
         // an alternative to this would be to determine the side of the surface during coloring
-        let outward_normal = (p - self.center) / self.radius;
+        let outward_normal = local / self.radius;
         let front_face = ray.direction().dot(outward_normal) < 0.0;
-        return Some(
-            HitRecord::new(
-                p,
-                if front_face {
-                    outward_normal
-                } else {
-                    -outward_normal
-                },
-                root,
-                front_face,
-                Rc::clone(&self.material)
-            )
+        let (u, v) = Sphere::get_sphere_uv(&outward_normal);
+        let mut hit = HitRecord::new(
+            p,
+            if front_face {
+                outward_normal
+            } else {
+                -outward_normal
+            },
+            root,
+            u,
+            v,
+            front_face,
+            self.material
         );
+        hit.error = gamma(6) * (local.abs() + p.abs());
+        return Some(hit);
+    }
+    fn bounding_box(&self) -> Aabb {
+        // a negative radius turns the sphere inside out without changing its size
+        let r = Point3D::splat(Float::abs(self.radius));
+        return Aabb::new(self.center - r, self.center + r);
+    }
+}
+
+#[test]
+fn test_get_sphere_uv() {
+    let (u, v) = Sphere::get_sphere_uv(&Point3D::new(1.0, 0.0, 0.0));
+    assert_eq!(u, 0.5);
+    assert_eq!(v, 0.5);
+    let (_, v) = Sphere::get_sphere_uv(&Point3D::new(0.0, 1.0, 0.0));
+    assert_eq!(v, 1.0);
+    let (u, _) = Sphere::get_sphere_uv(&Point3D::new(0.0, 0.0, 1.0));
+    assert_eq!(u, 0.25);
+}
+
+#[cfg(test)]
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+#[cfg(test)]
+use crate::{ vec3::{ Vec3, UnitVec }, float::to_f64 };
+
+// a random unit vector on the side of normal that sign picks
+#[cfg(test)]
+fn random_direction(rng: &mut StdRng, normal: Vec3, sign: Float) -> Vec3 {
+    let v = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)).unit_vec();
+    return if v.dot(normal) * sign < 0.0 { -v } else { v };
+}
+
+// Spheres from a millimeter to a hundred kilometers across, seen from afar and from just above the
+// surface like the ground sphere of the random spheres scene. Every hit must land on the sphere
+// within its error bounds, rays leaving it outwards must never hit it again and rays going into it
+// must only hit its far side.
+#[test]
+fn test_sphere_hit_at_scales() {
+    let mut rng = StdRng::seed_from_u64(11);
+    let ray_t = Interval::new(0.0, Float::INFINITY);
+    for exponent in -3..=5 {
+        let radius = (10.0 as Float).powi(exponent);
+        for height in [3.0 * radius, 0.5] {
+            let center = Point3D::new(0.1 * radius, -(radius + height), -0.2 * radius);
+            let sphere = Sphere::new(center, radius, 0);
+            for _ in 0..200 {
+                // somewhere on the sphere's upper half, which the origin can see
+                let target = center + radius * random_direction(&mut rng, Vec3::new(0.0, 1.0, 0.0), 1.0) * 0.99;
+                let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), target);
+                let Some(hit) = sphere.hit(&ray, &ray_t) else {
+                    continue;
+                };
+                assert!(hit.front_face);
+                // how far off the surface the hit is, worked out in f64
+                let offset = [hit.p.x() - center.x(), hit.p.y() - center.y(), hit.p.z() - center.z()];
+                let distance = offset.iter().map(|&v| to_f64(v) * to_f64(v)).sum::<f64>().sqrt();
+                let error = hit.error.length() + 2.0 * Float::EPSILON * center.abs().length();
+                assert!((distance - to_f64(radius)).abs() <= to_f64(error), "{} {:?}", radius, hit);
+
+                for _ in 0..8 {
+                    let outwards = hit.spawn_ray(random_direction(&mut rng, hit.normal, 1.0));
+                    assert!(sphere.hit(&outwards, &ray_t).is_none(), "{} {:?}", radius, hit);
+                    // steeply into the sphere, where the far side is at least a radius away
+                    let inwards = hit.spawn_ray((random_direction(&mut rng, -hit.normal, 1.0) - 2.0 * hit.normal).unit_vec());
+                    let far = sphere.hit(&inwards, &ray_t).unwrap();
+                    assert!(!far.front_face);
+                    assert!(far.t > 0.9 * radius, "{} {}", radius, far.t);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,282 @@
+use std::marker::PhantomData;
+
+use crate::{
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    ray::Ray,
+    sphere::Sphere,
+    aabb::Aabb,
+    simd::{ FloatLanes, Lanes, Vec3Lanes, MAX_LANES },
+    float::Float,
+};
+#[cfg(target_arch = "x86_64")]
+use crate::simd::{ AvxLanes, AvxSupport };
+
+// Spheres kept as a structure of arrays so a ray is tested against L::LANES of them at once. Each
+// lane does exactly the arithmetic Sphere::hit does to find the roots, so the batch hits the same
+// sphere at the same t as a HittableList of the same spheres would. Only the closest sphere gets a
+// hit record, from its own Sphere::hit. The default lanes are the widest the build allows, and
+// builds without avx still switch to it when the cpu has it.
+#[derive(Clone, Debug)]
+pub struct SphereBatch<L: FloatLanes = Lanes> {
+    // padded to a multiple of MAX_LANES with nan, which never hits, so any lanes fit
+    center_x: Vec<Float>,
+    center_y: Vec<Float>,
+    center_z: Vec<Float>,
+    radius_squared: Vec<Float>,
+    spheres: Vec<Sphere>,
+    bounds: Aabb,
+    lanes: PhantomData<L>,
+}
+
+impl<L: FloatLanes> SphereBatch<L> {
+    pub fn new() -> SphereBatch<L> {
+        SphereBatch {
+            center_x: Vec::new(),
+            center_y: Vec::new(),
+            center_z: Vec::new(),
+            radius_squared: Vec::new(),
+            spheres: Vec::new(),
+            bounds: Aabb::EMPTY,
+            lanes: PhantomData,
+        }
+    }
+    pub fn len(&self) -> usize {
+        return self.spheres.len();
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.spheres.is_empty();
+    }
+    pub fn add(&mut self, sphere: Sphere) {
+        let index = self.spheres.len();
+        if index == self.center_x.len() {
+            for values in [&mut self.center_x, &mut self.center_y, &mut self.center_z, &mut self.radius_squared] {
+                values.resize(index + MAX_LANES, Float::NAN);
+            }
+        }
+        let center = sphere.center();
+        self.center_x[index] = center.x();
+        self.center_y[index] = center.y();
+        self.center_z[index] = center.z();
+        self.radius_squared[index] = sphere.radius() * sphere.radius();
+        self.bounds = self.bounds.union(&sphere.bounding_box());
+        self.spheres.push(sphere);
+    }
+}
+
+// Sorts spheres into batches of up to MAX_LANES neighbours, halving them along the longest axis of
+// their centers until each half fits. A tree over the batches then culls whole batches at a time
+// and a ray tests the spheres left in a batch together. A sphere wider than the spread of all the
+// centers, like a ground sphere, would stretch its batch's box over everything and make every ray
+// test its neighbours too, so it gets a batch of its own. Spheres are numbered batch by batch.
+pub fn batch_neighbours<L: FloatLanes>(spheres: Vec<Sphere>) -> Vec<SphereBatch<L>> {
+    fn centers(spheres: &[Sphere]) -> Aabb {
+        return spheres.iter().fold(Aabb::EMPTY, |bounds, sphere| bounds.union(&Aabb::new(sphere.center(), sphere.center())));
+    }
+    fn split<L: FloatLanes>(spheres: &mut [Sphere], batches: &mut Vec<SphereBatch<L>>) {
+        if spheres.len() <= MAX_LANES {
+            let mut batch = SphereBatch::new();
+            for sphere in spheres.iter() {
+                batch.add(*sphere);
+            }
+            batches.push(batch);
+            return;
+        }
+        let axis = centers(spheres).longest_axis();
+        let middle = spheres.len() / 2;
+        spheres.select_nth_unstable_by(middle, |a, b| a.center().axis(axis).total_cmp(&b.center().axis(axis)));
+        let (left, right) = spheres.split_at_mut(middle);
+        split(left, batches);
+        split(right, batches);
+    }
+    let spread = centers(&spheres).extent();
+    let spread = Float::max(spread.x(), Float::max(spread.y(), spread.z()));
+    let (large, mut spheres): (Vec<Sphere>, Vec<Sphere>) = spheres.into_iter().partition(|sphere| 2.0 * sphere.radius() > spread);
+    let mut batches = Vec::new();
+    for sphere in large {
+        let mut batch = SphereBatch::new();
+        batch.add(sphere);
+        batches.push(batch);
+    }
+    if !spheres.is_empty() {
+        split(&mut spheres, &mut batches);
+    }
+    return batches;
+}
+
+impl<L: FloatLanes> Default for SphereBatch<L> {
+    fn default() -> Self {
+        return SphereBatch::new();
+    }
+}
+
+impl<L: FloatLanes> SphereBatch<L> {
+    // Hit with the avx lanes, for builds that don't target avx on cpus that have it. Enabling avx
+    // here, with the lanes' operations always inlined, turns them into plain avx instructions.
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx")]
+    fn hit_avx(&self, support: AvxSupport, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        return self.hit_lanes::<AvxLanes>(support, ray, ray_t);
+    }
+
+    #[inline(always)]
+    fn hit_lanes<W: FloatLanes>(&self, support: W::Support, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let origin = Vec3Lanes::<W>::splat(support, ray.origin());
+        let direction = Vec3Lanes::<W>::splat(support, ray.direction());
+        let a = ray.direction().length_squared();
+        let (positive_a, negative_a) = (W::splat(support, a), W::splat(support, -a));
+        let (min, max) = (W::splat(support, ray_t.min), W::splat(support, ray_t.max));
+        let (zero, miss) = (W::splat(support, 0.0), W::splat(support, Float::INFINITY));
+
+        let mut closest_so_far = ray_t.max;
+        let mut closest = None;
+        let mut roots = [0.0; MAX_LANES];
+        for start in (0..self.center_x.len()).step_by(W::LANES) {
+            let center = Vec3Lanes::<W>::load(support, &self.center_x[start..], &self.center_y[start..], &self.center_z[start..]);
+            let oc = origin - center;
+            let half_b = oc.dot(direction);
+            let radius_squared = W::load(support, &self.radius_squared[start..]);
+            let negative_c = radius_squared - oc.length_squared();
+            let closest_point = oc - direction.scale(half_b / positive_a);
+            let discriminant = positive_a * (radius_squared - closest_point.length_squared());
+            // most rays miss most spheres, so skip the square root and divisions when they all miss
+            if !W::any(zero.le(discriminant)) {
+                continue;
+            }
+            // any other negative discriminant makes the roots nan, which fail the range checks below
+            let sqrtd = discriminant.sqrt();
+            // the roots as Sphere::hit finds them. zero - sqrtd is +0 rather than -0 when sqrtd is 0,
+            // but then it is only added to a negative half_b, which gives the same sum either way.
+            let sum = half_b + W::select(half_b.lt(zero), zero - sqrtd, sqrtd);
+            let t0 = sum / negative_a;
+            let t1 = negative_c / sum;
+            let swap = t1.lt(t0);
+            let near = W::select(swap, t1, t0);
+            let far = W::select(swap, t0, t1);
+            let near_ok = min.lt(near) & near.lt(max);
+            let far_ok = min.lt(far) & far.lt(max);
+            let root = W::select(near_ok, near, W::select(far_ok, far, miss));
+            root.store(&mut roots);
+            // ties go to the first sphere, as they do in a HittableList
+            for (lane, &t) in roots[..W::LANES].iter().enumerate() {
+                if t < closest_so_far {
+                    closest_so_far = t;
+                    closest = Some(start + lane);
+                }
+            }
+        }
+
+        let index = closest?;
+        let mut hit = self.spheres[index].hit(ray, ray_t)?;
+        hit.object_id = index;
+        return Some(hit);
+    }
+}
+
+impl<L: FloatLanes> Hittable for SphereBatch<L> {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        #[cfg(target_arch = "x86_64")]
+        if L::AVX_AT_RUNTIME {
+            if let Some(support) = AvxSupport::detect() {
+                // safety: support shows the cpu has avx
+                return unsafe { self.hit_avx(support, ray, ray_t) };
+            }
+        }
+        let support = L::detect().expect("the cpu lacks the instructions of the sphere batch's lanes");
+        return self.hit_lanes::<L>(support, ray, ray_t);
+    }
+    fn bounding_box(&self) -> Aabb {
+        return self.bounds;
+    }
+    fn object_count(&self) -> usize {
+        return self.spheres.len();
+    }
+}
+
+#[cfg(test)]
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+#[cfg(test)]
+use crate::{
+    bvh::Bvh,
+    primitive::Primitive,
+    hittable_list::HittableList,
+    scene::SceneDescription,
+    simd::ScalarLanes,
+    point3d::Point3D,
+    vec3::Vec3,
+};
+
+// the hits of rays from around the camera into the scene
+#[cfg(test)]
+fn shoot(world: &dyn Hittable, seed: u64) -> Vec<Option<HitRecord>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    return (0..1000)
+        .map(|_| {
+            let origin = Point3D::new(13.0, 2.0, 3.0) + Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+            let target = Point3D::new(rng.gen_range(-11.0..11.0), rng.gen_range(-1.0..2.0), rng.gen_range(-11.0..11.0));
+            let ray = Ray::new(origin, target - origin);
+            world.hit(&ray, &Interval::new(0.001, Float::INFINITY))
+        })
+        .collect();
+}
+
+#[test]
+fn test_batch_matches_scalar_spheres() {
+    let scene = SceneDescription::random_spheres(3);
+    let mut list = HittableList::new();
+    let mut batch: SphereBatch = SphereBatch::new();
+    let mut scalar_batch: SphereBatch<ScalarLanes> = SphereBatch::new();
+    for sphere in scene.build_spheres() {
+        batch.add(sphere);
+        scalar_batch.add(sphere);
+        list.add(sphere);
+    }
+    let expected = shoot(&list, 5);
+    assert!(expected.iter().filter(|hit| hit.is_some()).count() > 500);
+    assert_eq!(shoot(&batch, 5), expected);
+    assert_eq!(shoot(&scalar_batch, 5), expected);
+}
+
+#[test]
+fn test_batch_padding() {
+    let mut batch: SphereBatch = SphereBatch::new();
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+    assert!(batch.is_empty());
+    assert!(batch.hit(&ray, &Interval::new(0.001, Float::INFINITY)).is_none());
+    // more spheres than fit in one set of lanes, the padding lanes after the last must never hit
+    for i in 0..(Lanes::LANES + 1) {
+        batch.add(Sphere::new(Point3D::new(0.0, 10.0 * (i as Float), -5.0), 1.0, 0));
+    }
+    assert_eq!(batch.len(), Lanes::LANES + 1);
+    assert_eq!(batch.object_count(), Lanes::LANES + 1);
+    let hit = batch.hit(&ray, &Interval::new(0.001, Float::INFINITY)).unwrap();
+    assert_eq!((hit.t, hit.object_id), (4.0, 0));
+    let up = Ray::new(Point3D::new(0.0, 10.0 * (Lanes::LANES as Float), 0.0), Vec3::new(0.0, 0.0, -1.0));
+    assert_eq!(batch.hit(&up, &Interval::new(0.001, Float::INFINITY)).unwrap().object_id, Lanes::LANES);
+    // inside a sphere the far root is the hit
+    let inside = Ray::new(Point3D::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0));
+    assert_eq!(batch.hit(&inside, &Interval::new(0.001, Float::INFINITY)).unwrap().t, 1.0);
+}
+
+#[test]
+fn test_batch_neighbours() {
+    let scene = SceneDescription::random_spheres(3);
+    let batches: Vec<SphereBatch> = batch_neighbours(scene.build_spheres());
+    assert!(batches.iter().all(|batch| !batch.is_empty() && batch.len() <= MAX_LANES));
+    assert_eq!(batches.iter().map(|batch| batch.len()).sum::<usize>(), scene.spheres.len());
+    assert!(batch_neighbours::<Lanes>(Vec::new()).is_empty());
+    // the ground sphere is batched on its own
+    assert!(batches.iter().any(|batch| batch.len() == 1 && batch.spheres[0].radius() == 1000.0));
+
+    // the same surfaces as a list of the spheres, only numbered batch by batch
+    let mut list = HittableList::new();
+    for sphere in scene.build_spheres() {
+        list.add(sphere);
+    }
+    let bvh = Bvh::new(batches.into_iter().map(Primitive::SphereBatch).collect());
+    let expected = shoot(&list, 6);
+    let without_id = |hit: Option<HitRecord>| hit.map(|hit| HitRecord { object_id: 0, ..hit });
+    for (hit, expected) in shoot(&bvh, 6).into_iter().zip(expected) {
+        assert_eq!(without_id(hit), without_id(expected));
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{ self, Error, ErrorKind };
+
+use crate::{ vec3::Vec3, float::{ Float, consts } };
+
+// How far a shape goes around its y axis, starting at +x and turning the way a rotation about +y
+// does, so round shapes can be cut open like a pie. The faces of the cut are left open.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sweep {
+    phi_max: Float,
+}
+
+impl Sweep {
+    pub const FULL: Sweep = Sweep { phi_max: consts::TAU };
+
+    // the angle in radians, anything past a full turn is a full turn, fails unless it is finite and
+    // more than 0
+    pub fn new(phi_max: Float) -> io::Result<Sweep> {
+        if phi_max <= 0.0 || !phi_max.is_finite() {
+            return Err(Error::new(ErrorKind::InvalidInput, "a sweep's angle must be finite and more than 0"));
+        }
+        return Ok(Sweep { phi_max: phi_max.min(consts::TAU) });
+    }
+    pub fn phi_max(&self) -> Float {
+        return self.phi_max;
+    }
+    // the angle of p around the y axis, from 0 up to a full turn
+    pub fn angle(p: Vec3) -> Float {
+        let phi = Float::atan2(-p.z(), p.x());
+        return if phi < 0.0 { phi + consts::TAU } else { phi };
+    }
+    // how far round the sweep p is, from 0 to 1, or None if it is in the part cut away
+    pub fn u(&self, p: Vec3) -> Option<Float> {
+        let phi = Sweep::angle(p);
+        return if phi <= self.phi_max { Some(phi / self.phi_max) } else { None };
+    }
+}
+
+#[test]
+fn test_sweep() {
+    let x = Vec3::new(1.0, 0.0, 0.0);
+    let minus_z = Vec3::new(0.0, 5.0, -1.0);
+    assert_eq!(Sweep::angle(x), 0.0);
+    assert_eq!(Sweep::angle(minus_z), consts::FRAC_PI_2);
+    assert_eq!(Sweep::angle(-x), consts::PI);
+    assert_eq!(Sweep::FULL.u(minus_z), Some(0.25));
+    let half = Sweep::new(consts::PI).unwrap();
+    assert_eq!(half.u(minus_z), Some(0.5));
+    assert_eq!(half.u(Vec3::new(0.0, 0.0, 1.0)), None);
+    assert_eq!(Sweep::new(10.0).unwrap(), Sweep::FULL);
+    for phi_max in [0.0, -1.0, Float::INFINITY, Float::NAN] {
+        assert_eq!(Sweep::new(phi_max).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{ io::{ self, Write }, time::Duration };
+
+use crate::{ color::Color, image::Image, tonemap::DisplayTransform, observer::RenderStatus, float::Float };
+
+// Draws the render in progress straight into the terminal, so a render running over ssh can be
+// watched without copying images around. Every character cell shows two pixels stacked on top of
+// each other: the upper half block character in the top pixel's color over a background in the
+// bottom pixel's color, using 24 bit ansi colors.
+#[derive(Clone, Copy, Debug)]
+pub struct TerminalPreviewSettings {
+    // width of the preview in characters, it is never wider than the image
+    pub columns: u32,
+    // redraw at most this often
+    pub refresh_interval: Duration,
+}
+
+impl Default for TerminalPreviewSettings {
+    fn default() -> Self {
+        TerminalPreviewSettings {
+            columns: 80,
+            refresh_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+pub struct TerminalPreview {
+    settings: TerminalPreviewSettings,
+    // lines printed by the last draw, which the next draw moves the cursor back up over
+    lines_drawn: usize,
+}
+
+impl TerminalPreview {
+    pub fn new(settings: TerminalPreviewSettings) -> TerminalPreview {
+        TerminalPreview { settings, lines_drawn: 0 }
+    }
+    pub fn settings(&self) -> &TerminalPreviewSettings {
+        return &self.settings;
+    }
+    // draws over the previous preview, if there was one
+    pub fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        image: &Image,
+        display: &DisplayTransform,
+        status: &RenderStatus
+    ) -> io::Result<()> {
+        let mut text = String::new();
+        if self.lines_drawn > 0 {
+            text += &format!("\x1b[{}A\r", self.lines_drawn);
+        }
+        let lines = half_block_lines(image, display, self.settings.columns);
+        for line in &lines {
+            text += line;
+            text += "\n";
+        }
+        // clear the rest of the status line in case the last one was longer
+        text += &format!("{}\x1b[K\n", status);
+        out.write_all(text.as_bytes())?;
+        out.flush()?;
+        self.lines_drawn = lines.len() + 1;
+        return Ok(());
+    }
+}
+
+// the image shrunk to at most columns characters wide, as lines of colored half blocks
+pub fn half_block_lines(image: &Image, display: &DisplayTransform, columns: u32) -> Vec<String> {
+    let width = i32::min(columns.max(1) as i32, image.width());
+    // keep the image's aspect ratio with square preview pixels, two to a character
+    let pixel_rows = i32::max(((width as Float) * (image.height() as Float) / (image.width() as Float)).round() as i32, 1);
+    let rows = (pixel_rows + 1) / 2;
+    let mut lines = Vec::new();
+    for row in 0..rows {
+        let mut line = String::new();
+        for column in 0..width {
+            let [tr, tg, tb] = display.to_rgb8(average(image, column, 2 * row, width, pixel_rows));
+            let [br, bg, bb] = if 2 * row + 1 < pixel_rows {
+                display.to_rgb8(average(image, column, 2 * row + 1, width, pixel_rows))
+            } else {
+                [0, 0, 0]
+            };
+            line += &format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}", tr, tg, tb, br, bg, bb);
+        }
+        line += "\x1b[0m";
+        lines.push(line);
+    }
+    return lines;
+}
+
+// the average of the image pixels covered by preview pixel x,y of a width x height preview
+fn average(image: &Image, x: i32, y: i32, width: i32, height: i32) -> Color {
+    let span = |p: i32, preview_size: i32, image_size: i32| {
+        let start = p * image_size / preview_size;
+        let end = i32::max((p + 1) * image_size / preview_size, start + 1);
+        start..i32::min(end, image_size)
+    };
+    let mut sum = Color::new(0.0, 0.0, 0.0);
+    let mut count = 0;
+    for j in span(y, height, image.height()) {
+        for i in span(x, width, image.width()) {
+            sum += image.get(i, j);
+            count += 1;
+        }
+    }
+    return sum * (1.0 / (count.max(1) as Float));
+}
+
+#[test]
+fn test_half_block_lines() {
+    // a 4x2 image: the top row white, the bottom row black
+    let mut image = Image::new(4, 2);
+    for i in 0..4 {
+        image.set(i, 0, Color::new(1.0, 1.0, 1.0));
+    }
+    let lines = half_block_lines(&image, &DisplayTransform::default(), 2);
+    // two columns by one row of preview pixels (4x2 at half the width), rounded up to one line
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].matches('\u{2580}').count(), 2);
+    // the top row is averaged with the black bottom row, the missing bottom half is black
+    assert!(lines[0].starts_with("\x1b[38;2;181;181;181m\x1b[48;2;0;0;0m"));
+
+    let lines = half_block_lines(&image, &DisplayTransform::default(), 80);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].matches('\u{2580}').count(), 4);
+    assert!(lines[0].starts_with("\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m"));
+}
+
+#[test]
+fn test_preview_redraws_in_place() {
+    let image = Image::new(4, 4);
+    let status = RenderStatus { samples: 0, resumed_samples: 0, total_samples: 16, pixels: 16, elapsed: Duration::ZERO };
+    let mut preview = TerminalPreview::new(TerminalPreviewSettings::default());
+    let mut out = Vec::new();
+    preview.draw(&mut out, &image, &DisplayTransform::default(), &status).unwrap();
+    assert!(String::from_utf8_lossy(&out).starts_with("\x1b[38;2;"));
+    out.clear();
+    preview.draw(&mut out, &image, &DisplayTransform::default(), &status).unwrap();
+    // two lines of blocks and the status line
+    assert!(String::from_utf8_lossy(&out).starts_with("\x1b[3A\r"));
+}
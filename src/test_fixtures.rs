@@ -0,0 +1,18 @@
+use crate::{ camera::Camera, point3d::Point3D, vec3::Vec3, float::Float };
+
+// a tiny camera looking at SceneDescription::random_spheres the way the binary's default camera
+// does, for tests that render the built in scene
+pub fn spheres_camera(aspect_ratio: Float, image_width: i32, samples_per_pixel: i32, defocus_angle: Float) -> Camera {
+    return Camera::new(
+        aspect_ratio,
+        image_width,
+        samples_per_pixel,
+        4,
+        20.0,
+        Point3D::new(13.0, 2.0, 3.0),
+        Point3D::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle,
+        10.0
+    );
+}
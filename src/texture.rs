@@ -0,0 +1,134 @@
+use std::{ fmt::Debug, sync::Arc };
+
+use crate::{ color::Color, point3d::Point3D, float::Float };
+
+// This is implemented by anything that can be looked up at a surface point, u and v are the surface
+// coordinates in [0, 1] and p is the hit point in world space
+pub trait SurfaceTexture: Debug + Send + Sync {
+    fn value(&self, u: Float, v: Float, p: &Point3D) -> Color;
+}
+
+// Every texture a primitive can hold, the built in ones as variants like Material and anything else
+// that implements SurfaceTexture in Custom
+#[derive(Clone, Debug)]
+pub enum Texture {
+    Solid(SolidColor),
+    Checker(CheckerTexture),
+    Stripe(StripeTexture),
+    Custom(Arc<dyn SurfaceTexture>),
+}
+
+impl SurfaceTexture for Texture {
+    fn value(&self, u: Float, v: Float, p: &Point3D) -> Color {
+        return match self {
+            Texture::Solid(texture) => texture.value(u, v, p),
+            Texture::Checker(texture) => texture.value(u, v, p),
+            Texture::Stripe(texture) => texture.value(u, v, p),
+            Texture::Custom(texture) => texture.value(u, v, p),
+        };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl SurfaceTexture for SolidColor {
+    fn value(&self, _u: Float, _v: Float, _p: &Point3D) -> Color {
+        return self.color;
+    }
+}
+
+// alternates between two colors on a grid laid out in uv space, scale is the number of
+// squares along each of u and v
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CheckerTexture {
+    pub even: Color,
+    pub odd: Color,
+    pub scale: Float,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Color, odd: Color, scale: Float) -> CheckerTexture {
+        CheckerTexture { even, odd, scale }
+    }
+}
+
+impl SurfaceTexture for CheckerTexture {
+    fn value(&self, u: Float, v: Float, _p: &Point3D) -> Color {
+        let u_index = Float::floor(u * self.scale) as i32;
+        let v_index = Float::floor(v * self.scale) as i32;
+        return if (u_index + v_index) % 2 == 0 { self.even } else { self.odd };
+    }
+}
+
+// horizontal bands in uv space, useful as a cheap fence or slatted mask when used as an opacity texture
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StripeTexture {
+    pub on: Color,
+    pub off: Color,
+    pub count: Float,
+    pub duty: Float,
+}
+
+impl StripeTexture {
+    pub fn new(on: Color, off: Color, count: Float, duty: Float) -> StripeTexture {
+        StripeTexture { on, off, count, duty }
+    }
+}
+
+impl SurfaceTexture for StripeTexture {
+    fn value(&self, _u: Float, v: Float, _p: &Point3D) -> Color {
+        let phase = v * self.count - Float::floor(v * self.count);
+        return if phase < self.duty { self.on } else { self.off };
+    }
+}
+
+#[test]
+fn test_solid_color() {
+    let t = SolidColor::new(Color::new(0.1, 0.2, 0.3));
+    let c = t.value(0.5, 0.5, &Point3D::new(0.0, 0.0, 0.0));
+    assert_eq!(c.x(), 0.1);
+    assert_eq!(c.y(), 0.2);
+    assert_eq!(c.z(), 0.3);
+}
+
+#[test]
+fn test_checker_texture() {
+    let t = CheckerTexture::new(Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0), 2.0);
+    let p = Point3D::new(0.0, 0.0, 0.0);
+    assert_eq!(t.value(0.25, 0.25, &p).x(), 1.0);
+    assert_eq!(t.value(0.75, 0.25, &p).x(), 0.0);
+    assert_eq!(t.value(0.75, 0.75, &p).x(), 1.0);
+}
+
+#[test]
+fn test_stripe_texture() {
+    let t = StripeTexture::new(Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0), 4.0, 0.5);
+    let p = Point3D::new(0.0, 0.0, 0.0);
+    assert_eq!(t.value(0.0, 0.1, &p).x(), 1.0);
+    assert_eq!(t.value(0.0, 0.2, &p).x(), 0.0);
+}
+
+#[test]
+fn test_custom_texture() {
+    // a texture defined outside the library, looked up through the enum like the built in ones
+    #[derive(Debug)]
+    struct UvGradient;
+    impl SurfaceTexture for UvGradient {
+        fn value(&self, u: Float, v: Float, _p: &Point3D) -> Color {
+            return Color::new(u, v, 0.0);
+        }
+    }
+    let t = Texture::Custom(Arc::new(UvGradient));
+    let c = t.value(0.25, 0.75, &Point3D::new(0.0, 0.0, 0.0));
+    assert_eq!(c.x(), 0.25);
+    assert_eq!(c.y(), 0.75);
+}
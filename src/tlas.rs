@@ -0,0 +1,178 @@
+use std::io;
+
+use crate::{
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    instance::Instance,
+    matrix::Matrix4,
+    bvh::Bvh,
+    ray::Ray,
+    aabb::Aabb,
+};
+
+// The top level of a two level acceleration structure: a Bvh over instances, each pointing at a
+// shared bottom level object (usually a Bvh of its own) with a transform. Moving instances only
+// touches this level, the objects they share are never rebuilt. Object ids count through every
+// instance's objects in the order the instances were given.
+#[derive(Clone, Debug)]
+pub struct Tlas {
+    bvh: Bvh<Instance>,
+}
+
+impl Tlas {
+    pub fn new(instances: Vec<Instance>) -> Tlas {
+        Tlas { bvh: Bvh::new(instances) }
+    }
+    pub fn len(&self) -> usize {
+        return self.bvh.len();
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.bvh.is_empty();
+    }
+    // the index-th instance given to new
+    pub fn instance(&self, index: usize) -> &Instance {
+        return self.bvh.object(index);
+    }
+    // Moves an instance, or leaves it where it was if the transform can't be inverted. Call update
+    // once all the instances for a frame have been moved, before rendering it.
+    pub fn set_transform(&mut self, index: usize, transform: Matrix4) -> io::Result<()> {
+        return self.bvh.object_mut(index).set_transform(transform);
+    }
+    // Refits the tree around the instances where they are now, which only recomputes the boxes and
+    // is all a frame of an animation usually needs.
+    pub fn update(&mut self) {
+        self.bvh.refit();
+    }
+    // Builds the tree again from scratch, for when instances have moved so far from where they were
+    // that the refit boxes overlap and slow traversal down. With one node per instance it is cheap.
+    pub fn rebuild(&mut self) {
+        self.bvh.rebuild();
+    }
+}
+
+impl Hittable for Tlas {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        return self.bvh.hit(ray, ray_t);
+    }
+    fn bounding_box(&self) -> Aabb {
+        return self.bvh.bounding_box();
+    }
+    fn object_count(&self) -> usize {
+        return self.bvh.object_count();
+    }
+}
+
+#[cfg(test)]
+use std::sync::Arc;
+#[cfg(test)]
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+#[cfg(test)]
+use crate::{
+    hittable_list::HittableList,
+    primitive::Primitive,
+    sphere::Sphere,
+    point3d::Point3D,
+    vec3::{ Vec3, Dot, UnitVec },
+    float::Float,
+};
+
+// a little cluster of spheres to instance, and where the copies of it go
+#[cfg(test)]
+fn cluster() -> (Vec<Sphere>, Vec<Matrix4>) {
+    let spheres = vec![
+        Sphere::new(Point3D::new(0.0, 0.0, 0.0), 0.3, 0),
+        Sphere::new(Point3D::new(0.4, 0.1, 0.0), 0.15, 1),
+        Sphere::new(Point3D::new(-0.2, 0.35, 0.2), 0.1, 2),
+    ];
+    let mut rng = StdRng::seed_from_u64(8);
+    let transforms = (0..1000)
+        .map(|_| {
+            let offset = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-30.0..-10.0));
+            let axis = Vec3::new(rng.gen_range(-1.0..1.0), 1.0, rng.gen_range(-1.0..1.0));
+            Matrix4::translation(offset)
+                * Matrix4::rotation(axis, rng.gen_range(0.0..6.0))
+                * Matrix4::scaling(Vec3::splat(rng.gen_range(0.5..2.0)))
+        })
+        .collect();
+    return (spheres, transforms);
+}
+
+// the same scene with every sphere of every copy transformed into a plain list, which only works
+// because moved, turned and evenly scaled spheres are still spheres
+#[cfg(test)]
+fn flattened(spheres: &[Sphere], transforms: &[Matrix4]) -> HittableList {
+    let mut list = HittableList::new();
+    for transform in transforms {
+        let scale = transform.transform_vector(Vec3::new(1.0, 0.0, 0.0)).length();
+        for sphere in spheres {
+            list.add(Sphere::new(transform.transform_point(sphere.center()), sphere.radius() * scale, sphere.material()));
+        }
+    }
+    return list;
+}
+
+// compares the hits of rays from the origin into the scene, allowing for rounding in the transforms
+#[cfg(test)]
+fn assert_same_hits(tlas: &Tlas, list: &HittableList, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let ray_t = Interval::new(0.001, Float::INFINITY);
+    let mut hits = 0;
+    for _ in 0..2000 {
+        let target = Point3D::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), -20.0);
+        let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), target);
+        match (tlas.hit(&ray, &ray_t), list.hit(&ray, &ray_t)) {
+            (Some(hit), Some(expected)) => {
+                hits += 1;
+                assert_eq!((hit.object_id, hit.material), (expected.object_id, expected.material));
+                assert!((hit.t - expected.t).abs() < 1e-3 * expected.t);
+                // where a ray only grazes a sphere rounding can decide which side of it the ray is on,
+                // and the list's sphere normals are only as unit length as the rounding allows
+                if ray.direction().unit_vec().dot(expected.normal).abs() > 0.05 {
+                    assert_eq!(hit.front_face, expected.front_face);
+                    assert!(hit.normal.dot(expected.normal.unit_vec()) > 0.999);
+                }
+            }
+            (hit, expected) => assert_eq!(hit.is_some(), expected.is_some()),
+        }
+    }
+    assert!(hits > 200);
+}
+
+#[test]
+fn test_tlas_matches_flattened_scene() {
+    let (spheres, transforms) = cluster();
+    let bottom = Arc::new(Primitive::Bvh(Bvh::new(spheres.iter().map(|&sphere| Primitive::Sphere(sphere)).collect())));
+    let tlas = Tlas::new(transforms.iter().map(|&transform| Instance::new(bottom.clone(), transform).unwrap()).collect());
+    // one copy of the spheres however many instances there are
+    assert_eq!(Arc::strong_count(&bottom), transforms.len() + 1);
+    assert_eq!(tlas.len(), transforms.len());
+    assert_eq!(tlas.object_count(), transforms.len() * spheres.len());
+    assert_same_hits(&tlas, &flattened(&spheres, &transforms), 9);
+}
+
+#[test]
+fn test_tlas_update() {
+    let (spheres, mut transforms) = cluster();
+    let bottom = Arc::new(Primitive::Bvh(Bvh::new(spheres.iter().map(|&sphere| Primitive::Sphere(sphere)).collect())));
+    let mut tlas = Tlas::new(transforms.iter().map(|&transform| Instance::new(bottom.clone(), transform).unwrap()).collect());
+    // move half the instances somewhere else entirely, as the next frame of an animation might
+    for (index, transform) in transforms.iter_mut().enumerate().step_by(2) {
+        *transform = Matrix4::translation(Vec3::new(0.0, 3.0, 5.0)) * *transform;
+        tlas.set_transform(index, *transform).unwrap();
+    }
+    tlas.update();
+    // the instances still share the same, untouched bottom level
+    assert!(Arc::ptr_eq(tlas.instance(0).object(), &bottom));
+    assert_eq!(Arc::strong_count(&bottom), transforms.len() + 1);
+    assert_eq!(tlas.instance(2).transform(), transforms[2]);
+    let list = flattened(&spheres, &transforms);
+    // the instances' boxes are around their turned boxes, so a little bigger than around the spheres,
+    // and the moved instances reach closer than any did before
+    assert_eq!(tlas.bounding_box().union(&list.bounding_box()), tlas.bounding_box());
+    assert!(tlas.bounding_box().max.z() > -8.0);
+    assert_same_hits(&tlas, &list, 10);
+    // a move that far is worth a rebuild, which keeps the instances' indices
+    tlas.rebuild();
+    assert_eq!(tlas.instance(2).transform(), transforms[2]);
+    assert_same_hits(&tlas, &list, 11);
+}
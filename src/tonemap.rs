@@ -0,0 +1,218 @@
+use crate::{ color::Color, interval::Interval, float::Float };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMapper {
+    // no curve, values above 1 simply clip
+    Clamp,
+    // maps luminance l to l / (1 + l), hue is kept by scaling all channels together
+    Reinhard,
+    // reinhard that reaches 1 at the given white point instead of at infinity
+    ExtendedReinhard {
+        white: Float,
+    },
+    // stephen hill's fit of the aces reference rendering and output transforms
+    AcesFitted,
+    // troy sobotka's agx using the common polynomial fit of its default contrast curve,
+    // desaturates very bright colors towards white instead of skewing their hue
+    Agx,
+}
+
+impl ToneMapper {
+    pub fn parse(name: &str) -> Option<ToneMapper> {
+        return match name {
+            "clamp" => Some(ToneMapper::Clamp),
+            "reinhard" => Some(ToneMapper::Reinhard),
+            "extended-reinhard" => Some(ToneMapper::ExtendedReinhard { white: 4.0 }),
+            "aces" => Some(ToneMapper::AcesFitted),
+            "agx" => Some(ToneMapper::Agx),
+            _ => None,
+        };
+    }
+    // maps scene linear color to display linear color, mostly in [0, 1]
+    pub fn apply(&self, color: Color) -> Color {
+        return match self {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard => ToneMapper::scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMapper::ExtendedReinhard { white } => {
+                let white_squared = white * white;
+                ToneMapper::scale_luminance(color, |l| (l * (1.0 + l / white_squared)) / (1.0 + l))
+            }
+            ToneMapper::AcesFitted => ToneMapper::aces_fitted(color),
+            ToneMapper::Agx => ToneMapper::agx(color),
+        };
+    }
+    fn scale_luminance(color: Color, curve: impl Fn(Float) -> Float) -> Color {
+        let luminance = color.luminance();
+        if luminance <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        return color * (curve(luminance) / luminance);
+    }
+    fn aces_fitted(color: Color) -> Color {
+        // srgb => xyz => d65_2_d60 => ap1 => rrt_sat
+        const INPUT: [[Float; 3]; 3] = [
+            [0.59719, 0.35458, 0.04823],
+            [0.076, 0.90834, 0.01566],
+            [0.0284, 0.13383, 0.83777],
+        ];
+        // odt_sat => xyz => d60_2_d65 => srgb
+        const OUTPUT: [[Float; 3]; 3] = [
+            [1.60475, -0.53108, -0.07367],
+            [-0.10208, 1.10813, -0.00605],
+            [-0.00327, -0.07276, 1.07602],
+        ];
+        let rrt_and_odt_fit = |v: Float| {
+            let a = v * (v + 0.0245786) - 0.000090537;
+            let b = v * (0.983729 * v + 0.432951) + 0.238081;
+            a / b
+        };
+        let v = mul(&INPUT, color);
+        let v = Color::new(rrt_and_odt_fit(v.x()), rrt_and_odt_fit(v.y()), rrt_and_odt_fit(v.z()));
+        return mul(&OUTPUT, v);
+    }
+    fn agx(color: Color) -> Color {
+        const INSET: [[Float; 3]; 3] = [
+            [0.8424791, 0.0784336, 0.07922375],
+            [0.04232824, 0.8784686, 0.07916613],
+            [0.04237565, 0.0784336, 0.879143],
+        ];
+        const OUTSET: [[Float; 3]; 3] = [
+            [1.196879, -0.09802088, -0.09902974],
+            [-0.05289685, 1.151903, -0.09896118],
+            [-0.05297164, -0.09804345, 1.151074],
+        ];
+        const MIN_EV: Float = -12.47393;
+        const MAX_EV: Float = 4.026069;
+        let encode = |v: Float| {
+            let ev = Float::log2(Float::max(v, 1e-10)).clamp(MIN_EV, MAX_EV);
+            let x = (ev - MIN_EV) / (MAX_EV - MIN_EV);
+            let x2 = x * x;
+            let x4 = x2 * x2;
+            15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x -
+                0.00232
+        };
+        let v = mul(&INSET, color);
+        let v = mul(&OUTSET, Color::new(encode(v.x()), encode(v.y()), encode(v.z())));
+        // the curve produces display encoded values for a 2.2 gamma display, so decode them back to linear
+        let decode = |v: Float| Float::powf(Float::max(v, 0.0), 2.2);
+        return Color::new(decode(v.x()), decode(v.y()), decode(v.z()));
+    }
+}
+
+fn mul(m: &[[Float; 3]; 3], c: Color) -> Color {
+    return Color::new(
+        m[0][0] * c.x() + m[0][1] * c.y() + m[0][2] * c.z(),
+        m[1][0] * c.x() + m[1][1] * c.y() + m[1][2] * c.z(),
+        m[2][0] * c.x() + m[2][1] * c.y() + m[2][2] * c.z()
+    );
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransferFunction {
+    // a plain square root, what the renderer has always written
+    Gamma2,
+    // the exact piecewise srgb curve (iec 61966-2-1)
+    Srgb,
+}
+
+impl TransferFunction {
+    pub fn parse(name: &str) -> Option<TransferFunction> {
+        return match name {
+            "gamma2" => Some(TransferFunction::Gamma2),
+            "srgb" => Some(TransferFunction::Srgb),
+            _ => None,
+        };
+    }
+    pub fn encode(&self, linear: Float) -> Float {
+        return match self {
+            TransferFunction::Gamma2 => Color::linear_to_gamma(linear),
+            TransferFunction::Srgb => Color::linear_to_srgb(linear),
+        };
+    }
+}
+
+// turns linear scene radiance into the 8 bit values written to images:
+// exposure, then the tone curve, then the display transfer function, then quantization
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayTransform {
+    // in stops, every +1 doubles the brightness of the image
+    pub exposure_ev: Float,
+    pub tone_mapper: ToneMapper,
+    pub transfer: TransferFunction,
+}
+
+impl DisplayTransform {
+    pub fn new(exposure_ev: Float, tone_mapper: ToneMapper, transfer: TransferFunction) -> DisplayTransform {
+        DisplayTransform { exposure_ev, tone_mapper, transfer }
+    }
+    // display encoded color with every channel in [0, 1]
+    pub fn apply(&self, linear: Color) -> Color {
+        let exposed = linear * Float::exp2(self.exposure_ev);
+        let mapped = self.tone_mapper.apply(exposed);
+        let unit = Interval::new(0.0, 1.0);
+        return Color::new(
+            unit.clamp(self.transfer.encode(Float::max(mapped.x(), 0.0))),
+            unit.clamp(self.transfer.encode(Float::max(mapped.y(), 0.0))),
+            unit.clamp(self.transfer.encode(Float::max(mapped.z(), 0.0)))
+        );
+    }
+    pub fn to_rgb8(&self, linear: Color) -> [i32; 3] {
+        let display = self.apply(linear);
+        let intensity = Interval::new(0.0, 0.999);
+        return [
+            (256.0 * intensity.clamp(display.x())) as i32,
+            (256.0 * intensity.clamp(display.y())) as i32,
+            (256.0 * intensity.clamp(display.z())) as i32,
+        ];
+    }
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        return DisplayTransform::new(0.0, ToneMapper::Clamp, TransferFunction::Gamma2);
+    }
+}
+
+#[test]
+fn test_default_display_transform_matches_gamma2() {
+    let display = DisplayTransform::default();
+    let [r, g, b] = display.to_rgb8(Color::new(0.25, 1.0, 4.0));
+    assert_eq!(r, 128);
+    assert_eq!(g, 255);
+    assert_eq!(b, 255);
+}
+
+#[test]
+fn test_exposure_doubles_per_stop() {
+    let display = DisplayTransform::new(1.0, ToneMapper::Clamp, TransferFunction::Gamma2);
+    let c = display.apply(Color::new(0.125, 0.0, 0.0));
+    assert_eq!(c.x(), 0.5);
+}
+
+#[test]
+fn test_tone_mappers_compress_highlights() {
+    for tone_mapper in [
+        ToneMapper::Reinhard,
+        ToneMapper::ExtendedReinhard { white: 4.0 },
+        ToneMapper::AcesFitted,
+        ToneMapper::Agx,
+    ] {
+        let display = DisplayTransform::new(0.0, tone_mapper, TransferFunction::Srgb);
+        let dark = display.apply(Color::new(0.0, 0.0, 0.0));
+        let mid = display.apply(Color::new(0.18, 0.18, 0.18));
+        let bright = display.apply(Color::new(8.0, 8.0, 8.0));
+        let brighter = display.apply(Color::new(16.0, 16.0, 16.0));
+        assert!(dark.y() < 0.01);
+        assert!(dark.y() < mid.y());
+        assert!(mid.y() < bright.y());
+        // highlights roll off rather than clip, so doubling stays distinguishable up to very bright values
+        assert!(bright.y() < brighter.y() || brighter.y() == 1.0);
+        assert!(bright.y() <= 1.0);
+    }
+}
+
+#[test]
+fn test_extended_reinhard_reaches_white() {
+    let mapped = ToneMapper::ExtendedReinhard { white: 4.0 }.apply(Color::new(4.0, 4.0, 4.0));
+    assert!((mapped.luminance() - 1.0).abs() < 1e-4);
+}
@@ -0,0 +1,157 @@
+use std::io::{ self, Error, ErrorKind };
+
+use crate::{
+    point3d::Point3D,
+    hittable::{ Hittable, HitRecord },
+    interval::Interval,
+    polynomial::{ quadratic_roots, real_roots },
+    sweep::Sweep,
+    aabb::Aabb,
+    ray::Ray,
+    vec3::{ Vec3, Dot },
+    float::{ Float, consts, gamma, to_f64, from_f64 },
+};
+
+// A ring around the y axis through center: a tube of minor_radius swept around a circle of
+// major_radius lying flat in the xz plane. minor_radius has to be the smaller, so the tube doesn't
+// overlap itself through the axis. The ring can be swept only part of the way round, which leaves
+// the tube's ends open. Other orientations come from an Instance.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Torus {
+    center: Point3D,
+    major_radius: Float,
+    minor_radius: Float,
+    sweep: Sweep,
+    // index into the world's materials
+    material: usize,
+}
+
+impl Torus {
+    // fails unless 0 < minor_radius < major_radius
+    pub fn new(center: Point3D, major_radius: Float, minor_radius: Float, material: usize) -> io::Result<Torus> {
+        if !(minor_radius > 0.0 && minor_radius < major_radius && major_radius.is_finite()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "a torus's minor radius must be more than 0 and less than its major radius"));
+        }
+        return Ok(Torus { center, major_radius, minor_radius, sweep: Sweep::FULL, material });
+    }
+    pub fn with_sweep(self, sweep: Sweep) -> Torus {
+        Torus { sweep, ..self }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let o = ray.origin() - self.center;
+        let d = ray.direction();
+        let (major, minor) = (self.major_radius, self.minor_radius);
+
+        // only the part of the ray inside the torus's bounding sphere can hit it
+        let bound = major + minor;
+        let a = d.length_squared();
+        let half_b = o.dot(d);
+        let closest_point = o - (half_b / a) * d;
+        let discriminant = a * (bound * bound - closest_point.length_squared());
+        let (enter, exit) = quadratic_roots(a, half_b, o.length_squared() - bound * bound, discriminant)?;
+        let (lo, hi) = (Float::max(enter, ray_t.min), Float::min(exit, ray_t.max));
+        if lo >= hi {
+            return None;
+        }
+
+        // The torus is where (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2), a quartic in t along the ray.
+        // It is solved in f64 from the point where the ray enters the bounding sphere, which keeps
+        // the coefficients small however far away the ray starts.
+        let start = o + lo * d;
+        let (ox, oy, oz) = (to_f64(start.x()), to_f64(start.y()), to_f64(start.z()));
+        let (dx, dy, dz) = (to_f64(d.x()), to_f64(d.y()), to_f64(d.z()));
+        let (major_squared, minor_squared) = (to_f64(major) * to_f64(major), to_f64(minor) * to_f64(minor));
+        let quadratic = [dx * dx + dy * dy + dz * dz, 2.0 * (ox * dx + oy * dy + oz * dz), ox * ox + oy * oy + oz * oz + major_squared - minor_squared];
+        let planar = [dx * dx + dz * dz, 2.0 * (ox * dx + oz * dz), ox * ox + oz * oz];
+        let [qa, qb, qc] = quadratic;
+        let four_major_squared = 4.0 * major_squared;
+        let quartic = [
+            qa * qa,
+            2.0 * qa * qb,
+            qb * qb + 2.0 * qa * qc - four_major_squared * planar[0],
+            2.0 * qb * qc - four_major_squared * planar[1],
+            qc * qc - four_major_squared * planar[2],
+        ];
+
+        for &root in real_roots(&quartic, 0.0, to_f64(hi - lo)).as_slice() {
+            let t = lo + from_f64(root);
+            if !ray_t.surrounds(t) {
+                continue;
+            }
+            let local = o + t * d;
+            let Some(u) = self.sweep.u(local) else {
+                continue;
+            };
+            // move the hit onto the tube, out from the nearest point of the circle through its middle
+            let distance = Float::sqrt(local.x() * local.x() + local.z() * local.z());
+            if distance == 0.0 {
+                continue;
+            }
+            let ring = Vec3::new(local.x() * major / distance, 0.0, local.z() * major / distance);
+            let tube = local - ring;
+            let outward_normal = tube / tube.length();
+            let local = ring + minor * outward_normal;
+            // v goes once round the tube, starting on the outside of the ring
+            let theta = Float::atan2(outward_normal.y(), (distance - major).signum() * Float::sqrt(1.0 - outward_normal.y() * outward_normal.y()));
+            let v = (if theta < 0.0 { theta + consts::TAU } else { theta }) / consts::TAU;
+
+            let p = self.center + local;
+            let mut hit = HitRecord::new(p, outward_normal, t, u, v, false, self.material);
+            hit.set_face_normal(ray, &outward_normal);
+            hit.error = gamma(8) * (local.abs() + p.abs());
+            return Some(hit);
+        }
+        return None;
+    }
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.major_radius + self.minor_radius, self.minor_radius, self.major_radius + self.minor_radius);
+        return Aabb::new(self.center - r, self.center + r);
+    }
+}
+
+#[test]
+fn test_torus_hit() {
+    let torus = Torus::new(Point3D::new(0.0, 0.0, -10.0), 2.0, 0.5, 5).unwrap();
+    let ray_t = Interval::new(0.0, Float::INFINITY);
+    assert_eq!(torus.bounding_box(), Aabb::new(Point3D::new(-2.5, -0.5, -12.5), Point3D::new(2.5, 0.5, -7.5)));
+
+    // along the ring's plane through the middle, meeting the outside of the near side of the tube
+    let hit = torus.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert!((hit.t - 7.5).abs() < 1e-5);
+    assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-5);
+    assert!(hit.front_face);
+    assert_eq!(hit.material, 5);
+    assert!((hit.u - 0.75).abs() < 1e-5 && hit.v.abs() < 1e-5);
+    // from inside the hole, the inside of the tube
+    let hole = torus.hit(&Ray::new(Point3D::new(0.0, 0.0, -10.0), Vec3::new(1.0, 0.0, 0.0)), &ray_t).unwrap();
+    assert!((hole.t - 1.5).abs() < 1e-5);
+    assert!((hole.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-5);
+    assert!(hole.front_face);
+    assert!((hole.v - 0.5).abs() < 1e-5);
+    // down through the top of the tube, and down through the hole
+    let top = torus.hit(&Ray::new(Point3D::new(2.0, 10.0, -10.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).unwrap();
+    assert!((top.t - 9.5).abs() < 1e-5);
+    assert!((top.v - 0.25).abs() < 1e-5);
+    assert!(torus.hit(&Ray::new(Point3D::new(0.0, 10.0, -10.0), Vec3::new(0.0, -1.0, 0.0)), &ray_t).is_none());
+    // inside the tube, going round it
+    let inside = torus.hit(&Ray::new(Point3D::new(2.0, 0.0, -10.0), Vec3::new(0.0, 1.0, 0.0)), &ray_t).unwrap();
+    assert!((inside.t - 0.5).abs() < 1e-5);
+    assert!(!inside.front_face);
+
+    // with only the -z half of the ring, the ray passes the missing near side and hits the far one
+    let half = torus.with_sweep(Sweep::new(consts::PI).unwrap());
+    let far = half.hit(&Ray::new(Point3D::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+    assert!((far.t - 11.5).abs() < 1e-5);
+    assert!(far.front_face);
+}
+
+#[test]
+fn test_torus_radii() {
+    let center = Point3D::new(0.0, 0.0, 0.0);
+    for (major, minor) in [(1.0, 1.0), (0.5, 1.0), (1.0, 0.0), (1.0, -0.5), (Float::INFINITY, 1.0), (1.0, Float::NAN)] {
+        assert_eq!(Torus::new(center, major, minor, 0).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -2,37 +2,62 @@ use std::ops;
 
 use rand::{ random, Rng };
 
-#[derive(Clone, Copy, Debug)]
+use crate::float::Float;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Vec3 {
-    x: f32,
-    y: f32,
-    z: f32,
+    x: Float,
+    y: Float,
+    z: Float,
 }
 impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+    pub fn new(x: Float, y: Float, z: Float) -> Vec3 {
         Vec3 { x, y, z }
     }
-    pub fn x(&self) -> f32 {
+    // v in every component
+    pub const fn splat(v: Float) -> Vec3 {
+        Vec3 { x: v, y: v, z: v }
+    }
+    pub fn x(&self) -> Float {
         self.x
     }
 
-    pub fn y(&self) -> f32 {
+    pub fn y(&self) -> Float {
         self.y
     }
 
-    pub fn z(&self) -> f32 {
+    pub fn z(&self) -> Float {
         self.z
     }
-    pub fn length_squared(&self) -> f32 {
+    // x, y or z for axis 0, 1 or 2
+    pub fn axis(&self, axis: usize) -> Float {
+        return match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        };
+    }
+    // the smaller of each component
+    pub fn min(a: Vec3, b: Vec3) -> Vec3 {
+        return Vec3::new(Float::min(a.x, b.x), Float::min(a.y, b.y), Float::min(a.z, b.z));
+    }
+    pub fn max(a: Vec3, b: Vec3) -> Vec3 {
+        return Vec3::new(Float::max(a.x, b.x), Float::max(a.y, b.y), Float::max(a.z, b.z));
+    }
+    // the absolute value of each component
+    pub fn abs(&self) -> Vec3 {
+        return Vec3::new(Float::abs(self.x), Float::abs(self.y), Float::abs(self.z));
+    }
+    pub fn length_squared(&self) -> Float {
         return self.x * self.x + self.y * self.y + self.z * self.z;
     }
-    pub fn length(&self) -> f32 {
-        f32::sqrt(self.length_squared())
+    pub fn length(&self) -> Float {
+        Float::sqrt(self.length_squared())
     }
     pub fn random() -> Vec3 {
-        return Vec3::new(random::<f32>(), random::<f32>(), random::<f32>());
+        return Vec3::new(random::<Float>(), random::<Float>(), random::<Float>());
     }
-    pub fn random_in_range(min: f32, max: f32) -> Vec3 {
+    pub fn random_in_range(min: Float, max: Float) -> Vec3 {
         let mut rng = rand::thread_rng();
         return Vec3::new(rng.gen_range(min..max), rng.gen_range(min..max), rng.gen_range(min..max));
     }
@@ -53,16 +78,16 @@ impl Vec3 {
     }
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
-        return f32::abs(self.x) < s && f32::abs(self.y) < s && f32::abs(self.z) < s;
+        return Float::abs(self.x) < s && Float::abs(self.y) < s && Float::abs(self.z) < s;
     }
     pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
         return v - 2.0 * v.dot(n) * n;
     }
     // snell's law
-    pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f32) -> Vec3 {
-        let cos_theta = f32::min(-uv.dot(n), 1.0);
+    pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: Float) -> Vec3 {
+        let cos_theta = Float::min(-uv.dot(n), 1.0);
         let r_out_perp = etai_over_etat * (uv + cos_theta * n);
-        let r_out_parallel = -f32::sqrt(f32::abs(1.0 - r_out_perp.length_squared())) * n;
+        let r_out_parallel = -Float::sqrt(Float::abs(1.0 - r_out_perp.length_squared())) * n;
         return r_out_perp + r_out_parallel;
     }
     pub fn random_in_unit_disk() -> Vec3 {
@@ -74,16 +99,22 @@ impl Vec3 {
             }
         }
     }
-    pub fn print(&self) {
-        print!("{} {} {}", self.x, self.y, self.x)
-    }
 }
 #[test]
 fn test_new() {
     let a = Vec3::new(1.0, 2.0, 3.0);
     assert_eq!(a.x(), 1.0);
-    assert_eq!(a.x(), 2.0);
-    assert_eq!(a.x(), 3.0);
+    assert_eq!(a.y(), 2.0);
+    assert_eq!(a.z(), 3.0);
+}
+#[test]
+fn test_axis_min_max() {
+    let a = Vec3::new(1.0, 5.0, -3.0);
+    let b = Vec3::new(2.0, -1.0, -4.0);
+    assert_eq!((a.axis(0), a.axis(1), a.axis(2)), (1.0, 5.0, -3.0));
+    assert_eq!(Vec3::min(a, b), Vec3::new(1.0, -1.0, -4.0));
+    assert_eq!(Vec3::max(a, b), Vec3::new(2.0, 5.0, -3.0));
+    assert_eq!(b.abs(), Vec3::new(2.0, 1.0, 4.0));
 }
 #[test]
 fn test_length_squared() {
@@ -95,7 +126,7 @@ fn test_length_squared() {
 fn test_length() {
     let a = Vec3::new(1.0, 2.0, 3.0);
     let b = a.length();
-    assert_eq!(b, f32::sqrt(14.0));
+    assert_eq!(b, Float::sqrt(14.0));
 }
 
 impl ops::Neg for Vec3 {
@@ -182,10 +213,10 @@ fn test_vec_mul() {
     assert_eq!(c.z(), 2.0);
 }
 
-impl ops::Mul<f32> for Vec3 {
+impl ops::Mul<Float> for Vec3 {
     type Output = Vec3;
 
-    fn mul(self, rhs: f32) -> Self::Output {
+    fn mul(self, rhs: Float) -> Self::Output {
         return Vec3::new(self.x() * rhs, self.y() * rhs, self.z() * rhs);
     }
 }
@@ -199,7 +230,7 @@ fn test_f32_rhs_mul() {
     assert_eq!(c.z(), 4.0);
 }
 
-impl ops::Mul<Vec3> for f32 {
+impl ops::Mul<Vec3> for Float {
     type Output = Vec3;
 
     fn mul(self, rhs: Vec3) -> Self::Output {
@@ -217,8 +248,8 @@ fn test_f32_lhs_mul() {
     assert_eq!(c.z(), 4.0);
 }
 
-impl ops::MulAssign<f32> for Vec3 {
-    fn mul_assign(&mut self, rhs: f32) {
+impl ops::MulAssign<Float> for Vec3 {
+    fn mul_assign(&mut self, rhs: Float) {
         self.x *= rhs;
         self.y *= rhs;
         self.z *= rhs;
@@ -234,10 +265,10 @@ fn test_f32_mul_assign() {
     assert_eq!(a.z(), 4.0);
 }
 
-impl ops::Div<f32> for Vec3 {
+impl ops::Div<Float> for Vec3 {
     type Output = Vec3;
 
-    fn div(self, rhs: f32) -> Self::Output {
+    fn div(self, rhs: Float) -> Self::Output {
         return self * (1.0 / rhs);
     }
 }
@@ -251,8 +282,8 @@ fn test_f32_div() {
     assert_eq!(c.z(), 0.0);
 }
 
-impl ops::DivAssign<f32> for Vec3 {
-    fn div_assign(&mut self, rhs: f32) {
+impl ops::DivAssign<Float> for Vec3 {
+    fn div_assign(&mut self, rhs: Float) {
         *self *= 1.0 / rhs;
     }
 }
@@ -267,10 +298,10 @@ fn test_f32_div_assign() {
 }
 
 pub trait Dot {
-    fn dot(self, rhs: Vec3) -> f32;
+    fn dot(self, rhs: Vec3) -> Float;
 }
 impl Dot for Vec3 {
-    fn dot(self, rhs: Vec3) -> f32 {
+    fn dot(self, rhs: Vec3) -> Float {
         return self.x * rhs.x() + self.y * rhs.y() + self.z * rhs.z();
     }
 }
@@ -316,7 +347,7 @@ impl UnitVec for Vec3 {
 fn test_unit_vec() {
     let a = Vec3::new(1.0, 0.0, 1.0);
     let b = a.unit_vec();
-    assert_eq!(b.x(), 1.0 / f32::sqrt(2.0));
+    assert_eq!(b.x(), 1.0 / Float::sqrt(2.0));
     assert_eq!(b.y(), 0.0);
-    assert_eq!(b.z(), 1.0 / f32::sqrt(2.0));
+    assert_eq!(b.z(), 1.0 / Float::sqrt(2.0));
 }
@@ -0,0 +1,75 @@
+use crate::{
+    hittable::{ Hittable, HitRecord },
+    hittable_list::HittableList,
+    interval::Interval,
+    material::Material,
+    primitive::Primitive,
+    ray::Ray,
+    aabb::Aabb,
+};
+
+// Everything a camera renders: the objects, and the arena of materials their hit records point into
+// by index. The only shared parts are behind Arcs (instanced objects and custom materials and
+// textures), so it is Send + Sync and can be handed to other threads as it is. It is not serialized
+// itself, a SceneDescription is the form a scene is written and read in and builds into a World.
+#[derive(Clone, Debug, Default)]
+pub struct World {
+    pub objects: HittableList,
+    pub materials: Vec<Material>,
+}
+
+impl World {
+    pub fn new() -> World {
+        return World::default();
+    }
+    // returns the index objects use to refer to the material
+    pub fn add_material(&mut self, material: Material) -> usize {
+        self.materials.push(material);
+        return self.materials.len() - 1;
+    }
+    pub fn add(&mut self, object: impl Into<Primitive>) {
+        self.objects.add(object);
+    }
+    // None when no material was added at the index
+    pub fn material(&self, index: usize) -> Option<&Material> {
+        return self.materials.get(index);
+    }
+}
+
+impl Hittable for World {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        return self.objects.hit(ray, ray_t);
+    }
+    fn bounding_box(&self) -> Aabb {
+        return self.objects.bounding_box();
+    }
+    fn object_count(&self) -> usize {
+        return self.objects.object_count();
+    }
+}
+
+#[cfg(test)]
+use std::thread;
+#[cfg(test)]
+use crate::{ scene::SceneDescription, point3d::Point3D, vec3::Vec3, float::Float };
+
+#[test]
+fn test_world_is_shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<World>();
+
+    let world = SceneDescription::random_spheres(0).build();
+    let ray = Ray::new(Point3D::new(13.0, 2.0, 3.0), Vec3::new(-13.0, -1.0, -3.0));
+    let hit = world.hit(&ray, &Interval::new(0.001, Float::INFINITY)).unwrap();
+    // every thread sees the same world without copying it
+    thread::scope(|scope| {
+        let threads: Vec<_> = (0..4)
+            .map(|_| scope.spawn(|| world.hit(&ray, &Interval::new(0.001, Float::INFINITY)).unwrap()))
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), hit);
+        }
+    });
+    assert!(world.material(hit.material).is_some());
+    assert!(world.material(world.materials.len()).is_none());
+}
@@ -0,0 +1,69 @@
+/* Builds a small scene through the c interface, renders it and checks the result. Compiled and run
+ * by tests/c_api.rs, or by hand with
+ *   cc tests/c/render_test.c -Iinclude -Ltarget/debug -lraytracer -o render_test */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "raytracer.h"
+
+#define CHECK(call)                                                                      \
+    do {                                                                                 \
+        RtStatus status = (call);                                                        \
+        if (status != RT_STATUS_OK) {                                                    \
+            const char *message = rt_scene_last_error(scene);                            \
+            fprintf(stderr, "%s failed with %d: %s\n", #call, (int)status,               \
+                    message ? message : "no message");                                   \
+            return 1;                                                                    \
+        }                                                                                \
+    } while (0)
+
+int main(void) {
+    RtScene *scene = rt_scene_new();
+    if (!scene) {
+        fprintf(stderr, "rt_scene_new returned null\n");
+        return 1;
+    }
+
+    uint32_t ground, glass, gold;
+    CHECK(rt_scene_add_lambertian(scene, 0.5f, 0.5f, 0.5f, &ground));
+    CHECK(rt_scene_add_dielectric(scene, 1.5f, &glass));
+    CHECK(rt_scene_add_metal(scene, 0.8f, 0.6f, 0.2f, 0.1f, &gold));
+    CHECK(rt_scene_add_sphere(scene, (RtVec3){0.0f, -1000.0f, 0.0f}, 1000.0f, ground));
+    CHECK(rt_scene_add_sphere(scene, (RtVec3){0.0f, 1.0f, 0.0f}, 1.0f, glass));
+    CHECK(rt_scene_add_sphere(scene, (RtVec3){4.0f, 1.0f, 0.0f}, 1.0f, gold));
+
+    /* errors are reported rather than crashing */
+    if (rt_scene_add_sphere(scene, (RtVec3){0.0f, 0.0f, 0.0f}, 1.0f, 42) != RT_STATUS_INVALID_ARGUMENT ||
+        rt_scene_last_error(scene) == NULL) {
+        fprintf(stderr, "a sphere with an unknown material was accepted\n");
+        return 1;
+    }
+
+    CHECK(rt_scene_set_camera(scene, 16.0f / 9.0f, 32, 4, 8, 20.0f, (RtVec3){13.0f, 2.0f, 3.0f},
+                              (RtVec3){0.0f, 0.0f, 0.0f}, (RtVec3){0.0f, 1.0f, 0.0f}, 0.6f, 10.0f));
+    CHECK(rt_scene_set_seed(scene, 7));
+    int32_t width, height;
+    CHECK(rt_scene_image_size(scene, &width, &height));
+
+    size_t len = (size_t)width * (size_t)height * 3;
+    float *pixels = malloc(len * sizeof(float));
+    if (rt_scene_render(scene, pixels, len - 1) != RT_STATUS_BUFFER_TOO_SMALL) {
+        fprintf(stderr, "a buffer that is too small was accepted\n");
+        return 1;
+    }
+    CHECK(rt_scene_render(scene, pixels, len));
+
+    double sum = 0.0;
+    for (size_t i = 0; i < len; i++) {
+        if (!(pixels[i] >= 0.0f)) {
+            fprintf(stderr, "pixel value %zu is %f\n", i, pixels[i]);
+            return 1;
+        }
+        sum += pixels[i];
+    }
+    printf("rendered %dx%d, average %.3f\n", width, height, sum / (double)len);
+
+    free(pixels);
+    rt_scene_free(scene);
+    return sum > 0.0 ? 0 : 1;
+}
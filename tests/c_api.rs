@@ -0,0 +1,39 @@
+#[cfg(unix)]
+use std::{ env, path::PathBuf, process::Command };
+
+// Compiles tests/c/render_test.c against the cdylib and the generated header and runs it, to check
+// the c interface works from c and not just from rust. Skipped when there is no c compiler. Only
+// on unix, where cc, -l and -rpath find the library the same way everywhere.
+#[cfg(unix)]
+#[test]
+fn test_c_program() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // the cdylib is built next to this test's executable
+    let deps_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let compiler = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let program = env::temp_dir().join(format!("raytracer_render_test_{}", std::process::id()));
+
+    let compiled = Command::new(&compiler)
+        .arg(manifest_dir.join("tests/c/render_test.c"))
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg(format!("-I{}", manifest_dir.join("include").display()))
+        .arg(format!("-L{}", deps_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", deps_dir.display()))
+        .arg("-lraytracer")
+        .arg("-o")
+        .arg(&program)
+        .status();
+    let Ok(compiled) = compiled else {
+        println!("skipping, {} is not available", compiler);
+        return;
+    };
+    assert!(compiled.success(), "compiling the c test program failed");
+
+    let output = Command::new(&program).output().unwrap();
+    let _ = std::fs::remove_file(&program);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.starts_with("rendered 32x18"), "{}", stdout);
+}